@@ -0,0 +1,8 @@
+// Node.js addons need linker flags on macOS, other builds need nothing.
+#[cfg(feature = "node")]
+extern crate napi_build;
+
+fn main() {
+    #[cfg(feature = "node")]
+    napi_build::setup();
+}
@@ -0,0 +1,47 @@
+/* C interface of yadict, built with
+ *
+ *     cargo rustc --release --features ffi --crate-type cdylib
+ *
+ * Strings are UTF-8 and NUL-terminated. A handle may be used from several
+ * threads at once. */
+
+#ifndef YADICT_H
+#define YADICT_H
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+#define YADICT_OK 0
+/* Null pointer, a string which isn't UTF-8 or an unknown direction. */
+#define YADICT_INVALID_ARGUMENT 1
+#define YADICT_KEY_INVALID 2
+#define YADICT_KEY_BLOCKED 3
+#define YADICT_DAILY_LIMIT_EXCEEDED 4
+#define YADICT_TEXT_TOO_LONG 5
+#define YADICT_LANG_NOT_SUPPORTED 6
+/* Network failures, timeouts and unexpected answers. */
+#define YADICT_REQUEST_FAILED 7
+#define YADICT_PANIC 8
+
+typedef struct yadict yadict;
+
+/* Returns a handle for the API key, NULL if the key is NULL or empty. */
+yadict *yadict_new(const char *token);
+
+/* Looks up text in direction like "en-ru". On success *out is a JSON array
+ * of definitions, otherwise a JSON object with "error" and "message" fields.
+ * *out is NULL for invalid arguments. Free *out with yadict_string_free. */
+int yadict_lookup_json(const yadict *api, const char *lang, const char *text, char **out);
+
+/* Frees a string returned by the library, NULL is ignored. */
+void yadict_string_free(char *s);
+
+/* Frees a handle of yadict_new, NULL is ignored. */
+void yadict_free(yadict *api);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
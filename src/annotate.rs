@@ -0,0 +1,126 @@
+// Annotation of running text for reading assistants: every word of a
+// sentence gets definitions of its lowercase form.
+
+use super::{Api, Def, LangPair, RequestError};
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Token {
+    pub text: String,
+    // Byte range of the token in the annotated text.
+    pub start: usize,
+    pub end: usize,
+    // Empty for unknown words, numbers and failed lookups.
+    pub defs: Vec<Def>,
+}
+
+#[derive(Debug)]
+pub struct Annotation {
+    // Tokens in order of the text, repeated words included.
+    pub tokens: Vec<Token>,
+    // Lowercase words which failed to be looked up.
+    pub errors: Vec<(String, RequestError)>,
+}
+
+// Splits text into words with their byte offsets. Words are runs of
+// letters and digits, apostrophes and hyphens inside them are kept, so
+// "don't" and "well-known" are single words.
+pub fn tokenize(text: &str) -> Vec<(usize, &str)> {
+    let mut tokens = Vec::new();
+    let mut start = None;
+    let mut chars = text.char_indices().peekable();
+    while let Some((idx, c)) = chars.next() {
+        if c.is_alphanumeric() {
+            if start.is_none() {
+                start = Some(idx);
+            }
+            continue;
+        }
+        let joins = match (start, chars.peek()) {
+            (Some(_), Some(&(_, next))) => is_joiner(c) && next.is_alphanumeric(),
+            _ => false,
+        };
+        if !joins {
+            if let Some(begin) = start.take() {
+                tokens.push((begin, &text[begin..idx]));
+            }
+        }
+    }
+    if let Some(begin) = start {
+        tokens.push((begin, &text[begin..]));
+    }
+    tokens
+}
+
+fn is_joiner(c: char) -> bool {
+    c == '\'' || c == '\u{2019}' || c == '-'
+}
+
+impl Api {
+    // Looks up every distinct word of `text` once, with the cache and
+    // `with_concurrency` parallel requests like `lookup_batch`.
+    pub fn annotate<L: Into<LangPair>>(&self, lang: L, text: &str) -> Annotation {
+        let tokens = tokenize(text);
+        let words: Vec<String> = tokens.iter()
+            .filter(|&&(_, token)| token.chars().any(char::is_alphabetic))
+            .map(|&(_, token)| token.to_lowercase())
+            .collect();
+        let words: Vec<&str> = words.iter().map(String::as_str).collect();
+        let mut found = Vec::new();
+        let mut errors = Vec::new();
+        for (word, result) in self.lookup_batch(lang, &words) {
+            match result {
+                Ok(defs) => found.push((word, defs)),
+                Err(e) => errors.push((word, e)),
+            }
+        }
+        let tokens = tokens.into_iter().map(|(start, token)| {
+            let word = token.to_lowercase();
+            Token {
+                text: token.to_owned(),
+                start: start,
+                end: start + token.len(),
+                defs: found.iter()
+                    .find(|&(w, _)| *w == word)
+                    .map(|(_, defs)| defs.clone())
+                    .unwrap_or_default(),
+            }
+        }).collect();
+        Annotation {
+            tokens: tokens,
+            errors: errors,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::tokenize;
+    use super::super::Api;
+    use testing::MockTransport;
+
+    #[test]
+    fn check_tokenize() {
+        let text = "Don't rust -- well-known, 42 'quoted' кот!";
+        let tokens: Vec<&str> = tokenize(text).into_iter().map(|(_, token)| token).collect();
+        assert_eq!(tokens, vec!["Don't", "rust", "well-known", "42", "quoted", "кот"]);
+        assert_eq!(tokenize(text)[5].0, text.find("кот").unwrap());
+        assert!(tokenize(" - ").is_empty());
+    }
+
+    #[test]
+    fn check_annotate() {
+        let transport = MockTransport::with_fixtures();
+        let api = Api::from_token("token").unwrap().with_transport(transport.clone());
+        let annotation = api.annotate("en-ru", "Rust never sleeps, rust 42.");
+        let texts: Vec<&str> = annotation.tokens.iter().map(|token| token.text.as_str()).collect();
+        assert_eq!(texts, vec!["Rust", "never", "sleeps", "rust", "42"]);
+        assert_eq!(annotation.tokens[0].defs.len(), 3);
+        assert_eq!(annotation.tokens[0].defs, annotation.tokens[3].defs);
+        assert!(annotation.tokens[1].defs.is_empty());
+        assert_eq!((annotation.tokens[3].start, annotation.tokens[3].end), (19, 23));
+        assert!(annotation.errors.is_empty());
+        // One request per distinct word, none for numbers
+        assert_eq!(transport.requests().len(), 3);
+    }
+}
@@ -0,0 +1,292 @@
+// Non-blocking counterpart of `Api` built on top of reqwest futures.
+// Responses are decoded with the same routines as the blocking client.
+
+use std::env;
+use std::fmt;
+use std::future::Future;
+use std::sync::Arc;
+use std::time::Instant;
+use futures::future::{FutureExt, TryFutureExt};
+use futures::stream::{self, Stream, StreamExt};
+use reqwest::Client;
+use serde_json::Value;
+use super::{API_URL, DEFAULT_POST_THRESHOLD, ApiError, Def, LangPair, Langs, LookupFlags, LookupOptions, LookupResponse, RequestError};
+use super::{api_url, header_names, lookup_params, response_to_json, json_to_langs};
+use batch::DEFAULT_CONCURRENCY;
+use parse::{self, ParseMode};
+use metrics::{Metrics, RequestEvent};
+
+#[cfg(feature = "tracing")]
+fn instrumented<F: Future>(future: F, method: &str) -> impl Future<Output = F::Output> {
+    ::tracing::Instrument::instrument(future, ::tracing::debug_span!("yadict_request", method = method))
+}
+
+#[cfg(not(feature = "tracing"))]
+fn instrumented<F: Future>(future: F, _method: &str) -> F {
+    future
+}
+
+// Start of a request for its latency. There is no clock in std on wasm32,
+// `Instant::now` panics there, so latency is reported as zero.
+#[cfg(not(target_arch = "wasm32"))]
+fn started() -> Option<Instant> {
+    Some(Instant::now())
+}
+
+#[cfg(target_arch = "wasm32")]
+fn started() -> Option<Instant> {
+    None
+}
+
+pub struct AsyncApi {
+    token: String,
+    base_url: String,
+    client: Client,
+    metrics: Option<Arc<dyn Metrics>>,
+    parse_mode: ParseMode,
+    headers: Vec<(String, String)>,
+    post_threshold: usize,
+}
+
+impl fmt::Debug for AsyncApi {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("AsyncApi")
+            .field("token", &"***")
+            .field("base_url", &self.base_url)
+            .field("parse_mode", &self.parse_mode)
+            .field("headers", &header_names(&self.headers))
+            .field("post_threshold", &self.post_threshold)
+            .finish()
+    }
+}
+
+// reqwest prefers native TLS when both backends are built in, while the
+// blocking transport goes with rustls, so both clients are kept on it.
+#[cfg(all(feature = "native-tls", feature = "rustls", not(target_arch = "wasm32")))]
+fn new_client() -> Client {
+    Client::builder().use_rustls_tls().build().unwrap_or_else(|_| Client::new())
+}
+
+#[cfg(not(all(feature = "native-tls", feature = "rustls", not(target_arch = "wasm32"))))]
+fn new_client() -> Client {
+    Client::new()
+}
+
+impl AsyncApi {
+    pub fn from_token(token: &str) -> Result<AsyncApi, ApiError> {
+        Ok(AsyncApi {
+            token: token.to_owned(),
+            base_url: API_URL.to_owned(),
+            client: new_client(),
+            metrics: None,
+            parse_mode: ParseMode::Strict,
+            headers: Vec::new(),
+            post_threshold: DEFAULT_POST_THRESHOLD,
+        })
+    }
+
+    pub fn with_base_url(mut self, base_url: &str) -> AsyncApi {
+        self.base_url = base_url.trim_end_matches('/').to_owned();
+        self
+    }
+
+    // Same as `Api::with_parse_mode`.
+    pub fn with_parse_mode(mut self, mode: ParseMode) -> AsyncApi {
+        self.parse_mode = mode;
+        self
+    }
+
+    // Reports every request to `metrics` when its response is received.
+    pub fn with_metrics<M: Metrics + 'static>(mut self, metrics: M) -> AsyncApi {
+        self.metrics = Some(Arc::new(metrics));
+        self
+    }
+
+    // Same as `Api::with_header`.
+    pub fn with_header(mut self, name: &str, value: &str) -> AsyncApi {
+        self.headers.retain(|(other, _)| !other.eq_ignore_ascii_case(name));
+        self.headers.push((name.to_owned(), value.to_owned()));
+        self
+    }
+
+    pub fn with_user_agent(self, user_agent: &str) -> AsyncApi {
+        self.with_header("User-Agent", user_agent)
+    }
+
+    // Same as `Api::with_post_threshold`.
+    pub fn with_post_threshold(mut self, threshold: usize) -> AsyncApi {
+        self.post_threshold = threshold;
+        self
+    }
+
+    pub fn from_env(var: &str) -> Result<AsyncApi, ApiError> {
+        let token = match env::var(var) {
+            Ok(tok) => tok,
+            Err(e) => return Err(ApiError::InvalidEnvironmentVar(e)),
+        };
+        Self::from_token(&token)
+    }
+
+    // Takes ready URL, so the future doesn't borrow any of the arguments.
+    fn fetch_json(&self, method: &'static str, url: String) -> impl Future<Output = Result<Value, RequestError>> {
+        let metrics = self.metrics.clone();
+        let started = started();
+        let mut request = match url.find('?') {
+            Some(pos) if url.len() > self.post_threshold => {
+                self.client.post(&url[..pos])
+                    .header("Content-Type", "application/x-www-form-urlencoded")
+                    .body(url[pos + 1..].to_owned())
+            },
+            _ => self.client.get(&url),
+        };
+        for (name, value) in &self.headers {
+            request = request.header(name.as_str(), value.as_str());
+        }
+        let future = request.send()
+            .and_then(|response| {
+                let status = response.status().as_u16();
+                response.text().map_ok(move |body| (status, body))
+            })
+            .map(move |response| {
+                let status = response.as_ref().ok().map(|&(status, _)| status);
+                let result = match response {
+                    Ok((status, body)) => response_to_json(status, &body),
+                    Err(e) => Err(RequestError::from(e)),
+                };
+                if let Some(metrics) = metrics {
+                    metrics.record(&RequestEvent {
+                        method: method,
+                        latency: started.map(|started| started.elapsed()).unwrap_or_default(),
+                        status: status,
+                        error: result.as_ref().err().map(RequestError::kind),
+                    });
+                }
+                result
+            });
+        instrumented(future, method)
+    }
+
+    pub fn get_langs(&self) -> impl Future<Output = Result<Vec<String>, RequestError>> {
+        self.fetch_json("getLangs", api_url(&self.base_url, "getLangs", &[("key", &self.token)]))
+            .map(|result| result.and_then(json_to_langs))
+    }
+
+    pub fn lang_pairs(&self) -> impl Future<Output = Result<Langs, RequestError>> {
+        self.get_langs().map(|result| result.map(|langs| Langs::from_codes(&langs)))
+    }
+
+    pub fn lookup<L: Into<LangPair>>(&self, lang: L, text: &str)
+        -> impl Future<Output = Result<Value, RequestError>> {
+        self.lookup_with_flags(lang, text, LookupFlags::empty())
+    }
+
+    pub fn lookup_with_flags<L: Into<LangPair>>(&self, lang: L, text: &str, flags: LookupFlags)
+        -> impl Future<Output = Result<Value, RequestError>> {
+        let options = LookupOptions {
+            flags: flags,
+            ui: None,
+        };
+        self.lookup_with_options(lang, text, &options)
+    }
+
+    pub fn lookup_with_options<L: Into<LangPair>>(&self, lang: L, text: &str, options: &LookupOptions)
+        -> impl Future<Output = Result<Value, RequestError>> {
+        let lang = lang.into();
+        let params = lookup_params(&self.token, lang.as_str(), text, options);
+        self.fetch_json("lookup", api_url(&self.base_url, "lookup", &params)).map(|result| result.and_then(|json| {
+            if json.is_object() {
+                Ok(json)
+            } else {
+                Err(RequestError::InvalidDataFormat)
+            }
+        }))
+    }
+
+    pub fn lookup_def<L: Into<LangPair>>(&self, lang: L, text: &str)
+        -> impl Future<Output = Result<Vec<Def>, RequestError>> {
+        self.lookup_def_with_flags(lang, text, LookupFlags::empty())
+    }
+
+    pub fn lookup_def_with_flags<L: Into<LangPair>>(&self, lang: L, text: &str, flags: LookupFlags)
+        -> impl Future<Output = Result<Vec<Def>, RequestError>> {
+        let mode = self.parse_mode;
+        self.lookup_with_flags(lang, text, flags)
+            .map(move |result| result.and_then(|json| parse::parse_defs(json, mode)).map(LookupResponse::into_defs))
+    }
+
+    pub fn lookup_def_with_options<L: Into<LangPair>>(&self, lang: L, text: &str, options: &LookupOptions)
+        -> impl Future<Output = Result<Vec<Def>, RequestError>> {
+        let mode = self.parse_mode;
+        self.lookup_with_options(lang, text, options)
+            .map(move |result| result.and_then(|json| parse::parse_defs(json, mode)).map(LookupResponse::into_defs))
+    }
+
+    // Stream counterpart of `Api::lookup_iter`: results in order of
+    // completion with a few requests at a time, words are taken from
+    // `words` only as results are consumed.
+    pub fn lookup_stream<'a, L, I>(&'a self, lang: L, words: I)
+        -> impl Stream<Item = (String, Result<Vec<Def>, RequestError>)> + 'a
+        where L: Into<LangPair>, I: IntoIterator, I::Item: Into<String>, I::IntoIter: 'a {
+        let lang = lang.into();
+        stream::iter(words)
+            .map(move |word| {
+                let word: String = word.into();
+                self.lookup_def(lang.clone(), &word).map(move |result| (word, result))
+            })
+            .buffer_unordered(DEFAULT_CONCURRENCY)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use std::future::Future;
+    use futures::StreamExt;
+    use tokio::runtime::Builder;
+    use super::AsyncApi;
+    use testing::MockTransport;
+
+    fn block_on<F: Future>(future: F) -> F::Output {
+        Builder::new_current_thread().enable_all().build().unwrap().block_on(future)
+    }
+
+    fn api(transport: &MockTransport) -> AsyncApi {
+        AsyncApi::from_token("token").unwrap().with_base_url(&transport.serve().unwrap())
+    }
+
+    #[test]
+    fn check_async_get_langs() {
+        let transport = MockTransport::with_fixtures();
+        let langs = block_on(api(&transport).get_langs()).unwrap();
+        assert!(langs.contains(&"en-ru".to_string()));
+        assert_eq!(transport.requests(), vec!["/getLangs?key=token"]);
+    }
+
+    #[test]
+    fn check_async_lookup_def() {
+        let transport = MockTransport::with_fixtures();
+        let api = api(&transport);
+        let defs = block_on(api.lookup_def("en-ru", "rust")).unwrap();
+        assert_eq!(defs.len(), 3);
+        for def in defs {
+            assert_eq!(def.word.text, "rust");
+            assert!(!def.trans.is_empty());
+        }
+        assert!(block_on(api.lookup_def("en-ru", "qwzx")).unwrap().is_empty());
+        // Long requests go as POST
+        let api = api.with_post_threshold(0);
+        assert_eq!(block_on(api.lookup_def("en-ru", "rust")).unwrap().len(), 3);
+        assert_eq!(transport.forms().len(), 1);
+    }
+
+    #[test]
+    fn check_async_lookup_stream() {
+        // Nothing listens there, so every word fails fast
+        let api = AsyncApi::from_token("token").unwrap().with_base_url("http://127.0.0.1:1");
+        let words = (0..10).map(|idx| format!("word{}", idx));
+        let results: Vec<_> = block_on(api.lookup_stream("en-ru", words).collect());
+        assert_eq!(results.len(), 10);
+        assert!(results.iter().all(|(_, result)| result.is_err()));
+        assert!(results.iter().any(|(word, _)| word == "word9"));
+    }
+}
@@ -0,0 +1,751 @@
+use std::cell::Cell;
+use std::collections::{HashMap, HashSet};
+use std::sync::{mpsc, Arc, Mutex};
+use std::fmt;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::thread;
+use std::time::Instant;
+use cache::CacheKey;
+use super::{Api, CancellationToken, Def, ErrorContext, LangPair, Limits, LookupOptions, RequestError, with_cache};
+use unwind;
+
+pub const DEFAULT_CONCURRENCY: usize = 4;
+
+pub type BatchResult = Vec<(String, Result<Vec<Def>, RequestError>)>;
+
+// Which words of a batch are looked up once, see `BatchOptions`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[derive(Default)]
+pub enum DedupMode {
+    // Only equal words.
+    #[default]
+    Exact,
+    // "Apple" and "apple".
+    CaseInsensitive,
+    // "Apple pie" and " apple  pie", runs of whitespace count as
+    // a space and the ends are trimmed.
+    CaseAndWhitespaceInsensitive,
+}
+
+
+impl DedupMode {
+    fn key(&self, word: &str) -> String {
+        match *self {
+            DedupMode::Exact => word.to_owned(),
+            DedupMode::CaseInsensitive => word.to_lowercase(),
+            DedupMode::CaseAndWhitespaceInsensitive => {
+                let words: Vec<&str> = word.split_whitespace().collect();
+                words.join(" ").to_lowercase()
+            },
+        }
+    }
+}
+
+// Options of `Api::lookup_many`.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct BatchOptions {
+    // Words which are the same for the mode are looked up once, as the
+    // first of them is spelled. `BatchReport::get` finds the result by
+    // any spelling.
+    pub dedup_mode: DedupMode,
+    // A rejected key (`KeyInvalid` or `KeyBlocked`) stops the batch by
+    // default: words which aren't looked up yet fail with
+    // `RequestError::Skipped(SkipReason::AuthFailure)`. Set it to go on,
+    // e.g. when keys of `Api::from_tokens` may get unblocked.
+    pub continue_on_auth_failure: bool,
+}
+
+// Why a word of a batch wasn't looked up, see `RequestError::Skipped`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum SkipReason {
+    // The service rejected the key earlier in the batch.
+    AuthFailure,
+}
+
+impl fmt::Display for SkipReason {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            SkipReason::AuthFailure => f.write_str("API key was rejected earlier in the batch"),
+        }
+    }
+}
+
+// Result of `Api::lookup_many`.
+#[derive(Debug)]
+pub struct BatchReport {
+    // Words looked up in order of the first occurrence, as `lookup_batch`
+    // gives them.
+    pub results: BatchResult,
+    // Why the batch stopped early, `None` if every word was looked up.
+    pub error: Option<RequestError>,
+    // Distinct input words in order with indexes of their results.
+    inputs: Vec<(String, usize)>,
+    index: HashMap<String, usize>,
+}
+
+impl BatchReport {
+    // Result of an input word, the same for words deduplicated together.
+    pub fn get(&self, input: &str) -> Option<&Result<Vec<Def>, RequestError>> {
+        self.index.get(input).map(|&idx| &self.results[idx].1)
+    }
+
+    // Every distinct input word with its result, in order of input.
+    pub fn entries(&self) -> Vec<(&str, &Result<Vec<Def>, RequestError>)> {
+        self.inputs.iter().map(|&(ref word, idx)| (word.as_str(), &self.results[idx].1)).collect()
+    }
+}
+
+// Results of `Api::lookup_iter` in order of completion.
+pub struct LookupIter {
+    results: mpsc::IntoIter<(String, Result<Vec<Def>, RequestError>)>,
+}
+
+impl Iterator for LookupIter {
+    type Item = (String, Result<Vec<Def>, RequestError>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.results.next()
+    }
+}
+
+impl Api {
+    // Number of threads of `lookup_batch`, `lookup_multi` and `annotate`,
+    // `DEFAULT_CONCURRENCY` by default. They are scoped to the call and
+    // share the rate limit, the budget and the cache of the `Api`.
+    pub fn with_concurrency(mut self, concurrency: usize) -> Api {
+        self.concurrency = if concurrency == 0 { 1 } else { concurrency };
+        self
+    }
+
+    // Looks up every distinct word of `words`. Results go in order of the
+    // first occurrence of every word, errors come with `ErrorContext`.
+    // A rejected key stops the batch, see `BatchOptions`.
+    pub fn lookup_batch<L: Into<LangPair>>(&self, lang: L, words: &[&str]) -> BatchResult {
+        self.batch(&lang.into(), words, None, &BatchOptions::default()).results
+    }
+
+    // Same as `lookup_batch` with `options` and the reason the batch
+    // stopped early, if it did.
+    pub fn lookup_many<L: Into<LangPair>>(&self, lang: L, words: &[&str], options: &BatchOptions) -> BatchReport {
+        self.batch(&lang.into(), words, None, options)
+    }
+
+    // Same as `lookup_batch`, but once `cancel` is cancelled words which
+    // aren't looked up yet fail with `RequestError::Cancelled`.
+    pub fn lookup_batch_cancellable<L: Into<LangPair>>(&self, lang: L, words: &[&str], cancel: &CancellationToken)
+        -> BatchResult {
+        self.batch(&lang.into(), words, Some(cancel), &BatchOptions::default()).results
+    }
+
+    // Looks up words as the iterator asks for them with `with_concurrency`
+    // threads, so memory doesn't grow with the number of words: words are
+    // taken from `words` only as results are consumed. Repeated words are
+    // looked up and given once, as in `lookup_batch`, so the set of words
+    // seen so far is kept. Dropping the iterator stops the lookups.
+    pub fn lookup_iter<L, I>(&self, lang: L, words: I) -> LookupIter
+        where L: Into<LangPair>, I: IntoIterator, I::Item: Into<String>, I::IntoIter: Send + 'static {
+        self.lookup_iter_with(lang, words, &BatchOptions::default())
+    }
+
+    // Same as `lookup_iter` with `options`: a rejected key stops it unless
+    // `continue_on_auth_failure` is set, and words are deduplicated by
+    // `dedup_mode`.
+    pub fn lookup_iter_with<L, I>(&self, lang: L, words: I, options: &BatchOptions) -> LookupIter
+        where L: Into<LangPair>, I: IntoIterator, I::Item: Into<String>, I::IntoIter: Send + 'static {
+        let lang = lang.into();
+        let words = Arc::new(Mutex::new(words.into_iter()));
+        let seen: Arc<Mutex<HashSet<String>>> = Arc::new(Mutex::new(HashSet::new()));
+        let stop = Arc::new(AuthStop::new(options));
+        let dedup_mode = options.dedup_mode;
+        let (sender, receiver) = mpsc::sync_channel(self.concurrency);
+        for _ in 0..self.concurrency {
+            let api = self.clone();
+            let lang = lang.clone();
+            let words = words.clone();
+            let seen = seen.clone();
+            let stop = stop.clone();
+            let sender = sender.clone();
+            thread::spawn(move || {
+                loop {
+                    let word: String = {
+                        let mut words = unwind::lock(&words);
+                        let next = words.by_ref()
+                            .map(Into::into)
+                            .find(|word: &String| unwind::lock(&seen).insert(dedup_mode.key(word)));
+                        match next {
+                            Some(word) => word,
+                            None => break,
+                        }
+                    };
+                    let result = stop.lookup(&api, &lang, &word, None);
+                    if sender.send((word, result)).is_err() {
+                        break;
+                    }
+                }
+            });
+        }
+        LookupIter {
+            results: receiver.into_iter(),
+        }
+    }
+
+    // Counts what `lookup_batch` of `words` would cost without sending
+    // anything: distinct words which aren't fresh in the cache take
+    // a request each. See `BatchPlan::fits` for the comparison with limits.
+    pub fn plan_batch<L: Into<LangPair>>(&self, lang: L, words: &[&str]) -> BatchPlan {
+        let lang = lang.into();
+        let mut seen: HashSet<&str> = HashSet::new();
+        let unique: Vec<String> = words.iter()
+            .filter(|word| seen.insert(**word))
+            .map(|word| word.to_string())
+            .collect();
+        let cached = unique.iter().filter(|word| self.is_cached(&lang, word)).count();
+        let remaining = self.limiter.as_ref()
+            .and_then(|limiter| unwind::lock(limiter).remaining_today(Instant::now()));
+        let remaining = match (remaining, self.remaining_estimate()) {
+            (Some(a), Some(b)) => Some(a.min(b)),
+            (a, b) => a.or(b),
+        };
+        BatchPlan {
+            api: self.clone(),
+            lang: lang,
+            requests: unique.len() - cached,
+            words: unique,
+            cached: cached,
+            remaining: remaining,
+        }
+    }
+
+    // Whether `lookup_word` would be answered by the cache, as
+    // `lookup_fresh` decides it. A panicking backend has nothing.
+    fn is_cached(&self, lang: &LangPair, word: &str) -> bool {
+        let options = self.options_for(lang, &LookupOptions::default());
+        let key = CacheKey {
+            lang: lang.as_str().to_owned(),
+            text: word.to_owned(),
+            flags: options.flags.bits(),
+            ui: options.ui,
+        };
+        let cache = match self.cache {
+            Some(ref cache) => cache,
+            None => return false,
+        };
+        let max_age = self.cache_policy.max_age();
+        with_cache(cache, |cache| match (cache.get(&key), max_age) {
+            (None, _) => false,
+            (Some(_), None) => true,
+            (Some(_), Some(max_age)) => cache.age(&key).is_none_or(|age| age <= max_age),
+        }).unwrap_or(false)
+    }
+
+    fn lookup_word(&self, lang: &LangPair, word: &str, cancel: Option<&CancellationToken>)
+        -> Result<Vec<Def>, RequestError> {
+        let status = Cell::new(None);
+        let limits = Limits {
+            timeout: self.timeout,
+            cancel: cancel,
+            status: Some(&status),
+        };
+        self.lookup_with_limits(lang, word, &LookupOptions::default(), limits)
+            .and_then(|json| self.parse_defs(json))
+            .map_err(|e| e.with_context(word_context(lang, word, status.get())))
+    }
+
+    fn batch(&self, lang: &LangPair, words: &[&str], cancel: Option<&CancellationToken>, options: &BatchOptions)
+        -> BatchReport {
+        // Looked up words and every distinct input with the index of its
+        // looked up one
+        let mut unique: Vec<&str> = Vec::new();
+        let mut inputs: Vec<(String, usize)> = Vec::new();
+        let mut groups: HashMap<String, usize> = HashMap::new();
+        let mut seen: HashSet<&str> = HashSet::new();
+        for word in words {
+            let key = options.dedup_mode.key(word);
+            let idx = *groups.entry(key).or_insert_with(|| {
+                unique.push(word);
+                unique.len() - 1
+            });
+            if seen.insert(*word) {
+                inputs.push((word.to_string(), idx));
+            }
+        }
+        let stop = AuthStop::new(options);
+        let results = self.parallel(unique.len(), |idx| stop.lookup(self, lang, unique[idx], cancel));
+        BatchReport {
+            results: unique.into_iter()
+                .zip(results)
+                .map(|(word, result)| (word.to_owned(), result))
+                .collect(),
+            error: stop.into_error(),
+            index: inputs.iter().cloned().collect(),
+            inputs: inputs,
+        }
+    }
+
+    // Results of `run` for indexes up to `count` with `with_concurrency`
+    // threads, in order of indexes.
+    fn parallel<T: Send, F: Fn(usize) -> T + Sync>(&self, count: usize, run: F) -> Vec<T> {
+        let next = AtomicUsize::new(0);
+        let results: Mutex<Vec<Option<T>>> = Mutex::new((0..count).map(|_| None).collect());
+        let workers = self.concurrency.min(count);
+        thread::scope(|scope| {
+            for _ in 0..workers {
+                scope.spawn(|| {
+                    loop {
+                        let idx = next.fetch_add(1, Ordering::SeqCst);
+                        if idx >= count {
+                            break;
+                        }
+                        let result = run(idx);
+                        unwind::lock(&results)[idx] = Some(result);
+                    }
+                });
+            }
+        });
+        results.into_inner().unwrap().into_iter().map(Option::unwrap).collect()
+    }
+
+    // Looks `text` up from `source` into every language of `targets` in
+    // parallel, e.g. for a sheet of a word in several languages. A rejected
+    // key skips the languages which aren't looked up yet.
+    pub fn lookup_multi(&self, source: &str, targets: &[&str], text: &str) -> MultiLookup {
+        let langs: Vec<LangPair> = targets.iter().map(|target| LangPair::new(source, target)).collect();
+        let stop = AuthStop::new(&BatchOptions::default());
+        let results = self.parallel(langs.len(), |idx| stop.lookup(self, &langs[idx], text, None));
+        MultiLookup {
+            source: source.to_owned(),
+            text: text.to_owned(),
+            targets: targets.iter().map(|target| target.to_string()).zip(results).collect(),
+        }
+    }
+}
+
+// Cost of a batch counted by `Api::plan_batch`.
+pub struct BatchPlan {
+    api: Api,
+    lang: LangPair,
+    // Distinct words in order of the first occurrence.
+    pub words: Vec<String>,
+    // Words fresh in the cache, they cost nothing.
+    pub cached: usize,
+    // Requests the batch needs, one per word which isn't cached.
+    pub requests: usize,
+    // Requests left today: the smaller of the daily limit of
+    // `with_rate_limit` and the budget of `with_daily_budget` or
+    // `with_quota_file`, `None` without both.
+    pub remaining: Option<u32>,
+}
+
+impl BatchPlan {
+    // Whether the batch fits into what's left today. The service may
+    // count the day differently, so it's an estimate like
+    // `Api::remaining_estimate`.
+    pub fn fits(&self) -> bool {
+        self.remaining.is_none_or(|remaining| self.requests <= remaining as usize)
+    }
+
+    // Runs the batch as `lookup_batch` does, words cached since
+    // planning cost nothing still.
+    pub fn execute(&self) -> BatchResult {
+        let words: Vec<&str> = self.words.iter().map(String::as_str).collect();
+        self.api.batch(&self.lang, &words, None, &BatchOptions::default()).results
+    }
+}
+
+// Stops lookups of a batch once the service rejects the key, unless
+// `BatchOptions::continue_on_auth_failure` is set. Words taken after that
+// are skipped, lookups in flight finish as they go.
+struct AuthStop {
+    enabled: bool,
+    stopped: AtomicBool,
+    error: Mutex<Option<RequestError>>,
+}
+
+impl AuthStop {
+    fn new(options: &BatchOptions) -> AuthStop {
+        AuthStop {
+            enabled: !options.continue_on_auth_failure,
+            stopped: AtomicBool::new(false),
+            error: Mutex::new(None),
+        }
+    }
+
+    fn lookup(&self, api: &Api, lang: &LangPair, word: &str, cancel: Option<&CancellationToken>)
+        -> Result<Vec<Def>, RequestError> {
+        if self.stopped.load(Ordering::SeqCst) {
+            let skipped = RequestError::Skipped(SkipReason::AuthFailure);
+            return Err(skipped.with_context(word_context(lang, word, None)));
+        }
+        let result = api.lookup_word(lang, word, cancel);
+        if let Err(ref e) = result {
+            if self.enabled && is_auth_failure(e) && !self.stopped.swap(true, Ordering::SeqCst) {
+                *unwind::lock(&self.error) = Some(copy_auth_failure(e));
+            }
+        }
+        result
+    }
+
+    // The rejection which stopped the batch.
+    fn into_error(self) -> Option<RequestError> {
+        self.error.into_inner().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+fn word_context(lang: &LangPair, word: &str, status: Option<u16>) -> ErrorContext {
+    ErrorContext {
+        endpoint: "lookup".to_owned(),
+        lang: Some(lang.as_str().to_owned()),
+        text: Some(word.to_owned()),
+        status: status,
+    }
+}
+
+fn is_auth_failure(error: &RequestError) -> bool {
+    matches!(*error.root(), RequestError::KeyInvalid | RequestError::KeyBlocked)
+}
+
+// Errors aren't `Clone`, the report gets its own copy of the rejection.
+fn copy_auth_failure(error: &RequestError) -> RequestError {
+    let root = match *error.root() {
+        RequestError::KeyBlocked => RequestError::KeyBlocked,
+        _ => RequestError::KeyInvalid,
+    };
+    match error.context() {
+        Some(context) => root.with_context(context.clone()),
+        None => root,
+    }
+}
+
+// Result of `Api::lookup_multi`: a row of a word with a cell per target
+// language in order of targets. A failed cell doesn't fail the others.
+#[derive(Debug)]
+pub struct MultiLookup {
+    pub source: String,
+    pub text: String,
+    pub targets: Vec<(String, Result<Vec<Def>, RequestError>)>,
+}
+
+impl MultiLookup {
+    pub fn defs(&self, target: &str) -> Option<&Result<Vec<Def>, RequestError>> {
+        self.targets.iter().find(|&(lang, _)| lang == target).map(|(_, result)| result)
+    }
+
+    // Distinct translations into `target` in order of the answer, none if
+    // the lookup failed.
+    pub fn translations(&self, target: &str) -> Vec<&str> {
+        let mut texts: Vec<&str> = Vec::new();
+        if let Some(Ok(defs)) = self.defs(target) {
+            for tr in defs.iter().flat_map(|def| def.trans.iter()) {
+                if !texts.contains(&tr.text.as_str()) {
+                    texts.push(&tr.text);
+                }
+            }
+        }
+        texts
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use std::collections::HashMap;
+    use super::super::Api;
+    use super::{BatchOptions, DedupMode};
+    use testing::{MockTransport, FIXTURE_KEY_INVALID, FIXTURE_LOOKUP_RUST};
+
+    // The failing word needs a real client to be refused by the closed port.
+    #[cfg(any(feature = "hyper", feature = "ureq"))]
+    #[test]
+    fn check_lookup_batch() {
+        use serde_json::{self, Value};
+        use cache::{CacheKey, LruCache};
+        use super::super::RequestError;
+
+        let mut cache: LruCache<CacheKey, Value> = LruCache::new(10);
+        for word in &["rust", "iron"] {
+            let key = CacheKey {
+                lang: "en-ru".to_owned(),
+                text: word.to_string(),
+                flags: 0,
+                ui: None,
+            };
+            let body = format!(r#"{{"head": {{}}, "def": [{{"text": "{}", "tr": []}}]}}"#, word);
+            cache.put(key, serde_json::from_str(&body).unwrap());
+        }
+        // Nothing listens there, so uncached words fail fast
+        let api = Api::from_token("invalid").unwrap()
+            .with_base_url("http://127.0.0.1:1")
+            .with_cache_backend(cache)
+            .with_concurrency(2);
+        let results = api.lookup_batch("en-ru", &["rust", "qwzx", "iron", "rust"]);
+        let words: Vec<&str> = results.iter().map(|(w, _)| w.as_str()).collect();
+        assert_eq!(words, vec!["rust", "qwzx", "iron"]);
+        assert_eq!(results[0].1.as_ref().unwrap()[0].word.text, "rust");
+        // Either backend, ureq is preferred when both are built
+        match results[1].1.as_ref().map_err(RequestError::kind) {
+            Err("http") => (),
+            other => panic!("unexpected result: {:?}", other.map(|defs| defs.len())),
+        }
+        let context = results[1].1.as_ref().err().and_then(RequestError::context).unwrap();
+        assert_eq!(context.text.as_ref().unwrap(), "qwzx");
+        assert!(context.status.is_none());
+        assert_eq!(results[2].1.as_ref().unwrap()[0].word.text, "iron");
+    }
+
+    #[test]
+    fn check_lookup_iter() {
+        let transport = MockTransport::with_fixtures();
+        let api = Api::from_token("token").unwrap().with_transport(transport).with_concurrency(3);
+        let words = vec!["rust", "qwzx", "rust"].into_iter().chain((0..20).map(|_| "iron"));
+        let results: Vec<_> = api.lookup_iter("en-ru", words).collect();
+        assert_eq!(results.len(), 3);
+        let counts = results.iter().fold(HashMap::new(), |mut counts, (word, result)| {
+            *counts.entry(word.as_str()).or_insert(0) += 1;
+            assert!(result.is_ok());
+            counts
+        });
+        assert_eq!(counts["rust"], 1);
+        assert_eq!(counts["iron"], 1);
+
+        // Spellings are the same word for the mode
+        let options = BatchOptions {
+            dedup_mode: DedupMode::CaseInsensitive,
+            ..BatchOptions::default()
+        };
+        let results: Vec<_> = api.lookup_iter_with("en-ru", vec!["Rust", "rust", "RUST"], &options).collect();
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].0, "Rust");
+
+        // Stopping early is fine
+        let mut results = api.lookup_iter("en-ru", (0..1000).map(|idx| format!("word{}", idx)));
+        assert!(results.next().is_some());
+        drop(results);
+    }
+
+    #[test]
+    fn check_lookup_iter_auth_failure() {
+        use super::super::RequestError;
+
+        // The key is rejected on the fourth word
+        let transport = MockTransport::new()
+            .on("text=w3", 403, FIXTURE_KEY_INVALID)
+            .on("/lookup?", 200, FIXTURE_LOOKUP_RUST);
+        let api = Api::from_token("token").unwrap().with_transport(transport.clone()).with_concurrency(1);
+        let results: Vec<_> = api.lookup_iter("en-ru", (0..10).map(|idx| format!("w{}", idx))).collect();
+        assert_eq!(results.len(), 10);
+        assert_eq!(transport.requests().len(), 4);
+        assert!(results[..3].iter().all(|(_, result)| result.is_ok()));
+        match results[3].1.as_ref().map_err(RequestError::root) {
+            Err(&RequestError::KeyInvalid) => (),
+            other => panic!("unexpected result: {:?}", other),
+        }
+        assert!(results[4..].iter().all(|(_, result)| result.as_ref().err().unwrap().kind() == "skipped"));
+    }
+
+    #[test]
+    fn check_lookup_multi() {
+        let transport = MockTransport::new()
+            .on("lang=en-de&text=rust", 200, r#"{"def": [{"text": "rust", "tr": [{"text": "Rost"}, {"text": "Rost"}]}]}"#)
+            .on("lang=en-fr&text=rust", 503, "")
+            .on("lang=en-ru&text=rust", 200, FIXTURE_LOOKUP_RUST);
+        let api = Api::from_token("token").unwrap().with_transport(transport);
+        let multi = api.lookup_multi("en", &["ru", "de", "fr"], "rust");
+        let langs: Vec<&str> = multi.targets.iter().map(|(lang, _)| lang.as_str()).collect();
+        assert_eq!(langs, vec!["ru", "de", "fr"]);
+        assert_eq!(multi.translations("ru")[0], "ржавчина");
+        assert_eq!(multi.translations("de"), vec!["Rost"]);
+        assert!(multi.defs("fr").unwrap().is_err());
+        assert!(multi.translations("fr").is_empty());
+        assert!(multi.defs("es").is_none());
+    }
+
+    #[test]
+    fn check_plan_batch() {
+        use std::env;
+        use std::fs;
+        use std::time::{SystemTime, UNIX_EPOCH};
+        use serde_json;
+        use cache::{CacheKey, LruCache};
+        use super::super::RateLimit;
+
+        let mut cache = LruCache::new(10);
+        for word in &["rust", "iron"] {
+            let key = CacheKey {
+                lang: "en-ru".to_owned(),
+                text: word.to_string(),
+                flags: 0,
+                ui: None,
+            };
+            cache.put(key, serde_json::from_str(FIXTURE_LOOKUP_RUST).unwrap());
+        }
+        // 3 of 8 requests of today are spent already
+        let dir = env::temp_dir().join("yadict-test-plan-batch");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("quota.json");
+        let day = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs() / (24 * 60 * 60);
+        fs::write(&path, format!(r#"{{"day": {}, "used": 3}}"#, day)).unwrap();
+        let transport = MockTransport::with_fixtures();
+        let api = Api::from_token("token").unwrap()
+            .with_transport(transport.clone())
+            .with_cache_backend(cache)
+            .with_quota_file(&path, 8);
+
+        let words = ["rust", "tin", "iron", "rust", "zinc", "tin", "lead", "gold", "copper"];
+        let plan = api.plan_batch("en-ru", &words);
+        assert_eq!(plan.words, vec!["rust", "tin", "iron", "zinc", "lead", "gold", "copper"]);
+        assert_eq!(plan.cached, 2);
+        assert_eq!(plan.requests, 5);
+        assert_eq!(plan.remaining, Some(5));
+        assert!(plan.fits());
+        // The soft daily limit is smaller than the budget
+        let limited = api.clone().with_rate_limit(RateLimit {
+            per_day: Some(4),
+            ..RateLimit::default()
+        });
+        let plan = limited.plan_batch("en-ru", &words);
+        assert_eq!(plan.remaining, Some(4));
+        assert!(!plan.fits());
+        assert!(transport.requests().is_empty());
+
+        let plan = api.plan_batch("en-ru", &words[..6]);
+        assert_eq!((plan.cached, plan.requests), (2, 2));
+        let results = plan.execute();
+        assert_eq!(results.len(), 4);
+        assert!(results.iter().all(|(_, result)| result.is_ok()));
+        assert_eq!(transport.requests().len(), 2);
+        assert_eq!(api.remaining_estimate(), Some(3));
+        let plan = api.plan_batch("en-ru", &words);
+        assert_eq!((plan.cached, plan.requests, plan.remaining), (4, 3, Some(3)));
+        assert!(plan.fits());
+    }
+
+    #[test]
+    fn check_auth_failure_stops_batch() {
+        use super::super::RequestError;
+        use super::{BatchOptions, SkipReason};
+
+        // The key gets blocked on the fourth word
+        let transport = MockTransport::new()
+            .on("text=w3", 402, r#"{"code": 402, "message": "API key is blocked"}"#)
+            .on("/lookup?", 200, FIXTURE_LOOKUP_RUST);
+        let api = Api::from_token("token").unwrap().with_transport(transport.clone()).with_concurrency(1);
+        let words: Vec<String> = (0..10).map(|idx| format!("w{}", idx)).collect();
+        let words: Vec<&str> = words.iter().map(String::as_str).collect();
+        let report = api.lookup_many("en-ru", &words, &BatchOptions::default());
+        assert_eq!(transport.requests().len(), 4);
+        assert_eq!(report.results.len(), 10);
+        assert!(report.results[..3].iter().all(|(_, result)| result.is_ok()));
+        match report.results[3].1.as_ref().map_err(RequestError::root) {
+            Err(&RequestError::KeyBlocked) => (),
+            other => panic!("unexpected result: {:?}", other),
+        }
+        for (word, result) in &report.results[4..] {
+            let error = result.as_ref().err().unwrap();
+            match *error.root() {
+                RequestError::Skipped(SkipReason::AuthFailure) => (),
+                ref other => panic!("unexpected error: {:?}", other),
+            }
+            assert_eq!(error.context().unwrap().text.as_ref(), Some(word));
+        }
+        assert_eq!(report.results[9].1.as_ref().err().unwrap().to_string(),
+                   "lookup of 'w9' in en-ru: skipped: API key was rejected earlier in the batch");
+        let error = report.error.unwrap();
+        assert_eq!(error.to_string(), "lookup of 'w3' in en-ru (HTTP 402): API key is blocked");
+        assert_eq!(api.lookup_batch("en-ru", &words)[9].1.as_ref().err().unwrap().kind(), "skipped");
+
+        // Going on sends the rest
+        let transport = MockTransport::new()
+            .on("text=w3", 402, r#"{"code": 402, "message": "API key is blocked"}"#)
+            .on("/lookup?", 200, FIXTURE_LOOKUP_RUST);
+        let api = api.with_transport(transport.clone());
+        let options = BatchOptions {
+            continue_on_auth_failure: true,
+            ..BatchOptions::default()
+        };
+        let report = api.lookup_many("en-ru", &words, &options);
+        assert_eq!(transport.requests().len(), 10);
+        assert_eq!(report.results.iter().filter(|&(_, result)| result.is_ok()).count(), 9);
+        assert!(report.error.is_none());
+
+        // Workers finish lookups in flight only
+        let transport = MockTransport::new()
+            .on("text=w3", 402, r#"{"code": 402, "message": "API key is blocked"}"#)
+            .on("/lookup?", 200, FIXTURE_LOOKUP_RUST);
+        let api = api.with_transport(transport.clone()).with_concurrency(4);
+        let report = api.lookup_many("en-ru", &words, &BatchOptions::default());
+        let sent = transport.requests().len();
+        assert!((4..=7).contains(&sent), "{} sent", sent);
+        let skipped = report.results.iter().filter(|&(_, result)| {
+            matches!(result.as_ref().map_err(RequestError::root), Err(&RequestError::Skipped(_)))
+        }).count();
+        assert_eq!(skipped, 10 - sent);
+        assert!(report.error.is_some());
+    }
+
+    #[test]
+    fn check_dedup_modes() {
+        use super::{BatchOptions, DedupMode};
+
+        let transport = MockTransport::with_fixtures();
+        let api = Api::from_token("token").unwrap().with_transport(transport.clone());
+        let options = BatchOptions {
+            dedup_mode: DedupMode::CaseInsensitive,
+            ..BatchOptions::default()
+        };
+        let report = api.lookup_many("en-ru", &["Apple", "apple", "Apple"], &options);
+        assert_eq!(transport.requests().len(), 1);
+        assert!(transport.requests()[0].contains("text=Apple"));
+        assert_eq!(report.results.len(), 1);
+        let entries = report.entries();
+        assert_eq!(entries.iter().map(|&(word, _)| word).collect::<Vec<_>>(), vec!["Apple", "apple"]);
+        assert!(entries.iter().all(|&(_, result)| result.as_ref().unwrap().is_empty()));
+        assert!(report.get("apple").is_some());
+        assert!(report.get("APPLE").is_none());
+
+        // Exact by default
+        let report = api.lookup_many("en-ru", &["rust", "Rust", " rust"], &BatchOptions::default());
+        assert_eq!(report.results.len(), 3);
+        assert_eq!(report.entries().len(), 3);
+        assert_eq!(transport.requests().len(), 4);
+
+        let options = BatchOptions {
+            dedup_mode: DedupMode::CaseAndWhitespaceInsensitive,
+            ..BatchOptions::default()
+        };
+        let report = api.lookup_many("en-ru", &["rust", "Rust", " rust", "apple  pie", "Apple pie "], &options);
+        assert_eq!(report.results.len(), 2);
+        assert_eq!(report.get(" rust").unwrap().as_ref().unwrap().len(), 3);
+        assert!(report.get("Apple pie ").unwrap().as_ref().unwrap().is_empty());
+        assert_eq!(report.entries().len(), 5);
+        assert_eq!(transport.requests().len(), 6);
+    }
+
+    #[test]
+    fn check_batch_rate_limit() {
+        use super::super::{LimitAction, RateLimit, RequestError};
+
+        let api = Api::from_token("token").unwrap()
+            .with_transport(MockTransport::with_fixtures())
+            .with_concurrency(8)
+            .with_rate_limit(RateLimit {
+                per_second: Some(1.0),
+                action: LimitAction::Reject,
+                ..RateLimit::default()
+            });
+        let words: Vec<String> = (0..20).map(|idx| format!("word{}", idx)).collect();
+        let words: Vec<&str> = words.iter().map(String::as_str).collect();
+        let results = api.lookup_batch("en-ru", &words);
+        // The burst is one request and a permit more comes each second.
+        // With a limiter per worker each of 8 workers would send one at
+        // least, a shared one lets through one more per second of the test.
+        let sent = results.iter().filter(|&(_, result)| result.is_ok()).count();
+        assert!((1..8).contains(&sent), "{} sent", sent);
+        let order: Vec<&str> = results.iter().map(|(word, _)| word.as_str()).collect();
+        assert_eq!(order, words);
+        assert!(results.iter().all(|(_, result)| match *result {
+            Ok(_) => true,
+            Err(ref e) => matches!(*e.root(), RequestError::RateLimited),
+        }));
+    }
+}
@@ -0,0 +1,787 @@
+// The crate spells out field names and `'static` of constants.
+#![allow(clippy::redundant_field_names, clippy::redundant_static_lifetimes)]
+
+extern crate serde_json;
+extern crate yadict;
+
+use std::env;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Read, Write};
+use std::process;
+use std::time::{SystemTime, UNIX_EPOCH};
+use yadict::{Api, Def, KeyStatus, LangPair, LookupOptions, MultiLookup, RateLimit};
+use yadict::cache::{self, FileCache, Freshness};
+use yadict::config::{self, Config};
+use yadict::export;
+use yadict::format::Terminal;
+use yadict::history::{History, HistoryQuery};
+use yadict::review::Schedule;
+use yadict::stats::UsageLog;
+#[cfg(feature = "store")]
+use yadict::store::Store;
+#[cfg(feature = "tts")]
+use yadict::tts::{self, Speaker};
+
+const USAGE: &'static str = "Usage:
+    yadict [--token TOKEN] langs
+    yadict [--token TOKEN] ping
+    yadict [--token TOKEN] lookup [--format FORMAT] [--speak DIR] [LANG] TEXT...
+    yadict [--token TOKEN] lookup [--format FORMAT] [LANG] --to LANG,LANG... TEXT...
+    yadict [--token TOKEN] batch [--format FORMAT] [--speak DIR] [LANG] [--input FILE]
+                                 [--output FILE] [--rate N] [--no-cache]
+    yadict [--token TOKEN] warm FILE [LANG] [--rate N]
+    yadict [--token TOKEN] annotate [LANG] [--input FILE] [--output FILE]
+    yadict [--token TOKEN] repl [LANG]
+    yadict [--token TOKEN] history [LANG] [--days N] [--search TEXT] [--replay]
+    yadict [--token TOKEN] review [LANG] [--limit N]
+    yadict dict [--format FORMAT] [LANG] [--top N] [--days N]
+    yadict stats [--days N]
+
+Formats are text (lookup only), json, csv, tsv, markdown and anki. Flat formats
+have a row per translation: word, pos, transcription, translation. Anki format
+is a deck for File > Import with a note per word.
+
+--to compares translations of TEXT from the source language of LANG into
+every listed language, looked up in parallel, as a row of a table with a
+column per language (a line per language in text format). Formats are text,
+json, csv, tsv and markdown.
+
+Batch mode reads one word per line (stdin by default) and writes tsv unless
+--format is set, json is written as a line per word. Answers are cached
+in ~/.cache/yadict unless cache_dir is configured, requests are limited
+to --rate per second (5 by default).
+
+Warm looks up words of FILE (a word per line, or csv and tsv with words in the
+first column) into the cache of batch mode, so they are found later at once and
+without network. Cached words are skipped, requests are limited as in batch mode.
+
+Annotate reads a text (stdin by default) and writes an HTML page of it where
+translations of words show on hover, for reading in a foreign language. Answers
+are cached as in batch mode.
+
+--speak saves pronunciations of found words to DIR, Anki notes get them on
+the front: copy the files to collection.media of the Anki profile. Speech
+comes from Yandex SpeechKit with tts_key in the config, from espeak-ng
+otherwise. Needs yadict built with the tts feature.
+
+REPL looks up a word per line and understands commands :lang LANG,
+:flags [family|morpho|pos_filter...], :history and :quit.
+
+History lists lookups journaled with `history = true` in the config, from
+~/.local/share/yadict/history.jsonl: of LANG only, of the last N days, with
+TEXT in the word. --replay looks the listed words up again for review.
+
+Review quizzes words of the history when they are due by SM-2 schedule,
+kept in ~/.local/share/yadict/review.json: recall the translation, press
+Enter to check it and grade the answer from 0 (forgot) to 5 (perfect).
+--limit caps the number of words at a time (20 by default).
+
+Dict lists the personal dictionary of words found with `store = true` in the
+config, kept in ~/.local/share/yadict/dictionary.sqlite: of LANG only, N most
+looked up ones or ones added in the last N days. Lines show the last lookup,
+LANG, the word, the number of lookups and translations, --format exports the
+words instead. Needs yadict built with the store feature.
+
+Stats shows requests, cache hits, errors, traffic and average latency of
+lookups by UTC day, kept in ~/.local/share/yadict/usage.json, for the last N
+days (7 by default), and what's left of daily_budget if it's configured.
+
+Ping checks the key with the service and fails if it's invalid or blocked.
+
+Settings are read from ~/.config/yadict/config.toml: token, lang (the default
+LANG), cache_dir, cache_max_age_days (older answers are used only if the service
+can't be reached), flags, format, history, store and tts_key. Environment
+variables YANDEX_DICTIONARY_TOKEN, YADICT_LANG, YADICT_CACHE_DIR, YADICT_FORMAT
+and YADICT_TTS_KEY override the file, options override both.";
+
+fn usage() -> ! {
+    eprintln!("{}", USAGE);
+    process::exit(2);
+}
+
+fn fail<E: ::std::fmt::Display>(e: E) -> ! {
+    eprintln!("yadict: {}", e);
+    process::exit(1);
+}
+
+// Removes option with its value from `args`.
+fn take_option(args: &mut Vec<String>, names: &[&str]) -> Option<String> {
+    match args.iter().position(|arg| names.contains(&arg.as_str())) {
+        Some(idx) => {
+            if idx + 1 >= args.len() {
+                usage();
+            }
+            let value = args.remove(idx + 1);
+            args.remove(idx);
+            Some(value)
+        },
+        None => None,
+    }
+}
+
+fn take_flag(args: &mut Vec<String>, name: &str) -> bool {
+    match args.iter().position(|arg| arg == name) {
+        Some(idx) => {
+            args.remove(idx);
+            true
+        },
+        None => false,
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Format {
+    Text,
+    Json,
+    Csv,
+    Tsv,
+    Markdown,
+    Anki,
+}
+
+fn parse_format(name: &str) -> Format {
+    match name {
+        "text" => Format::Text,
+        "json" => Format::Json,
+        "csv" => Format::Csv,
+        "tsv" => Format::Tsv,
+        "markdown" | "md" => Format::Markdown,
+        "anki" => Format::Anki,
+        _ => usage(),
+    }
+}
+
+fn row_line(format: Format) -> fn(&[&str]) -> String {
+    match format {
+        Format::Csv => export::csv_line,
+        Format::Markdown => export::markdown_line,
+        _ => export::tsv_line,
+    }
+}
+
+fn write_header<W: Write>(out: &mut W, format: Format) -> io::Result<()> {
+    match format {
+        Format::Json => Ok(()),
+        Format::Anki => writeln!(out, "{}", export::ANKI_HEADER),
+        Format::Markdown => writeln!(out, "{}", export::markdown_header()),
+        _ => writeln!(out, "{}", row_line(format)(&export::COLUMNS)),
+    }
+}
+
+fn write_word<W: Write>(out: &mut W, format: Format, defs: &[Def], sounds: &[(String, String)]) -> io::Result<()> {
+    if format == Format::Json {
+        // Keeps a line per word, so the output can be streamed too
+        let line = serde_json::to_string(defs).map_err(io::Error::from);
+        return writeln!(out, "{}", line?);
+    }
+    if format == Format::Anki {
+        let sound = |text: &str| sounds.iter().find(|&(word, _)| word == text).map(|(_, file)| file.clone());
+        for note in export::anki_notes_with_sounds(defs, sound) {
+            writeln!(out, "{}", note)?;
+        }
+        return Ok(());
+    }
+    for row in export::rows(defs) {
+        writeln!(out, "{}", row_line(format)(&row.fields()))?;
+    }
+    Ok(())
+}
+
+// Saves pronunciations of headwords and returns their file names by words.
+type Pronounce = Box<dyn Fn(&str, &[Def]) -> Vec<(String, String)>>;
+
+// SpeechKit goes over hyper, builds without it speak with espeak-ng only.
+#[cfg(feature = "tts")]
+fn speaker(config: &Config) -> Box<dyn Speaker> {
+    #[cfg(feature = "hyper")]
+    {
+        if let Some(ref key) = config.tts_key {
+            return Box::new(tts::SpeechKit::from_key(key));
+        }
+    }
+    let _ = config;
+    Box::new(tts::Command::espeak())
+}
+
+#[cfg(feature = "tts")]
+fn pronouncer(config: &Config, dir: String) -> Pronounce {
+    let speaker = speaker(config);
+    ::std::fs::create_dir_all(&dir).unwrap_or_else(|e| fail(format!("{}: {}", dir, e)));
+    Box::new(move |lang, defs| {
+        let lang = lang.parse::<LangPair>().map(|pair| pair.source().to_owned()).unwrap_or_default();
+        let mut sounds: Vec<(String, String)> = Vec::new();
+        for def in defs {
+            if sounds.iter().any(|(word, _)| *word == def.word.text) {
+                continue;
+            }
+            match def.word.pronounce(&*speaker, &lang) {
+                Ok(audio) => {
+                    let file = audio.file_name(&def.word.text);
+                    let path = ::std::path::Path::new(&dir).join(&file);
+                    ::std::fs::write(&path, &audio.data).unwrap_or_else(|e| fail(format!("{}: {}", path.display(), e)));
+                    sounds.push((def.word.text.clone(), file));
+                },
+                Err(e) => eprintln!("yadict: can't pronounce '{}': {}", def.word.text, e),
+            }
+        }
+        sounds
+    })
+}
+
+#[cfg(not(feature = "tts"))]
+fn pronouncer(_config: &Config, _dir: String) -> Pronounce {
+    fail("--speak needs yadict built with the tts feature")
+}
+
+fn batch(api: Api, lang: &str, options: &LookupOptions, format: Format, speak: Option<Pronounce>,
+         mut args: Vec<String>) {
+    if format == Format::Text {
+        usage();
+    }
+    let input = take_option(&mut args, &["--input", "-i"]);
+    let output = take_option(&mut args, &["--output", "-o"]);
+    let rate = take_option(&mut args, &["--rate"]).map(|rate| {
+        rate.parse::<f64>().ok().filter(|rate| *rate > 0.0).unwrap_or_else(|| usage())
+    }).unwrap_or(5.0);
+    if !args.is_empty() {
+        usage();
+    }
+    let api = api.with_rate_limit(RateLimit {
+        per_second: Some(rate),
+        ..RateLimit::default()
+    });
+    let (reader, total): (Box<dyn BufRead>, Option<usize>) = match input {
+        Some(ref path) => {
+            // Counting lines first costs one more pass, but gives a real progress
+            let file = File::open(path).unwrap_or_else(|e| fail(format!("{}: {}", path, e)));
+            let total = BufReader::new(file).lines().count();
+            let file = File::open(path).unwrap_or_else(|e| fail(format!("{}: {}", path, e)));
+            (Box::new(BufReader::new(file)), Some(total))
+        },
+        None => (Box::new(BufReader::new(io::stdin())), None),
+    };
+    let mut out: Box<dyn Write> = match output {
+        Some(ref path) => {
+            let file = File::create(path).unwrap_or_else(|e| fail(format!("{}: {}", path, e)));
+            Box::new(BufWriter::new(file))
+        },
+        None => Box::new(BufWriter::new(io::stdout())),
+    };
+    write_header(&mut out, format).unwrap_or_else(|e| fail(e));
+    let mut processed = 0;
+    let mut failed = 0;
+    for line in reader.lines() {
+        let line = line.unwrap_or_else(|e| fail(e));
+        processed += 1;
+        let word = line.trim();
+        if !word.is_empty() {
+            match api.lookup_def_with_options(lang, word, options) {
+                Ok(defs) => {
+                    let sounds = speak.as_ref().map(|speak| speak(lang, &defs)).unwrap_or_default();
+                    write_word(&mut out, format, &defs, &sounds).unwrap_or_else(|e| fail(e));
+                },
+                Err(e) => {
+                    failed += 1;
+                    eprintln!("\ryadict: {}: {}", word, e);
+                },
+            }
+        }
+        match total {
+            Some(total) => eprint!("\r{}/{} words", processed, total),
+            None => eprint!("\r{} words", processed),
+        }
+    }
+    out.flush().unwrap_or_else(|e| fail(e));
+    eprintln!();
+    if failed > 0 {
+        fail(format!("{} of {} words failed", failed, processed));
+    }
+}
+
+fn print_multi(multi: &MultiLookup, format: Format) {
+    for (lang, result) in &multi.targets {
+        if let Err(ref e) = *result {
+            eprintln!("yadict: {}: {}", lang, e);
+        }
+    }
+    let mut header = vec![multi.source.as_str()];
+    header.extend(multi.targets.iter().map(|(lang, _)| lang.as_str()));
+    let cells: Vec<String> = multi.targets.iter().map(|(lang, _)| multi.translations(lang).join(", ")).collect();
+    let mut row = vec![multi.text.as_str()];
+    row.extend(cells.iter().map(String::as_str));
+    match format {
+        Format::Text => for (lang, cell) in header.iter().zip(row) {
+            println!("{}\t{}", lang, cell);
+        },
+        Format::Json => {
+            let mut object = serde_json::Map::new();
+            object.insert(multi.source.clone(), multi.text.clone().into());
+            for (lang, _) in &multi.targets {
+                object.insert(lang.clone(), multi.translations(lang).into());
+            }
+            println!("{}", serde_json::Value::Object(object));
+        },
+        Format::Csv => print!("{}\n{}\n", export::csv_line(&header), export::csv_line(&row)),
+        Format::Tsv => print!("{}\n{}\n", export::tsv_line(&header), export::tsv_line(&row)),
+        Format::Markdown => {
+            let delimiters: Vec<&str> = header.iter().map(|_| "---").collect();
+            print!("{}\n{}\n{}\n", export::markdown_line(&header), export::markdown_line(&delimiters),
+                   export::markdown_line(&row));
+        },
+        Format::Anki => usage(),
+    }
+    if multi.targets.iter().all(|(_, result)| result.is_err()) {
+        process::exit(1);
+    }
+}
+
+fn warm(api: Api, lang: &str, path: &str, mut args: Vec<String>) {
+    let rate = take_option(&mut args, &["--rate"]).map(|rate| {
+        rate.parse::<f64>().ok().filter(|rate| *rate > 0.0).unwrap_or_else(|| usage())
+    }).unwrap_or(5.0);
+    if !args.is_empty() {
+        usage();
+    }
+    let api = api.with_rate_limit(RateLimit {
+        per_second: Some(rate),
+        ..RateLimit::default()
+    });
+    let warmed = cache::warm_from_file(&api, lang, path).unwrap_or_else(|e| fail(format!("{}: {}", path, e)));
+    for (word, e) in &warmed.errors {
+        eprintln!("yadict: {}: {}", word, e);
+    }
+    eprintln!("{} of {} words cached", warmed.words - warmed.errors.len(), warmed.words);
+    if !warmed.errors.is_empty() {
+        fail(format!("{} of {} words failed", warmed.errors.len(), warmed.words));
+    }
+}
+
+fn annotate(api: Api, lang: &str, mut args: Vec<String>) {
+    let input = take_option(&mut args, &["--input", "-i"]);
+    let output = take_option(&mut args, &["--output", "-o"]);
+    if !args.is_empty() {
+        usage();
+    }
+    let mut text = String::new();
+    match input {
+        Some(ref path) => {
+            let mut file = File::open(path).unwrap_or_else(|e| fail(format!("{}: {}", path, e)));
+            file.read_to_string(&mut text).unwrap_or_else(|e| fail(format!("{}: {}", path, e)));
+        },
+        None => {
+            io::stdin().read_to_string(&mut text).unwrap_or_else(|e| fail(e));
+        },
+    }
+    let api = api.with_rate_limit(RateLimit {
+        per_second: Some(5.0),
+        ..RateLimit::default()
+    });
+    let annotation = api.annotate(lang, &text);
+    for (word, e) in &annotation.errors {
+        eprintln!("yadict: {}: {}", word, e);
+    }
+    let title = input.as_ref().map_or("yadict", |path| path.as_str());
+    let html = export::to_html(title, &text, &annotation);
+    match output {
+        Some(ref path) => {
+            let mut file = File::create(path).unwrap_or_else(|e| fail(format!("{}: {}", path, e)));
+            file.write_all(html.as_bytes()).unwrap_or_else(|e| fail(format!("{}: {}", path, e)));
+        },
+        None => print!("{}", html),
+    }
+}
+
+const REPL_COMMANDS: &'static str = "commands: :lang LANG, :flags [NAME...], :history, :quit";
+
+fn repl(api: Api, lang: &str, options: LookupOptions) {
+    let api = api.with_rate_limit(RateLimit {
+        per_second: Some(5.0),
+        ..RateLimit::default()
+    });
+    let mut lang = lang.to_owned();
+    let mut options = options;
+    let mut history: Vec<String> = Vec::new();
+    let stdin = io::stdin();
+    loop {
+        print!("{}> ", lang);
+        io::stdout().flush().unwrap_or_else(|e| fail(e));
+        let mut line = String::new();
+        if stdin.lock().read_line(&mut line).unwrap_or_else(|e| fail(e)) == 0 {
+            println!();
+            break;
+        }
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        if line.starts_with(':') {
+            let mut words = line.split_whitespace();
+            let command = words.next().unwrap_or("");
+            let args: Vec<&str> = words.collect();
+            match command {
+                ":lang" if args.len() == 1 => match args[0].parse::<LangPair>() {
+                    Ok(pair) => lang = pair.to_string(),
+                    Err(e) => eprintln!("{}", e),
+                },
+                ":flags" => match config::parse_flags(&args) {
+                    Some(flags) => options.flags = flags,
+                    None => eprintln!("flags are family, morpho and pos_filter"),
+                },
+                ":history" => {
+                    for (idx, text) in history.iter().enumerate() {
+                        println!("{:4}  {}", idx + 1, text);
+                    }
+                },
+                ":quit" | ":q" => break,
+                _ => eprintln!("{}", REPL_COMMANDS),
+            }
+            continue;
+        }
+        history.push(line.to_owned());
+        match api.lookup_def_with_options(lang.as_str(), line, &options) {
+            Ok(ref defs) if defs.is_empty() => eprintln!("no definitions of '{}'", line),
+            Ok(defs) => print_defs(&defs),
+            Err(e) => eprintln!("yadict: {}", e),
+        }
+    }
+}
+
+fn print_defs(defs: &[Def]) {
+    print!("{}", Terminal::auto().render(defs));
+}
+
+fn api(config: &Config) -> Api {
+    if config.token.is_none() {
+        fail(format!("no token, set --token, {} or token in the config", config::TOKEN_VAR));
+    }
+    let api = Api::from_settings(config).unwrap_or_else(|e| fail(e));
+    match UsageLog::default_path() {
+        Some(path) => api.with_usage_log(path),
+        None => api,
+    }
+}
+
+fn stats(config: &Config, mut args: Vec<String>) {
+    let days = take_option(&mut args, &["--days"]).map(|days| days.parse::<u64>().unwrap_or_else(|_| usage()));
+    if !args.is_empty() {
+        usage();
+    }
+    let path = UsageLog::default_path().unwrap_or_else(|| fail("no home directory to keep usage in"));
+    let log = UsageLog::new(path);
+    let today = unix_now() / 86400;
+    let since = today.saturating_sub(days.unwrap_or(7).saturating_sub(1));
+    println!("day\trequests\tcache hits\terrors\tKiB\tlatency");
+    for (day, stats) in log.days().range(since..) {
+        let latency = stats.average_latency().map_or(String::from("-"), |latency| format!("{} ms", latency.as_millis()));
+        println!("{}\t{}\t{}\t{}\t{}\t{}", &format_time(day * 86400)[..10], stats.requests, stats.cache_hits,
+                 stats.errors_total(), stats.bytes / 1024, latency);
+    }
+    if let Some(budget) = config.daily_budget {
+        let used = log.day(unix_now()).requests;
+        println!("{} of daily budget {} left, by this log", (budget as u64).saturating_sub(used), budget);
+    }
+}
+
+// UTC time like "2017-03-05 14:07" without a date crate, see
+// http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+fn format_time(time: u64) -> String {
+    let days = (time / 86400) as i64 + 719468;
+    let era = days.div_euclid(146097);
+    let day_of_era = days - era * 146097;
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    let seconds = time % 86400;
+    format!("{}-{:02}-{:02} {:02}:{:02}", year, month, day, seconds / 3600, seconds % 3600 / 60)
+}
+
+fn history(mut config: Config, mut args: Vec<String>) {
+    let days = take_option(&mut args, &["--days"]).map(|days| days.parse::<u64>().unwrap_or_else(|_| usage()));
+    let text = take_option(&mut args, &["--search", "-s"]);
+    let replay = take_flag(&mut args, "--replay");
+    let lang = match args.len() {
+        0 => None,
+        1 => Some(args[0].parse::<LangPair>().unwrap_or_else(|e| fail(e)).to_string()),
+        _ => usage(),
+    };
+    let now = unix_now();
+    let query = HistoryQuery {
+        lang: lang,
+        since: days.map(|days| now.saturating_sub(days * 86400)),
+        text: text,
+    };
+    let path = History::default_path().unwrap_or_else(|| fail("no home directory to keep history in"));
+    let entries = History::new(path).search(&query).unwrap_or_else(|e| fail(e));
+    if !replay {
+        for entry in entries {
+            println!("{}\t{}\t{}", format_time(entry.time), entry.lang, entry.text);
+        }
+        return;
+    }
+    // Reviewing words isn't looking them up again
+    config.history = false;
+    let api = api(&config);
+    let options = config.options();
+    let mut seen = Vec::new();
+    for entry in entries {
+        if seen.contains(&(entry.lang.clone(), entry.text.clone())) {
+            continue;
+        }
+        println!("# {} ({})", entry.text, entry.lang);
+        match api.lookup_def_with_options(entry.lang.as_str(), &entry.text, &options) {
+            Ok(defs) => print_defs(&defs),
+            Err(e) => eprintln!("yadict: {}: {}", entry.text, e),
+        }
+        seen.push((entry.lang, entry.text));
+    }
+}
+
+#[cfg(feature = "store")]
+fn dict(format: Option<Format>, mut args: Vec<String>) {
+    let top = take_option(&mut args, &["--top"]).map(|top| top.parse::<usize>().unwrap_or_else(|_| usage()));
+    let days = take_option(&mut args, &["--days"]).map(|days| days.parse::<u64>().unwrap_or_else(|_| usage()));
+    let lang = match args.len() {
+        0 => None,
+        1 => Some(args[0].parse::<LangPair>().unwrap_or_else(|e| fail(e)).to_string()),
+        _ => usage(),
+    };
+    let path = Store::default_path().unwrap_or_else(|| fail("no home directory to keep the dictionary in"));
+    let store = Store::open(&path).unwrap_or_else(|e| fail(e));
+    let now = unix_now();
+    let mut entries = match (top, days) {
+        (Some(_), Some(_)) => usage(),
+        (Some(top), None) if lang.is_none() => store.most_looked_up(top),
+        // The limit applies after the direction is filtered
+        (Some(_), None) => store.most_looked_up(usize::MAX),
+        (None, Some(days)) => store.by_date_range(now.saturating_sub(days * 86400), now + 1),
+        (None, None) => store.entries(),
+    }.unwrap_or_else(|e| fail(e));
+    if let Some(ref lang) = lang {
+        entries.retain(|entry| entry.lang == *lang);
+    }
+    if let Some(top) = top {
+        entries.truncate(top);
+    }
+    match format {
+        None | Some(Format::Text) => for entry in entries {
+            let trans: Vec<&str> = entry.defs.iter()
+                .flat_map(|def| def.trans.iter().map(|tr| tr.text.as_str()))
+                .collect();
+            println!("{}\t{}\t{}\t{}\t{}", format_time(entry.last_seen), entry.lang, entry.word, entry.lookups,
+                     trans.join(", "));
+        },
+        Some(format) => {
+            let mut out = BufWriter::new(io::stdout());
+            write_header(&mut out, format).unwrap_or_else(|e| fail(e));
+            for entry in entries {
+                write_word(&mut out, format, &entry.defs, &[]).unwrap_or_else(|e| fail(e));
+            }
+            out.flush().unwrap_or_else(|e| fail(e));
+        },
+    }
+}
+
+#[cfg(not(feature = "store"))]
+fn dict(_format: Option<Format>, _args: Vec<String>) {
+    fail("dict needs yadict built with the store feature")
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0)
+}
+
+// Reads a trimmed line after `text`, None at the end of input.
+fn prompt(text: &str) -> Option<String> {
+    print!("{}", text);
+    io::stdout().flush().unwrap_or_else(|e| fail(e));
+    let mut line = String::new();
+    match io::stdin().read_line(&mut line).unwrap_or_else(|e| fail(e)) {
+        0 => None,
+        _ => Some(line.trim().to_owned()),
+    }
+}
+
+fn review(mut config: Config, mut args: Vec<String>) {
+    let limit = take_option(&mut args, &["--limit"]).map_or(20, |limit| {
+        limit.parse::<usize>().ok().filter(|limit| *limit > 0).unwrap_or_else(|| usage())
+    });
+    let lang = match args.len() {
+        0 => None,
+        1 => Some(args[0].parse::<LangPair>().unwrap_or_else(|e| fail(e)).to_string()),
+        _ => usage(),
+    };
+    let history = History::default_path().unwrap_or_else(|| fail("no home directory to keep history in"));
+    let path = Schedule::default_path().unwrap_or_else(|| fail("no home directory to keep history in"));
+    let mut schedule = Schedule::open(&path).unwrap_or_else(|e| fail(format!("{}: {}", path.display(), e)));
+    let entries = History::new(history).entries().unwrap_or_else(|e| fail(e));
+    schedule.add_history(&entries).unwrap_or_else(|e| fail(e));
+    let due: Vec<_> = schedule.due(unix_now()).into_iter()
+        .filter(|card| lang.as_ref().is_none_or(|lang| *lang == card.lang))
+        .take(limit)
+        .collect();
+    if due.is_empty() {
+        println!("nothing to review");
+        return;
+    }
+    // Quizzes aren't lookups of the history
+    config.history = false;
+    let api = api(&config);
+    let options = config.options();
+    for (idx, card) in due.iter().enumerate() {
+        if prompt(&format!("[{}/{}] {} ({}) ", idx + 1, due.len(), card.text, card.lang)).is_none() {
+            break;
+        }
+        match api.lookup_def_with_options(card.lang.as_str(), &card.text, &options) {
+            Ok(defs) => print_defs(&defs),
+            Err(e) => {
+                eprintln!("yadict: {}: {}", card.text, e);
+                continue;
+            },
+        }
+        let grade = loop {
+            match prompt("grade 0-5, q to stop: ") {
+                None => return,
+                Some(ref answer) if answer == "q" => return,
+                Some(answer) => match answer.parse::<u8>() {
+                    Ok(grade) if grade <= 5 => break grade,
+                    _ => (),
+                },
+            }
+        };
+        schedule.answer(&card.lang, &card.text, grade, unix_now()).unwrap_or_else(|e| fail(e));
+    }
+}
+
+// Takes LANG from the front of `args`, falling back to the configured pair.
+// A configured pair makes LANG optional, so the first argument counts
+// as LANG only if it looks like a pair.
+fn take_lang(args: &mut Vec<String>, config: &Config) -> String {
+    let explicit = args.first().is_some_and(|arg| arg.parse::<LangPair>().is_ok());
+    if explicit || config.lang.is_none() {
+        if args.is_empty() {
+            usage();
+        }
+        return args.remove(0);
+    }
+    config.lang.clone().unwrap_or_else(|| usage())
+}
+
+fn main() {
+    let mut args: Vec<String> = env::args().skip(1).collect();
+    let token = take_option(&mut args, &["--token", "-t"]);
+    let format = take_option(&mut args, &["--format", "-f"]);
+    let no_cache = take_flag(&mut args, "--no-cache");
+    let speak_dir = take_option(&mut args, &["--speak"]);
+    let to = take_option(&mut args, &["--to"]);
+    if args.is_empty() || args[0] == "--help" || args[0] == "-h" {
+        usage();
+    }
+    let mut config = Config::load().unwrap_or_else(|e| fail(e));
+    if token.is_some() {
+        config.token = token;
+    }
+    let command = args.remove(0);
+    if command == "history" {
+        return history(config, args);
+    }
+    if command == "review" {
+        return review(config, args);
+    }
+    if command == "stats" {
+        return stats(&config, args);
+    }
+    if command == "dict" {
+        return dict(format.map(|name| parse_format(&name)), args);
+    }
+    if no_cache {
+        config.cache_dir = None;
+    } else if (command == "batch" || command == "annotate" || command == "warm") && config.cache_dir.is_none() {
+        config.cache_dir = FileCache::default_dir();
+    }
+    let cli_format = format.map(|name| parse_format(&name));
+    let config_format = config.format.as_ref().map(|name| parse_format(name));
+    let options = config.options();
+    let speak = speak_dir.map(|dir| pronouncer(&config, dir));
+    let api = api(&config);
+    match command.as_str() {
+        "langs" if args.is_empty() => {
+            for lang in api.get_langs().unwrap_or_else(|e| fail(e)) {
+                println!("{}", lang);
+            }
+        },
+        "ping" if args.is_empty() => match api.validate_key().unwrap_or_else(|e| fail(e)) {
+            KeyStatus::Valid => println!("key is valid"),
+            KeyStatus::Invalid => fail("key is invalid"),
+            KeyStatus::Blocked => fail("key is blocked"),
+        },
+        "lookup" => {
+            let lang = take_lang(&mut args, &config);
+            if args.is_empty() {
+                usage();
+            }
+            let text = args.join(" ");
+            if let Some(ref to) = to {
+                let pair = lang.parse::<LangPair>().unwrap_or_else(|e| fail(e));
+                let targets: Vec<&str> = to.split(',').map(str::trim).filter(|lang| !lang.is_empty()).collect();
+                if targets.is_empty() {
+                    usage();
+                }
+                let multi = api.lookup_multi(pair.source(), &targets, &text);
+                return print_multi(&multi, cli_format.or(config_format).unwrap_or(Format::Text));
+            }
+            let (defs, freshness) = api.lookup_def_with_freshness(lang.as_str(), &text, &options)
+                .unwrap_or_else(|e| fail(e));
+            if let Freshness::Stale(age) = freshness {
+                let age = match age.as_secs() {
+                    secs if secs >= 86400 => format!("{} days", secs / 86400),
+                    secs => format!("{} hours", secs / 3600),
+                };
+                eprintln!("yadict: the service can't be reached, the answer was cached {} ago", age);
+            }
+            if defs.is_empty() {
+                fail(format!("no definitions of '{}'", text));
+            }
+            let sounds = speak.map(|speak| speak(&lang, &defs)).unwrap_or_default();
+            match cli_format.or(config_format).unwrap_or(Format::Text) {
+                Format::Text => print_defs(&defs),
+                Format::Json => println!("{}", export::to_json(&defs)),
+                Format::Csv => print!("{}", export::to_csv(&defs)),
+                Format::Tsv => print!("{}", export::to_tsv(&defs)),
+                Format::Markdown => print!("{}", export::to_markdown(&defs)),
+                Format::Anki => {
+                    let mut out = io::stdout();
+                    write_header(&mut out, Format::Anki).unwrap_or_else(|e| fail(e));
+                    write_word(&mut out, Format::Anki, &defs, &sounds).unwrap_or_else(|e| fail(e));
+                },
+            }
+        },
+        "warm" if !args.is_empty() => {
+            let path = args.remove(0);
+            let lang = take_lang(&mut args, &config);
+            warm(api, &lang, &path, args);
+        },
+        "annotate" => {
+            let lang = take_lang(&mut args, &config);
+            annotate(api, &lang, args);
+        },
+        "repl" => {
+            let lang = take_lang(&mut args, &config);
+            if !args.is_empty() {
+                usage();
+            }
+            // Keeps answers of the session unless they are kept on disk
+            let api = if config.cache_dir.is_none() { api.with_cache(1000) } else { api };
+            repl(api, &lang, options);
+        },
+        "batch" => {
+            let lang = take_lang(&mut args, &config);
+            // Configured text format is for lookups
+            let format = cli_format.or(config_format.filter(|format| *format != Format::Text));
+            batch(api, &lang, &options, format.unwrap_or(Format::Tsv), speak, args);
+        },
+        _ => usage(),
+    }
+}
@@ -0,0 +1,188 @@
+// The crate spells out field names and `'static` of constants.
+#![allow(clippy::redundant_field_names, clippy::redundant_static_lifetimes)]
+
+extern crate hyper;
+#[macro_use]
+extern crate serde_json;
+extern crate url;
+extern crate yadict;
+
+use std::env;
+use std::process;
+use hyper::header::ContentType;
+use hyper::server::{Request, Response, Server};
+use hyper::status::StatusCode;
+use hyper::uri::RequestUri;
+use serde_json::Value;
+use url::Url;
+use yadict::{Api, LookupFlags, LookupOptions, RateLimit, RequestError};
+use yadict::cache::FileCache;
+
+const TOKEN_VAR: &'static str = "YANDEX_DICTIONARY_TOKEN";
+
+const DEFAULT_LISTEN: &'static str = "127.0.0.1:8700";
+
+const USAGE: &'static str = "Usage:
+    yadictd [--token TOKEN[,TOKEN...]] [--listen ADDR] [--threads N]
+            [--rate N] [--budget N] [--no-cache]
+
+Serves the dictionary over local HTTP:
+    GET /lookup?lang=en-ru&text=rust[&ui=ru][&flags=4]
+    GET /getLangs
+
+Answers and errors have the format of the service, so clients may set
+the daemon as their base URL, their keys are ignored. Tokens are used in
+turn when the service rejects one. Answers are cached in ~/.cache/yadict,
+requests are limited to --rate per second (5 by default) and to --budget
+per day (unlimited by default), the count is kept next to the cache.
+The daemon listens on 127.0.0.1:8700 by default.
+
+Tokens are taken from YANDEX_DICTIONARY_TOKEN environment variable if --token is not set.";
+
+fn usage() -> ! {
+    eprintln!("{}", USAGE);
+    process::exit(2);
+}
+
+fn fail<E: ::std::fmt::Display>(e: E) -> ! {
+    eprintln!("yadictd: {}", e);
+    process::exit(1);
+}
+
+// Removes option with its value from `args`.
+fn take_option(args: &mut Vec<String>, names: &[&str]) -> Option<String> {
+    match args.iter().position(|arg| names.contains(&arg.as_str())) {
+        Some(idx) => {
+            if idx + 1 >= args.len() {
+                usage();
+            }
+            let value = args.remove(idx + 1);
+            args.remove(idx);
+            Some(value)
+        },
+        None => None,
+    }
+}
+
+fn take_flag(args: &mut Vec<String>, name: &str) -> bool {
+    match args.iter().position(|arg| arg == name) {
+        Some(idx) => {
+            args.remove(idx);
+            true
+        },
+        None => false,
+    }
+}
+
+fn parse_number<T: ::std::str::FromStr>(value: &str) -> T {
+    value.parse().unwrap_or_else(|_| usage())
+}
+
+// Status and body of the service for the error. Errors of the service
+// keep their codes, the others have none and are seen by clients as
+// HTTP errors, so transient ones are retried.
+fn error_response(e: &RequestError) -> (u16, Value) {
+    let code = match *e.root() {
+        RequestError::KeyInvalid => Some(401),
+        RequestError::KeyBlocked => Some(402),
+        RequestError::DailyLimitExceeded => Some(403),
+        RequestError::TextTooLong => Some(413),
+        RequestError::LangNotSupported => Some(501),
+        RequestError::UnknownError(code) => Some(code),
+        _ => None,
+    };
+    match code {
+        Some(code) => {
+            let status = if (400..600).contains(&code) { code as u16 } else { 400 };
+            (status, json!({"code": code, "message": e.to_string()}))
+        },
+        None => {
+            let status = match *e.root() {
+                RequestError::RateLimited => 429,
+                RequestError::Timeout => 504,
+                RequestError::CircuitOpen => 503,
+                RequestError::CallbackPanicked(_) => 500,
+                _ => 502,
+            };
+            (status, json!({"message": e.to_string()}))
+        },
+    }
+}
+
+fn handle(api: &Api, uri: &RequestUri) -> (u16, Value) {
+    let url = match *uri {
+        RequestUri::AbsolutePath(ref path) => Url::parse(&format!("http://localhost{}", path)).ok(),
+        _ => None,
+    };
+    let url = match url {
+        Some(url) => url,
+        None => return (400, json!({"message": "bad request"})),
+    };
+    let param = |name: &str| url.query_pairs().find(|(key, _)| key == name).map(|(_, value)| value.into_owned());
+    let result = match url.path().trim_end_matches('/') {
+        "/getLangs" => api.get_langs().map(|langs| json!(langs)),
+        "/lookup" => {
+            let (lang, text) = match (param("lang"), param("text")) {
+                (Some(lang), Some(text)) => (lang, text),
+                _ => return (400, json!({"message": "lang and text are required"})),
+            };
+            let flags = param("flags").and_then(|flags| flags.parse().ok()).unwrap_or(0);
+            let options = LookupOptions {
+                flags: LookupFlags::from_bits_truncate(flags),
+                ui: param("ui"),
+            };
+            api.lookup_with_options(lang.as_str(), &text, &options)
+        },
+        _ => return (404, json!({"message": "not found"})),
+    };
+    match result {
+        Ok(json) => (200, json),
+        Err(e) => error_response(&e),
+    }
+}
+
+fn main() {
+    let mut args: Vec<String> = env::args().skip(1).collect();
+    if args.iter().any(|arg| arg == "--help" || arg == "-h") {
+        usage();
+    }
+    let tokens = take_option(&mut args, &["--token", "-t"])
+        .or_else(|| env::var(TOKEN_VAR).ok())
+        .unwrap_or_else(|| fail(format!("no token, set --token or {}", TOKEN_VAR)));
+    let listen = take_option(&mut args, &["--listen", "-l"]).unwrap_or(DEFAULT_LISTEN.to_owned());
+    let threads = take_option(&mut args, &["--threads"]).map(|n| parse_number(&n)).unwrap_or(4);
+    let rate = take_option(&mut args, &["--rate"]).map(|rate| parse_number::<f64>(&rate)).unwrap_or(5.0);
+    let budget = take_option(&mut args, &["--budget"]).map(|budget| parse_number::<u32>(&budget));
+    let no_cache = take_flag(&mut args, "--no-cache");
+    if !args.is_empty() || threads == 0 || rate <= 0.0 {
+        usage();
+    }
+
+    let tokens: Vec<&str> = tokens.split(',').map(str::trim).filter(|token| !token.is_empty()).collect();
+    let mut api = Api::from_tokens(&tokens).unwrap_or_else(|e| fail(e))
+        .with_rate_limit(RateLimit {
+            per_second: Some(rate),
+            ..RateLimit::default()
+        });
+    let dir = FileCache::default_dir();
+    if !no_cache {
+        if let Some(ref dir) = dir {
+            api = api.with_cache_backend(FileCache::new(dir));
+        }
+    }
+    if let Some(budget) = budget {
+        api = match dir {
+            Some(ref dir) => api.with_quota_file(dir.join("quota.json"), budget),
+            None => api.with_daily_budget(budget),
+        };
+    }
+
+    let server = Server::http(listen.as_str()).unwrap_or_else(|e| fail(format!("{}: {}", listen, e)));
+    let _listening = server.handle_threads(move |req: Request, mut res: Response| {
+        let (status, json) = handle(&api, &req.uri);
+        *res.status_mut() = StatusCode::from_u16(status);
+        res.headers_mut().set(ContentType::json());
+        let _ = res.send(json.to_string().as_bytes());
+    }, threads).unwrap_or_else(|e| fail(e));
+    eprintln!("yadictd: listening on {}", listen);
+}
@@ -0,0 +1,215 @@
+// Borrowing counterparts of `Def` and `Word` for callers which only look
+// at an answer while its body is around, e.g. to pick a few fields of many
+// answers. Strings without JSON escapes point into the body, the others
+// are unescaped copies. `into_owned` makes the usual types of them.
+
+use std::borrow::Cow;
+use serde::{Deserialize, Deserializer};
+use serde_json::{self, error::Category};
+use super::{Def, Example, PartOfSpeech, RawResponse, RequestError, Word, response_to_json};
+
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+pub struct DefRef<'a> {
+    #[serde(flatten, borrow)]
+    pub word: WordRef<'a>,
+    #[serde(rename = "tr", borrow)]
+    pub trans: Vec<WordRef<'a>>,
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+pub struct WordRef<'a> {
+    #[serde(borrow)]
+    pub text: Cow<'a, str>,
+    // Name of the part of speech as the service gives it, see `part_of_speech`.
+    #[serde(default, borrow, deserialize_with = "optional")]
+    pub pos: Option<Cow<'a, str>>,
+    // Trimmed, blank transcriptions are missing ones, as in `Word`.
+    #[serde(default, borrow, deserialize_with = "transcription")]
+    pub ts: Option<Cow<'a, str>>,
+    #[serde(default, borrow, deserialize_with = "optional")]
+    pub gen: Option<Cow<'a, str>>,
+    #[serde(default, borrow, deserialize_with = "optional")]
+    pub asp: Option<Cow<'a, str>>,
+    #[serde(default, borrow, deserialize_with = "optional")]
+    pub num: Option<Cow<'a, str>>,
+    #[serde(default)]
+    pub fr: Option<u32>,
+    #[serde(default, borrow)]
+    pub syn: Vec<WordRef<'a>>,
+    #[serde(default, borrow)]
+    pub mean: Vec<WordRef<'a>>,
+    #[serde(default, borrow)]
+    pub ex: Vec<ExampleRef<'a>>,
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+pub struct ExampleRef<'a> {
+    #[serde(borrow)]
+    pub text: Cow<'a, str>,
+    #[serde(default, borrow)]
+    pub tr: Vec<TextRef<'a>>,
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+pub struct TextRef<'a> {
+    #[serde(borrow)]
+    pub text: Cow<'a, str>,
+}
+
+#[derive(Deserialize)]
+struct LookupResponseRef<'a> {
+    #[serde(borrow)]
+    def: Vec<DefRef<'a>>,
+}
+
+// Serde borrows `Cow<str>` fields only, not ones wrapped in `Option`.
+#[derive(Deserialize)]
+struct Borrowed<'a>(#[serde(borrow)] Cow<'a, str>);
+
+fn optional<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<Cow<'de, str>>, D::Error> {
+    let value: Option<Borrowed<'de>> = Option::deserialize(deserializer)?;
+    Ok(value.map(|value| value.0))
+}
+
+fn transcription<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<Cow<'de, str>>, D::Error> {
+    let ts = optional(deserializer)?;
+    Ok(ts.and_then(|ts| {
+        let ts = match ts {
+            Cow::Borrowed(ts) => Cow::Borrowed(ts.trim()),
+            Cow::Owned(ts) => Cow::Owned(ts.trim().to_owned()),
+        };
+        if ts.is_empty() { None } else { Some(ts) }
+    }))
+}
+
+fn owned(text: &Option<Cow<str>>) -> Option<String> {
+    text.as_ref().map(|text| text.clone().into_owned())
+}
+
+impl<'a> DefRef<'a> {
+    pub fn into_owned(self) -> Def {
+        Def {
+            word: self.word.into_owned(),
+            trans: self.trans.into_iter().map(WordRef::into_owned).collect(),
+        }
+    }
+}
+
+impl<'a> WordRef<'a> {
+    pub fn part_of_speech(&self) -> Option<PartOfSpeech> {
+        self.pos.as_ref().map(|pos| PartOfSpeech::from(pos.as_ref()))
+    }
+
+    pub fn into_owned(self) -> Word {
+        Word {
+            pos: self.part_of_speech(),
+            ts: owned(&self.ts),
+            gen: owned(&self.gen),
+            asp: owned(&self.asp),
+            num: owned(&self.num),
+            fr: self.fr,
+            text: self.text.into_owned(),
+            syn: self.syn.into_iter().map(WordRef::into_owned).collect(),
+            mean: self.mean.into_iter().map(WordRef::into_owned).collect(),
+            ex: self.ex.into_iter().map(ExampleRef::into_owned).collect(),
+        }
+    }
+}
+
+impl<'a> ExampleRef<'a> {
+    pub fn into_owned(self) -> Example {
+        Example {
+            text: self.text.into_owned(),
+            tr: self.tr.into_iter().map(|tr| tr.text.into_owned()).collect(),
+        }
+    }
+}
+
+// Parses a body of `lookup` answer without copying its strings where
+// possible. Valid JSON of unexpected shape is `InvalidDataFormat`.
+pub fn parse_defs<'a>(body: &'a str) -> Result<Vec<DefRef<'a>>, RequestError> {
+    match serde_json::from_str::<LookupResponseRef>(body) {
+        Ok(response) => Ok(response.def),
+        Err(ref e) if e.classify() == Category::Data => Err(RequestError::InvalidDataFormat),
+        Err(e) => Err(RequestError::from(e)),
+    }
+}
+
+impl RawResponse {
+    // Definitions borrowed from the body, errors of the service are
+    // reported the same way as by `Api::lookup_def`.
+    pub fn defs<'a>(&'a self) -> Result<Vec<DefRef<'a>>, RequestError> {
+        if self.status != 200 {
+            return Err(match response_to_json(self.status, &self.body) {
+                Err(e) => e,
+                Ok(_) => RequestError::HttpStatus(self.status),
+            });
+        }
+        parse_defs(&self.body)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use std::borrow::Cow;
+    use serde_json;
+    use super::parse_defs;
+    use super::super::{Api, LookupOptions, RequestError, json_to_defs};
+    use testing::{MockTransport, FIXTURE_KEY_INVALID, FIXTURE_LOOKUP_RUST};
+
+    // Takes the `Cow` itself, it's the variant which is checked.
+    #[allow(clippy::ptr_arg)]
+    fn is_borrowed(text: &Cow<str>) -> bool {
+        match *text {
+            Cow::Borrowed(_) => true,
+            Cow::Owned(_) => false,
+        }
+    }
+
+    #[test]
+    fn check_borrowed_defs() {
+        let body = r#"{"head": {}, "def": [{"text": "rust", "pos": "noun", "ts": "r\u028cst",
+            "tr": [{"text": "ржавчина", "pos": "noun", "fr": 10, "ex": [{"text": "rust stain", "tr": [{"text": "пятно"}]}]}]}]}"#;
+        let defs = parse_defs(body).unwrap();
+        assert!(is_borrowed(&defs[0].word.text));
+        assert!(is_borrowed(defs[0].word.pos.as_ref().unwrap()));
+        // Escaped strings can't point into the body
+        assert!(!is_borrowed(defs[0].word.ts.as_ref().unwrap()));
+        assert!(is_borrowed(&defs[0].trans[0].text));
+        assert!(is_borrowed(&defs[0].trans[0].ex[0].tr[0].text));
+        assert_eq!(defs[0].trans[0].fr, Some(10));
+
+        let defs = parse_defs(r#"{"def": [{"text": "rust", "ts": " rʌst ", "tr": [{"text": "ржа", "ts": " "}]}]}"#).unwrap();
+        assert_eq!(defs[0].word.ts, Some(Cow::Borrowed("rʌst")));
+        assert!(is_borrowed(defs[0].word.ts.as_ref().unwrap()));
+        assert_eq!(defs[0].trans[0].ts, None);
+
+        let owned: Vec<_> = parse_defs(FIXTURE_LOOKUP_RUST).unwrap().into_iter().map(|def| def.into_owned()).collect();
+        assert_eq!(owned, json_to_defs(serde_json::from_str(FIXTURE_LOOKUP_RUST).unwrap()).unwrap());
+
+        match parse_defs(r#"{"head": {}}"#) {
+            Err(RequestError::InvalidDataFormat) => (),
+            other => panic!("unexpected result: {:?}", other),
+        }
+        match parse_defs("{") {
+            Err(RequestError::ParseError(_)) => (),
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn check_raw_defs() {
+        let transport = MockTransport::new()
+            .on("text=rust", 200, FIXTURE_LOOKUP_RUST)
+            .on("/lookup?", 403, FIXTURE_KEY_INVALID);
+        let api = Api::from_token("token").unwrap().with_transport(transport);
+        let raw = api.lookup_raw("en-ru", "rust", &LookupOptions::default()).unwrap();
+        assert_eq!(raw.defs().unwrap().len(), 3);
+        let raw = api.lookup_raw("en-ru", "blocked", &LookupOptions::default()).unwrap();
+        match raw.defs() {
+            Err(RequestError::KeyInvalid) => (),
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+}
@@ -0,0 +1,474 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::env;
+use std::fs::{self, File};
+use std::hash::Hash;
+use std::io::{Error as IOError, ErrorKind, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant, SystemTime};
+use serde_json::{self, Value};
+use export::COLUMNS;
+use super::{Api, LangPair, RequestError};
+
+// Identifies a `lookup` answer. Options which change the answer are
+// part of the key.
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct CacheKey {
+    pub lang: String,
+    pub text: String,
+    pub flags: u32,
+    pub ui: Option<String>,
+}
+
+// Storage of `lookup` answers used by `Api::with_cache_backend`.
+pub trait CacheBackend: Send {
+    fn get(&mut self, key: &CacheKey) -> Option<Value>;
+    fn put(&mut self, key: CacheKey, value: Value);
+
+    // Time since the entry was put, `None` if unknown. Entries of unknown
+    // age never get old for `CachePolicy`.
+    fn age(&mut self, _key: &CacheKey) -> Option<Duration> {
+        None
+    }
+
+    // Drops all entries. `Api` calls it when a method of the backend
+    // panicked, as it may be left half-updated. Does nothing by default.
+    fn clear(&mut self) {}
+}
+
+// How old cached answers are used, see `Api::with_cache_policy`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[derive(Default)]
+pub enum CachePolicy {
+    // Entries are used however old they are.
+    #[default]
+    Forever,
+    // Entries older than the age are looked up again.
+    MaxAge(Duration),
+    // Same as `MaxAge`, but when the service can't be reached the old
+    // entry is used instead of an error, see `Freshness::Stale`.
+    StaleIfError(Duration),
+}
+
+
+impl CachePolicy {
+    pub fn max_age(&self) -> Option<Duration> {
+        match *self {
+            CachePolicy::Forever => None,
+            CachePolicy::MaxAge(age) | CachePolicy::StaleIfError(age) => Some(age),
+        }
+    }
+}
+
+// Whether an answer is current, see `Api::lookup_def_with_freshness`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Freshness {
+    Fresh,
+    // An old cached answer used as the service failed, with its age.
+    Stale(Duration),
+}
+
+impl Freshness {
+    pub fn is_stale(&self) -> bool {
+        *self != Freshness::Fresh
+    }
+}
+
+// Map with limited capacity which evicts the least recently used entry.
+pub struct LruCache<K, V> {
+    capacity: usize,
+    tick: u64,
+    entries: HashMap<K, (V, u64, Instant)>,
+    // Last access tick to key, the first item is the eviction candidate.
+    order: BTreeMap<u64, K>,
+}
+
+impl<K: Hash + Eq + Clone, V: Clone> LruCache<K, V> {
+    pub fn new(capacity: usize) -> LruCache<K, V> {
+        LruCache {
+            capacity: capacity,
+            tick: 0,
+            entries: HashMap::new(),
+            order: BTreeMap::new(),
+        }
+    }
+
+    fn next_tick(&mut self) -> u64 {
+        self.tick += 1;
+        self.tick
+    }
+
+    pub fn get(&mut self, key: &K) -> Option<V> {
+        let tick = self.next_tick();
+        match self.entries.get_mut(key) {
+            Some(&mut (ref value, ref mut used, _)) => {
+                let key = self.order.remove(used).unwrap();
+                self.order.insert(tick, key);
+                *used = tick;
+                Some(value.clone())
+            },
+            None => None,
+        }
+    }
+
+    pub fn put(&mut self, key: K, value: V) {
+        if self.capacity == 0 {
+            return;
+        }
+        let tick = self.next_tick();
+        if let Some((_, used, _)) = self.entries.remove(&key) {
+            self.order.remove(&used);
+        } else if self.entries.len() == self.capacity {
+            let oldest = self.order.keys().next().cloned();
+            if let Some(oldest) = oldest {
+                let key = self.order.remove(&oldest).unwrap();
+                self.entries.remove(&key);
+            }
+        }
+        self.order.insert(tick, key.clone());
+        self.entries.insert(key, (value, tick, Instant::now()));
+    }
+
+    // Time since the entry was put, it isn't an access.
+    pub fn age(&self, key: &K) -> Option<Duration> {
+        self.entries.get(key).map(|&(_, _, put)| put.elapsed())
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.order.clear();
+    }
+}
+
+impl CacheBackend for LruCache<CacheKey, Value> {
+    fn get(&mut self, key: &CacheKey) -> Option<Value> {
+        LruCache::get(self, key)
+    }
+
+    fn put(&mut self, key: CacheKey, value: Value) {
+        LruCache::put(self, key, value)
+    }
+
+    fn age(&mut self, key: &CacheKey) -> Option<Duration> {
+        LruCache::age(self, key)
+    }
+
+    fn clear(&mut self) {
+        LruCache::clear(self)
+    }
+}
+
+// Numbers temporary files of `write_file_atomically` within the process.
+static TMP_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+// Writes to a temporary file first and renames it over the target, so
+// concurrent processes and threads never see a half-written file. Every
+// call has its own temporary file, named by the process and a counter.
+pub fn write_file_atomically(path: &Path, data: &[u8]) -> Result<(), IOError> {
+    if let Some(dir) = path.parent() {
+        if !dir.as_os_str().is_empty() {
+            fs::create_dir_all(dir)?;
+        }
+    }
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(format!(".{}.{}.tmp", process::id(), TMP_COUNTER.fetch_add(1, Ordering::SeqCst)));
+    let written = File::create(&tmp)
+        .and_then(|mut file| file.write_all(data))
+        .and_then(|_| fs::rename(&tmp, path));
+    if written.is_err() {
+        let _ = fs::remove_file(&tmp);
+    }
+    written
+}
+
+// FNV-1a, unlike std hashers it's guaranteed to be stable between builds.
+pub(crate) fn stable_hash(data: &[u8]) -> u64 {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for byte in data {
+        hash ^= *byte as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    hash
+}
+
+#[derive(Serialize, Deserialize)]
+struct FileEntry {
+    key: CacheKey,
+    value: Value,
+}
+
+// Keeps every answer in a separate JSON file of the directory.
+// Unreadable files are treated as missing entries.
+pub struct FileCache {
+    dir: PathBuf,
+}
+
+impl FileCache {
+    pub fn new<P: AsRef<Path>>(dir: P) -> FileCache {
+        FileCache {
+            dir: dir.as_ref().to_owned(),
+        }
+    }
+
+    // `$XDG_CACHE_HOME/yadict` or `~/.cache/yadict`.
+    pub fn default_dir() -> Option<PathBuf> {
+        match env::var_os("XDG_CACHE_HOME") {
+            Some(ref dir) if !dir.is_empty() => Some(PathBuf::from(dir).join("yadict")),
+            _ => env::var_os("HOME").map(|home| PathBuf::from(home).join(".cache").join("yadict")),
+        }
+    }
+
+    fn entry_path(&self, key: &CacheKey) -> PathBuf {
+        let data = serde_json::to_vec(key).unwrap_or_default();
+        self.dir.join(format!("{:016x}.json", stable_hash(&data)))
+    }
+}
+
+impl CacheBackend for FileCache {
+    fn get(&mut self, key: &CacheKey) -> Option<Value> {
+        let file = match File::open(self.entry_path(key)) {
+            Ok(file) => file,
+            Err(_) => return None,
+        };
+        match serde_json::from_reader::<_, FileEntry>(file) {
+            // Different keys may share a file name
+            Ok(ref entry) if entry.key == *key => Some(entry.value.clone()),
+            _ => None,
+        }
+    }
+
+    fn put(&mut self, key: CacheKey, value: Value) {
+        let path = self.entry_path(&key);
+        let entry = FileEntry {
+            key: key,
+            value: value,
+        };
+        // Cache is best effort, failed writes only cost a request next time
+        if let Ok(data) = serde_json::to_vec(&entry) {
+            let _ = write_file_atomically(&path, &data);
+        }
+    }
+
+    // By modification time of the file, which `put` replaces.
+    fn age(&mut self, key: &CacheKey) -> Option<Duration> {
+        fs::metadata(self.entry_path(key)).and_then(|meta| meta.modified()).ok()
+            .map(|modified| SystemTime::now().duration_since(modified).unwrap_or_default())
+    }
+}
+
+// First field of a TSV or CSV row, quotes of CSV are removed.
+fn first_field(line: &str) -> String {
+    if !line.starts_with('"') {
+        return line.split(['\t', ',']).next().unwrap_or("").to_owned();
+    }
+    let mut field = String::new();
+    let mut chars = line[1..].chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if chars.peek() == Some(&'"') => {
+                chars.next();
+                field.push('"');
+            },
+            '"' => break,
+            c => field.push(c),
+        }
+    }
+    field
+}
+
+// Words of the first column of a plain list, TSV or CSV, e.g. one written
+// by `export::to_csv`. Blank lines, `#` comments, header rows of `export`
+// and repeats are skipped.
+pub fn read_word_list(text: &str) -> Vec<String> {
+    let mut words: Vec<String> = Vec::new();
+    let mut seen: HashSet<String> = HashSet::new();
+    for line in text.lines() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') || line == COLUMNS.join(",") || line == COLUMNS.join("\t") {
+            continue;
+        }
+        let word = first_field(line).trim().to_owned();
+        if !word.is_empty() && seen.insert(word.clone()) {
+            words.push(word);
+        }
+    }
+    words
+}
+
+#[derive(Debug)]
+pub struct Warmed {
+    // Distinct words of the list, cached or failed.
+    pub words: usize,
+    pub errors: Vec<(String, RequestError)>,
+}
+
+// Looks up every word of the list at `path` into the cache of `api`, so
+// later lookups need no requests and work offline with `FileCache`.
+// Cached words cost nothing, others are throttled by the rate limit and
+// the budget of `api`. Fails if `api` has no cache.
+pub fn warm_from_file<L: Into<LangPair>, P: AsRef<Path>>(api: &Api, lang: L, path: P) -> Result<Warmed, IOError> {
+    if api.cache.is_none() {
+        return Err(IOError::new(ErrorKind::InvalidInput, "no cache to warm, set one with `with_cache_backend`"));
+    }
+    let words = read_word_list(&fs::read_to_string(path)?);
+    let words: Vec<&str> = words.iter().map(String::as_str).collect();
+    let errors = api.lookup_batch(lang, &words).into_iter()
+        .filter_map(|(word, result)| result.err().map(|e| (word, e)))
+        .collect();
+    Ok(Warmed {
+        words: words.len(),
+        errors: errors,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+
+    use std::env;
+    use std::fs;
+    use serde_json;
+    use std::thread;
+    use std::time::Duration;
+    use super::{LruCache, CacheBackend, CacheKey, CachePolicy, FileCache, Freshness, read_word_list, warm_from_file,
+                write_file_atomically};
+    use super::super::Api;
+    use testing::MockTransport;
+
+    #[test]
+    fn check_lru_eviction() {
+        let mut cache = LruCache::new(2);
+        cache.put("a", 1);
+        cache.put("b", 2);
+        assert_eq!(cache.get(&"a"), Some(1));
+        cache.put("c", 3);
+        assert_eq!(cache.len(), 2);
+        assert_eq!(cache.get(&"b"), None);
+        assert_eq!(cache.get(&"a"), Some(1));
+        assert_eq!(cache.get(&"c"), Some(3));
+        cache.put("a", 10);
+        cache.put("d", 4);
+        assert_eq!(cache.get(&"c"), None);
+        assert_eq!(cache.get(&"a"), Some(10));
+    }
+
+    #[test]
+    fn check_lru_zero_capacity() {
+        let mut cache = LruCache::new(0);
+        cache.put("a", 1);
+        assert_eq!(cache.get(&"a"), None);
+    }
+
+    fn key(text: &str) -> CacheKey {
+        CacheKey {
+            lang: "en-ru".to_owned(),
+            text: text.to_owned(),
+            flags: 0,
+            ui: None,
+        }
+    }
+
+    #[test]
+    fn check_file_cache() {
+        let dir = env::temp_dir().join("yadict-test-file-cache");
+        let _ = fs::remove_dir_all(&dir);
+        let mut cache = FileCache::new(&dir);
+        assert!(cache.get(&key("rust")).is_none());
+        cache.put(key("rust"), serde_json::from_str(r#"{"def": []}"#).unwrap());
+        // Another instance reads what the first one stored
+        let mut cache = FileCache::new(&dir);
+        assert_eq!(cache.get(&key("rust")).unwrap()["def"], serde_json::json!([]));
+        assert!(cache.get(&key("iron")).is_none());
+        fs::write(cache.entry_path(&key("rust")), b"{").unwrap();
+        assert!(cache.get(&key("rust")).is_none());
+    }
+
+    #[test]
+    fn check_concurrent_writes() {
+        let dir = env::temp_dir().join("yadict-test-atomic-writes");
+        let _ = fs::remove_dir_all(&dir);
+        let path = dir.join("data.json");
+        let writers: Vec<_> = (0..8).map(|idx| {
+            let path = path.clone();
+            thread::spawn(move || {
+                for _ in 0..20 {
+                    write_file_atomically(&path, format!("writer {}", idx).as_bytes()).unwrap();
+                }
+            })
+        }).collect();
+        for writer in writers {
+            writer.join().unwrap();
+        }
+        assert!(fs::read_to_string(&path).unwrap().starts_with("writer "));
+        // No temporary files are left
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn check_read_word_list() {
+        let text = "word,pos,transcription,translation\nrust,noun,rʌst,ржавчина\n\"iron, cast\",noun,,чугун\n\
+                    # comment\n\n  rust\tnoun\nsay \"\"hi\"\"\n\"a \"\"b\"\"\"\n";
+        assert_eq!(read_word_list(text), vec!["rust", "iron, cast", "say \"\"hi\"\"", "a \"b\""]);
+    }
+
+    #[test]
+    fn check_warm_from_file() {
+        let dir = env::temp_dir().join("yadict-test-warm");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("words.txt");
+        fs::write(&path, "rust\nflaky\nrust\n").unwrap();
+        let transport = MockTransport::new()
+            .on("text=flaky", 503, "")
+            .on("text=rust", 200, ::testing::FIXTURE_LOOKUP_RUST);
+        let api = Api::from_token("token").unwrap().with_transport(transport.clone());
+        assert!(warm_from_file(&api, "en-ru", &path).is_err());
+        let api = api.with_cache_backend(FileCache::new(dir.join("cache")));
+        let warmed = warm_from_file(&api, "en-ru", &path).unwrap();
+        assert_eq!(warmed.words, 2);
+        assert_eq!(warmed.errors.len(), 1);
+        assert_eq!(warmed.errors[0].0, "flaky");
+        // Warmed words are answered offline
+        let offline = Api::from_token("token").unwrap()
+            .with_transport(MockTransport::new())
+            .with_cache_backend(FileCache::new(dir.join("cache")));
+        assert_eq!(offline.lookup_def("en-ru", "rust").unwrap().len(), 3);
+        assert!(warm_from_file(&api, "en-ru", dir.join("missing.txt")).is_err());
+    }
+
+    #[test]
+    fn check_stale_if_error() {
+        let options = Default::default();
+        let api = Api::from_token("token").unwrap()
+            .with_transport(MockTransport::with_fixtures())
+            .with_cache(10)
+            .with_cache_policy(CachePolicy::StaleIfError(Duration::from_millis(1)));
+        let (defs, freshness) = api.lookup_def_with_freshness("en-ru", "rust", &options).unwrap();
+        assert_eq!((defs.len(), freshness), (3, Freshness::Fresh));
+        thread::sleep(Duration::from_millis(5));
+        // Clones share the cache
+        let offline = api.clone().with_transport(MockTransport::new().on("/lookup?", 503, ""));
+        let (defs, freshness) = offline.lookup_def_with_freshness("en-ru", "rust", &options).unwrap();
+        assert_eq!(defs.len(), 3);
+        match freshness {
+            Freshness::Stale(age) => assert!(age >= Duration::from_millis(5)),
+            Freshness::Fresh => panic!("cached answer isn't stale"),
+        }
+        assert!(offline.lookup_def("en-ru", "iron").is_err());
+        let strict = offline.clone().with_cache_policy(CachePolicy::MaxAge(Duration::from_millis(1)));
+        assert!(strict.lookup_def("en-ru", "rust").is_err());
+        // Errors of the request itself aren't hidden
+        let refused = api.clone().with_transport(MockTransport::new().on("/lookup?", 400, ""));
+        assert!(refused.lookup_def("en-ru", "rust").is_err());
+        let (_, freshness) = api.lookup_def_with_freshness("en-ru", "rust", &options).unwrap();
+        assert_eq!(freshness, Freshness::Fresh);
+    }
+}
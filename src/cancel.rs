@@ -0,0 +1,103 @@
+use std::cmp;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::Duration;
+use super::RequestError;
+
+// How often waits for a response, a retry or the rate limit look at the flag.
+pub const CANCEL_POLL: Duration = Duration::from_millis(50);
+
+// Stops requests from another thread, e.g. on Ctrl+C. Calls made with the
+// token fail with `RequestError::Cancelled` before the next request or while
+// waiting for one. Clones share the flag.
+#[derive(Clone, Debug, Default)]
+pub struct CancellationToken {
+    cancelled: Arc<AtomicBool>,
+}
+
+impl CancellationToken {
+    pub fn new() -> CancellationToken {
+        CancellationToken::default()
+    }
+
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::SeqCst)
+    }
+
+    pub fn check(&self) -> Result<(), RequestError> {
+        if self.is_cancelled() {
+            Err(RequestError::Cancelled)
+        } else {
+            Ok(())
+        }
+    }
+}
+
+// Sleeps for `duration` or until `cancel` is cancelled.
+pub fn pause(duration: Duration, cancel: Option<&CancellationToken>) -> Result<(), RequestError> {
+    let cancel = match cancel {
+        Some(cancel) => cancel,
+        None => {
+            thread::sleep(duration);
+            return Ok(());
+        },
+    };
+    let mut left = duration;
+    while left > Duration::from_secs(0) {
+        cancel.check()?;
+        let step = cmp::min(left, CANCEL_POLL);
+        thread::sleep(step);
+        left -= step;
+    }
+    cancel.check()
+}
+
+#[cfg(test)]
+mod tests {
+
+    use std::thread;
+    use std::time::{Duration, Instant};
+    use super::{CancellationToken, pause};
+    use super::super::{Api, RequestError};
+    use testing::MockTransport;
+
+    #[test]
+    fn check_pause() {
+        let cancel = CancellationToken::new();
+        assert!(pause(Duration::from_millis(10), Some(&cancel)).is_ok());
+        let canceller = cancel.clone();
+        thread::spawn(move || {
+            thread::sleep(Duration::from_millis(50));
+            canceller.cancel();
+        });
+        let started = Instant::now();
+        match pause(Duration::from_secs(10), Some(&cancel)) {
+            Err(RequestError::Cancelled) => (),
+            other => panic!("unexpected result: {:?}", other),
+        }
+        assert!(started.elapsed() < Duration::from_secs(5));
+    }
+
+    #[test]
+    fn check_cancelled_batch() {
+        let transport = MockTransport::with_fixtures();
+        let api = Api::from_token("token").unwrap().with_transport(transport.clone());
+        let cancel = CancellationToken::new();
+        assert_eq!(api.lookup_request("en-ru", "rust").cancel(&cancel).send_def().unwrap().len(), 3);
+        cancel.cancel();
+        match api.lookup_request("en-ru", "rust").cancel(&cancel).send_def() {
+            Err(RequestError::Cancelled) => (),
+            other => panic!("unexpected result: {:?}", other.map(|defs| defs.len())),
+        }
+        let results = api.lookup_batch_cancellable("en-ru", &["rust", "iron"], &cancel);
+        assert!(results.iter().all(|(_, result)| {
+            matches!(result.as_ref().map_err(RequestError::root), Err(&RequestError::Cancelled))
+        }));
+        assert_eq!(transport.requests().len(), 1);
+    }
+}
@@ -0,0 +1,190 @@
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::sync::{Arc, Condvar, Mutex, PoisonError};
+use unwind;
+
+struct State<V> {
+    done: bool,
+    // Calls waiting for the flight, the answer is copied only for them.
+    waiting: usize,
+    value: Option<V>,
+}
+
+struct Flight<V> {
+    state: Mutex<State<V>>,
+    landed: Condvar,
+}
+
+// Single-flight of requests: concurrent calls with the same key wait for
+// the first one instead of repeating its request. Errors can't be shared,
+// so waiting calls make their own requests when the first one fails.
+pub struct Coalescer<K, V> {
+    flights: Mutex<HashMap<K, Arc<Flight<V>>>>,
+}
+
+// Lands the flight even if the request panics, so nobody waits forever.
+struct Landing<'a, K: 'a + Hash + Eq, V: 'a> {
+    coalescer: &'a Coalescer<K, V>,
+    key: &'a K,
+    flight: Arc<Flight<V>>,
+    departed: bool,
+    value: Option<V>,
+}
+
+impl<'a, K: Hash + Eq, V> Landing<'a, K, V> {
+    // Closes the flight for new calls and tells if anybody waits for it.
+    // Once removed, the key may belong to the next flight already.
+    fn depart(&mut self) -> bool {
+        if !self.departed {
+            unwind::lock(&self.coalescer.flights).remove(self.key);
+            self.departed = true;
+        }
+        unwind::lock(&self.flight.state).waiting > 0
+    }
+}
+
+impl<'a, K: Hash + Eq, V> Drop for Landing<'a, K, V> {
+    fn drop(&mut self) {
+        self.depart();
+        let mut state = unwind::lock(&self.flight.state);
+        state.done = true;
+        state.value = self.value.take();
+        self.flight.landed.notify_all();
+    }
+}
+
+impl<K: Hash + Eq + Clone, V: Clone> Coalescer<K, V> {
+    pub fn new() -> Coalescer<K, V> {
+        Coalescer {
+            flights: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn run<E, F: Fn() -> Result<V, E>>(&self, key: &K, request: F) -> Result<V, E> {
+        let (flight, leader) = {
+            let mut flights = unwind::lock(&self.flights);
+            match flights.get(key) {
+                Some(flight) => {
+                    // Counted under the lock of flights, so `depart` sees it
+                    unwind::lock(&flight.state).waiting += 1;
+                    (flight.clone(), false)
+                },
+                None => {
+                    let flight = Arc::new(Flight {
+                        state: Mutex::new(State {
+                            done: false,
+                            waiting: 0,
+                            value: None,
+                        }),
+                        landed: Condvar::new(),
+                    });
+                    flights.insert(key.clone(), flight.clone());
+                    (flight, true)
+                },
+            }
+        };
+        if !leader {
+            let mut state = unwind::lock(&flight.state);
+            while !state.done {
+                state = flight.landed.wait(state).unwrap_or_else(PoisonError::into_inner);
+            }
+            if let Some(ref value) = state.value {
+                return Ok(value.clone());
+            }
+            drop(state);
+            return request();
+        }
+        let mut landing = Landing {
+            coalescer: self,
+            key: key,
+            flight: flight,
+            departed: false,
+            value: None,
+        };
+        let result = request();
+        if landing.depart() {
+            if let Ok(ref value) = result {
+                landing.value = Some(value.clone());
+            }
+        }
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use std::sync::{Arc, Mutex};
+    use std::thread;
+    use std::time::Duration;
+    use super::Coalescer;
+    use super::super::{Api, RequestError};
+    use transport::HttpTransport;
+
+    #[derive(Clone, Default)]
+    struct CountingTransport {
+        requests: Arc<Mutex<usize>>,
+    }
+
+    impl HttpTransport for CountingTransport {
+        fn get(&self, _url: &str) -> Result<(u16, String), RequestError> {
+            *self.requests.lock().unwrap() += 1;
+            thread::sleep(Duration::from_millis(100));
+            Ok((200, r#"{"head": {}, "def": []}"#.to_owned()))
+        }
+    }
+
+    #[test]
+    fn check_coalesced_lookups() {
+        let transport = CountingTransport::default();
+        let api = Api::from_token("token").unwrap().with_transport(transport.clone());
+        let workers: Vec<_> = (0..4).map(|_| {
+            let api = api.clone();
+            thread::spawn(move || api.lookup_def("en-ru", "rust").unwrap().len())
+        }).collect();
+        for worker in workers {
+            assert_eq!(worker.join().unwrap(), 0);
+        }
+        assert_eq!(*transport.requests.lock().unwrap(), 1);
+        // Nothing is kept after the flight
+        api.lookup_def("en-ru", "rust").unwrap();
+        assert_eq!(*transport.requests.lock().unwrap(), 2);
+    }
+
+    struct Answer(Arc<Mutex<usize>>);
+
+    impl Clone for Answer {
+        fn clone(&self) -> Answer {
+            *self.0.lock().unwrap() += 1;
+            Answer(self.0.clone())
+        }
+    }
+
+    #[test]
+    fn check_lone_flight_not_copied() {
+        let coalescer: Coalescer<&str, Answer> = Coalescer::new();
+        let copies = Arc::new(Mutex::new(0));
+        let result: Result<_, ()> = coalescer.run(&"key", || Ok(Answer(copies.clone())));
+        assert!(result.is_ok());
+        assert_eq!(*copies.lock().unwrap(), 0);
+    }
+
+    #[test]
+    fn check_failed_flight() {
+        let coalescer: Arc<Coalescer<&str, u32>> = Arc::new(Coalescer::new());
+        let calls = Arc::new(Mutex::new(0));
+        let workers: Vec<_> = (0..3).map(|_| {
+            let coalescer = coalescer.clone();
+            let calls = calls.clone();
+            thread::spawn(move || coalescer.run(&"key", || {
+                thread::sleep(Duration::from_millis(50));
+                let mut calls = calls.lock().unwrap();
+                *calls += 1;
+                if *calls == 1 { Err(()) } else { Ok(*calls) }
+            }))
+        }).collect();
+        let results: Vec<_> = workers.into_iter().map(|worker| worker.join().unwrap()).collect();
+        assert_eq!(results.iter().filter(|result| result.is_err()).count(), 1);
+        assert!(*calls.lock().unwrap() >= 2);
+    }
+}
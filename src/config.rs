@@ -0,0 +1,327 @@
+// Settings of `~/.config/yadict/config.toml`, like:
+//
+//     token = "dict.1.1..."
+//     lang = "en-ru"
+//     cache_dir = "/home/user/.cache/yadict"
+//     cache_max_age_days = 30
+//     flags = ["morpho"]
+//     format = "text"
+//     history = true
+//     store = true
+//     tts_key = "AQVN..."
+//     base_url = "https://dictionary.yandex.net/api/v1/dicservice.json"
+//     timeout_ms = 5000
+//     daily_budget = 10000
+//
+// Every setting is optional. Environment variables override the file,
+// `Config::from_env` takes them alone for deployments without files.
+
+use std::env;
+use std::error::Error;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use toml;
+use history::History;
+use super::{LangPair, LookupFlags, LookupOptions};
+
+pub const TOKEN_VAR: &'static str = "YANDEX_DICTIONARY_TOKEN";
+// Shorter name of the token variable, `TOKEN_VAR` wins if both are set.
+pub const TOKEN_ALT_VAR: &'static str = "YADICT_TOKEN";
+pub const LANG_VAR: &'static str = "YADICT_LANG";
+pub const CACHE_DIR_VAR: &'static str = "YADICT_CACHE_DIR";
+pub const FORMAT_VAR: &'static str = "YADICT_FORMAT";
+pub const TTS_KEY_VAR: &'static str = "YADICT_TTS_KEY";
+pub const BASE_URL_VAR: &'static str = "YADICT_BASE_URL";
+pub const TIMEOUT_VAR: &'static str = "YADICT_TIMEOUT_MS";
+pub const BUDGET_VAR: &'static str = "YADICT_DAILY_BUDGET";
+
+#[derive(Clone, Default, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    pub token: Option<String>,
+    // Default direction of lookups.
+    pub lang: Option<String>,
+    // Directory of `cache::FileCache`, no cache if unset.
+    pub cache_dir: Option<PathBuf>,
+    // Cached answers older than that are looked up again, but still used
+    // if the service can't be reached, see `CachePolicy::StaleIfError`.
+    pub cache_max_age_days: Option<u64>,
+    // Names of `LookupFlags`: family, morpho and pos_filter.
+    #[serde(default)]
+    pub flags: Vec<String>,
+    // Output format of the command-line tool.
+    pub format: Option<String>,
+    // Journal lookups to `History::default_path`.
+    #[serde(default)]
+    pub history: bool,
+    // Keep found words in `store::Store::default_path`, needs the store feature.
+    #[serde(default)]
+    pub store: bool,
+    // API key of Yandex SpeechKit for `tts::SpeechKit`.
+    pub tts_key: Option<String>,
+    // Endpoint instead of `API_URL`, e.g. a proxy.
+    pub base_url: Option<String>,
+    // Timeout of requests in milliseconds, see `Api::with_timeout`.
+    pub timeout_ms: Option<u64>,
+    // Requests per UTC day, see `Api::with_daily_budget`.
+    pub daily_budget: Option<u32>,
+}
+
+// Shows whether keys are set, not the keys.
+impl fmt::Debug for Config {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Config")
+            .field("token", &self.token.as_ref().map(|_| "***"))
+            .field("lang", &self.lang)
+            .field("cache_dir", &self.cache_dir)
+            .field("cache_max_age_days", &self.cache_max_age_days)
+            .field("flags", &self.flags)
+            .field("format", &self.format)
+            .field("history", &self.history)
+            .field("store", &self.store)
+            .field("tts_key", &self.tts_key.as_ref().map(|_| "***"))
+            .field("base_url", &self.base_url)
+            .field("timeout_ms", &self.timeout_ms)
+            .field("daily_budget", &self.daily_budget)
+            .finish()
+    }
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Io(PathBuf, io::Error),
+    Parse(PathBuf, toml::de::Error),
+    InvalidLang(String),
+    InvalidFlag(String),
+    // Name and value of an environment variable which isn't a number.
+    InvalidVar(&'static str, String),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ConfigError::Io(ref path, ref e) => write!(f, "can't read {}: {}", path.display(), e),
+            ConfigError::Parse(ref path, ref e) => write!(f, "invalid config {}: {}", path.display(), e),
+            ConfigError::InvalidLang(ref lang) => write!(f, "invalid lang pair in config: '{}'", lang),
+            ConfigError::InvalidFlag(ref name) => write!(f, "unknown lookup flag in config: '{}'", name),
+            ConfigError::InvalidVar(name, ref value) => write!(f, "invalid number in {}: '{}'", name, value),
+        }
+    }
+}
+
+impl Error for ConfigError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match *self {
+            ConfigError::Io(_, ref e) => Some(e),
+            ConfigError::Parse(_, ref e) => Some(e),
+            ConfigError::InvalidLang(_) | ConfigError::InvalidFlag(_) | ConfigError::InvalidVar(..) => None,
+        }
+    }
+}
+
+impl Config {
+    // `$XDG_CONFIG_HOME/yadict/config.toml` or `~/.config/yadict/config.toml`.
+    pub fn default_path() -> Option<PathBuf> {
+        let dir = match env::var_os("XDG_CONFIG_HOME") {
+            Some(ref dir) if !dir.is_empty() => PathBuf::from(dir),
+            _ => match env::var_os("HOME") {
+                Some(home) => PathBuf::from(home).join(".config"),
+                None => return None,
+            },
+        };
+        Some(dir.join("yadict").join("config.toml"))
+    }
+
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Config, ConfigError> {
+        let path = path.as_ref();
+        let mut text = String::new();
+        File::open(path)
+            .and_then(|mut file| file.read_to_string(&mut text))
+            .map_err(|e| ConfigError::Io(path.to_owned(), e))?;
+        Config::from_toml(&text).map_err(|e| match e {
+            ConfigError::Parse(_, e) => ConfigError::Parse(path.to_owned(), e),
+            e => e,
+        })
+    }
+
+    pub fn from_toml(text: &str) -> Result<Config, ConfigError> {
+        let config: Config = toml::from_str(text).map_err(|e| ConfigError::Parse(PathBuf::new(), e))?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    // Reads the file of `default_path` if there is one and applies
+    // the environment over it.
+    pub fn load() -> Result<Config, ConfigError> {
+        let mut config = match Config::default_path() {
+            Some(ref path) if path.exists() => Config::from_file(path)?,
+            _ => Config::default(),
+        };
+        config.apply_env()?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    // Settings of the environment only, no file is read.
+    pub fn from_env() -> Result<Config, ConfigError> {
+        let mut config = Config::default();
+        config.apply_env()?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    pub fn apply_env(&mut self) -> Result<(), ConfigError> {
+        let var = |name| env::var(name).ok().filter(|value: &String| !value.is_empty());
+        if let Some(token) = var(TOKEN_VAR).or_else(|| var(TOKEN_ALT_VAR)) {
+            self.token = Some(token);
+        }
+        if let Some(lang) = var(LANG_VAR) {
+            self.lang = Some(lang);
+        }
+        if let Some(dir) = var(CACHE_DIR_VAR) {
+            self.cache_dir = Some(PathBuf::from(dir));
+        }
+        if let Some(format) = var(FORMAT_VAR) {
+            self.format = Some(format);
+        }
+        if let Some(key) = var(TTS_KEY_VAR) {
+            self.tts_key = Some(key);
+        }
+        if let Some(url) = var(BASE_URL_VAR) {
+            self.base_url = Some(url);
+        }
+        if let Some(timeout) = var(TIMEOUT_VAR) {
+            self.timeout_ms = Some(timeout.parse().map_err(|_| ConfigError::InvalidVar(TIMEOUT_VAR, timeout))?);
+        }
+        if let Some(budget) = var(BUDGET_VAR) {
+            self.daily_budget = Some(budget.parse().map_err(|_| ConfigError::InvalidVar(BUDGET_VAR, budget))?);
+        }
+        Ok(())
+    }
+
+    pub fn lang_pair(&self) -> Option<LangPair> {
+        self.lang.as_ref().and_then(|lang| lang.parse().ok())
+    }
+
+    pub fn history_path(&self) -> Option<PathBuf> {
+        if self.history {
+            History::default_path()
+        } else {
+            None
+        }
+    }
+
+    pub fn options(&self) -> LookupOptions {
+        LookupOptions {
+            flags: parse_flags(&self.flags).unwrap_or_default(),
+            ui: None,
+        }
+    }
+
+    fn validate(&self) -> Result<(), ConfigError> {
+        if let Some(ref lang) = self.lang {
+            if lang.parse::<LangPair>().is_err() {
+                return Err(ConfigError::InvalidLang(lang.clone()));
+            }
+        }
+        match self.flags.iter().find(|name| parse_flag(name).is_none()) {
+            Some(name) => Err(ConfigError::InvalidFlag(name.clone())),
+            None => Ok(()),
+        }
+    }
+}
+
+pub fn parse_flag(name: &str) -> Option<LookupFlags> {
+    match name {
+        "family" => Some(LookupFlags::FAMILY),
+        "morpho" => Some(LookupFlags::MORPHO),
+        "pos_filter" => Some(LookupFlags::POS_FILTER),
+        _ => None,
+    }
+}
+
+pub fn parse_flags<S: AsRef<str>>(names: &[S]) -> Option<LookupFlags> {
+    let mut flags = LookupFlags::empty();
+    for name in names {
+        flags |= parse_flag(name.as_ref())?;
+    }
+    Some(flags)
+}
+
+#[cfg(test)]
+mod tests {
+
+    use std::env;
+    use std::fs;
+    use super::{Config, ConfigError};
+    use super::super::{Api, LookupFlags};
+
+    #[test]
+    fn check_config() {
+        let config = Config::from_toml(r#"
+            token = "secret"
+            lang = "en-ru"
+            flags = ["morpho", "family"]
+            history = true
+        "#).unwrap();
+        assert_eq!(config.token, Some("secret".to_owned()));
+        assert_eq!(config.lang_pair().unwrap().as_str(), "en-ru");
+        assert_eq!(config.options().flags, LookupFlags::MORPHO | LookupFlags::FAMILY);
+        assert_eq!(config.cache_dir, None);
+        assert!(config.history);
+        assert_eq!(Config::from_toml("").unwrap(), Config::default());
+
+        match Config::from_toml(r#"flags = ["fast"]"#) {
+            Err(ConfigError::InvalidFlag(ref name)) if name == "fast" => (),
+            other => panic!("unexpected result: {:?}", other),
+        }
+        assert!(Config::from_toml(r#"lang = "english""#).is_err());
+        assert!(Config::from_toml(r#"tokn = "secret""#).is_err());
+    }
+
+    #[test]
+    fn check_config_file() {
+        let dir = env::temp_dir().join("yadict-test-config");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("config.toml");
+        match Config::from_file(&path) {
+            Err(ConfigError::Io(..)) => (),
+            other => panic!("unexpected result: {:?}", other),
+        }
+        fs::write(&path, "token = \"secret\"\ncache_dir = \"/tmp/yadict\"\n").unwrap();
+        let config = Config::from_file(&path).unwrap();
+        assert!(Api::from_settings(&config).is_ok());
+        assert!(Api::from_settings(&Config::default()).is_err());
+        fs::write(&path, "token = ").unwrap();
+        let error = Config::from_file(&path).unwrap_err().to_string();
+        assert!(error.contains("config.toml"), "{}", error);
+    }
+
+    #[test]
+    fn check_config_from_env() {
+        use super::{BASE_URL_VAR, BUDGET_VAR, TIMEOUT_VAR, TOKEN_ALT_VAR};
+        env::set_var(TOKEN_ALT_VAR, "secret");
+        env::set_var(BASE_URL_VAR, "http://localhost:8080/");
+        env::set_var(TIMEOUT_VAR, "1500");
+        env::set_var(BUDGET_VAR, "many");
+        match Config::from_env() {
+            Err(ConfigError::InvalidVar(name, ref value)) if name == BUDGET_VAR && value == "many" => (),
+            other => panic!("unexpected result: {:?}", other),
+        }
+        env::set_var(BUDGET_VAR, "100");
+        let config = Config::from_env();
+        for name in &[TOKEN_ALT_VAR, BASE_URL_VAR, TIMEOUT_VAR, BUDGET_VAR] {
+            env::remove_var(name);
+        }
+        let config = config.unwrap();
+        assert_eq!(config.timeout_ms, Some(1500));
+        assert_eq!(config.daily_budget, Some(100));
+        let api = Api::from_settings(&config).unwrap();
+        assert_eq!(api.remaining_estimate(), Some(100));
+        let debug = format!("{:?}", api);
+        assert!(debug.contains("http://localhost:8080") && debug.contains("1.5s"), "{}", debug);
+    }
+}
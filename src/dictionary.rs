@@ -0,0 +1,72 @@
+use super::{Api, Def, LangPair, RequestError};
+
+// Source of definitions. `Api` is the online implementation, others can
+// serve local files or different services with the same data model.
+pub trait Dictionary {
+    fn lookup_def(&self, lang: &LangPair, text: &str) -> Result<Vec<Def>, RequestError>;
+}
+
+impl Dictionary for Api {
+    fn lookup_def(&self, lang: &LangPair, text: &str) -> Result<Vec<Def>, RequestError> {
+        Api::lookup_def(self, lang, text)
+    }
+}
+
+impl<D: Dictionary + ?Sized> Dictionary for &D {
+    fn lookup_def(&self, lang: &LangPair, text: &str) -> Result<Vec<Def>, RequestError> {
+        (**self).lookup_def(lang, text)
+    }
+}
+
+impl<D: Dictionary + ?Sized> Dictionary for Box<D> {
+    fn lookup_def(&self, lang: &LangPair, text: &str) -> Result<Vec<Def>, RequestError> {
+        (**self).lookup_def(lang, text)
+    }
+}
+
+// Asks `primary` and falls back to `secondary` when it fails, e.g. when
+// the service is unreachable or over quota and a local dictionary remains.
+// Words unknown to `primary` are looked up in `secondary` too, failure of
+// `secondary` then keeps the empty answer.
+pub struct Fallback<P, S> {
+    pub primary: P,
+    pub secondary: S,
+}
+
+impl<P: Dictionary, S: Dictionary> Dictionary for Fallback<P, S> {
+    fn lookup_def(&self, lang: &LangPair, text: &str) -> Result<Vec<Def>, RequestError> {
+        match self.primary.lookup_def(lang, text) {
+            Ok(ref defs) if defs.is_empty() => self.secondary.lookup_def(lang, text).or(Ok(Vec::new())),
+            Ok(defs) => Ok(defs),
+            Err(e) => self.secondary.lookup_def(lang, text).map_err(|_| e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::{Dictionary, Fallback};
+    use super::super::{Api, LangPair};
+    use testing::MockTransport;
+
+    #[test]
+    fn check_api_dictionary() {
+        let api = Api::from_token("token").unwrap().with_transport(MockTransport::with_fixtures());
+        let dicts: Vec<Box<dyn Dictionary>> = vec![Box::new(api)];
+        let lang = LangPair::from("en-ru");
+        assert_eq!(dicts[0].lookup_def(&lang, "rust").unwrap().len(), 3);
+        assert!(dicts[0].lookup_def(&lang, "qwzx").unwrap().is_empty());
+    }
+
+    #[test]
+    fn check_fallback() {
+        let offline = Api::from_token("token").unwrap().with_transport(MockTransport::new());
+        let online = Api::from_token("token").unwrap().with_transport(MockTransport::with_fixtures());
+        let lang = LangPair::from("en-ru");
+        let dict = Fallback { primary: &offline, secondary: &online };
+        assert_eq!(dict.lookup_def(&lang, "rust").unwrap().len(), 3);
+        let dict = Fallback { primary: &online, secondary: &offline };
+        assert!(dict.lookup_def(&lang, "qwzx").unwrap().is_empty());
+    }
+}
@@ -0,0 +1,354 @@
+// Serializers of definitions into formats for other programs and people.
+// Flat formats have a row per translation with columns listed in `COLUMNS`.
+
+use std::slice;
+use serde_json;
+use annotate::Annotation;
+use super::{Def, TranscriptionStyle};
+
+pub const COLUMNS: [&'static str; 4] = ["word", "pos", "transcription", "translation"];
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Row {
+    pub word: String,
+    pub pos: String,
+    pub transcription: String,
+    pub translation: String,
+}
+
+impl Row {
+    pub fn fields(&self) -> [&str; 4] {
+        [&self.word, &self.pos, &self.transcription, &self.translation]
+    }
+}
+
+// Definition without translations still gets a row with an empty translation.
+pub fn rows(defs: &[Def]) -> Vec<Row> {
+    let mut rows = Vec::new();
+    for def in defs {
+        let row = |translation: &str| Row {
+            word: def.word.text.clone(),
+            pos: def.word.pos.as_ref().map(|pos| pos.to_string()).unwrap_or_default(),
+            transcription: def.word.transcription().as_ipa().to_owned(),
+            translation: translation.to_owned(),
+        };
+        if def.trans.is_empty() {
+            rows.push(row(""));
+        }
+        for tr in &def.trans {
+            rows.push(row(&tr.text));
+        }
+    }
+    rows
+}
+
+pub fn to_json(defs: &[Def]) -> String {
+    serde_json::to_string_pretty(defs).expect("definitions are always serializable")
+}
+
+// Quotes fields as RFC 4180 requires.
+pub fn csv_line(fields: &[&str]) -> String {
+    let fields: Vec<String> = fields.iter().map(|field| {
+        if field.contains([',', '"', '\n', '\r']) {
+            format!("\"{}\"", field.replace('"', "\"\""))
+        } else {
+            field.to_string()
+        }
+    }).collect();
+    fields.join(",")
+}
+
+// TSV can't escape separators, they are replaced with spaces.
+pub fn tsv_line(fields: &[&str]) -> String {
+    let fields: Vec<String> = fields.iter()
+        .map(|field| field.replace(['\t', '\n', '\r'], " "))
+        .collect();
+    fields.join("\t")
+}
+
+pub fn markdown_line(fields: &[&str]) -> String {
+    let fields: Vec<String> = fields.iter()
+        .map(|field| field.replace('|', "\\|").replace(['\n', '\r'], " "))
+        .collect();
+    format!("| {} |", fields.join(" | "))
+}
+
+// Header of a Markdown table including the delimiter row.
+pub fn markdown_header() -> String {
+    let delimiters: Vec<&str> = COLUMNS.iter().map(|_| "---").collect();
+    format!("{}\n{}", markdown_line(&COLUMNS), markdown_line(&delimiters))
+}
+
+fn to_lines(header: String, defs: &[Def], line: fn(&[&str]) -> String) -> String {
+    let mut out = header;
+    out.push('\n');
+    for row in rows(defs) {
+        out.push_str(&line(&row.fields()));
+        out.push('\n');
+    }
+    out
+}
+
+pub fn to_csv(defs: &[Def]) -> String {
+    to_lines(csv_line(&COLUMNS), defs, csv_line)
+}
+
+pub fn to_tsv(defs: &[Def]) -> String {
+    to_lines(tsv_line(&COLUMNS), defs, tsv_line)
+}
+
+pub fn to_markdown(defs: &[Def]) -> String {
+    to_lines(markdown_header(), defs, markdown_line)
+}
+
+// Markdown table with transcriptions in `style`, a hidden one drops
+// the transcription column.
+pub fn to_markdown_with(defs: &[Def], style: TranscriptionStyle) -> String {
+    let hidden = style == TranscriptionStyle::Hidden;
+    let columns: Vec<&str> = COLUMNS.iter().cloned().filter(|&column| !hidden || column != "transcription").collect();
+    let delimiters: Vec<&str> = columns.iter().map(|_| "---").collect();
+    let mut out = format!("{}\n{}\n", markdown_line(&columns), markdown_line(&delimiters));
+    for def in defs {
+        let ts = style.render(&def.word.transcription());
+        for row in rows(slice::from_ref(def)) {
+            let line = if hidden {
+                markdown_line(&[&row.word, &row.pos, &row.translation])
+            } else {
+                markdown_line(&[&row.word, &row.pos, &ts, &row.translation])
+            };
+            out.push_str(&line);
+            out.push('\n');
+        }
+    }
+    out
+}
+
+// Header lines understood by Anki import, the card fields are HTML.
+pub const ANKI_HEADER: &'static str = "#separator:tab\n#html:true";
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;")
+}
+
+// Anki notes as TSV lines: a note per headword with transcription on the
+// front and translations with examples by parts of speech on the back.
+pub fn anki_notes(defs: &[Def]) -> Vec<String> {
+    anki_notes_with_sounds(defs, |_| None)
+}
+
+// Same as `anki_notes` with "[sound:FILE]" on the front of words which
+// `sound` gives a file name for, the files go to collection.media of Anki.
+pub fn anki_notes_with_sounds<F: Fn(&str) -> Option<String>>(defs: &[Def], sound: F) -> Vec<String> {
+    let mut words: Vec<(String, Vec<&Def>)> = Vec::new();
+    for def in defs {
+        match words.iter().position(|(text, _)| *text == def.word.text) {
+            Some(idx) => words[idx].1.push(def),
+            None => words.push((def.word.text.clone(), vec![def])),
+        }
+    }
+    words.into_iter().map(|(text, defs)| {
+        let mut front = escape_html(&text);
+        if let Some(ts) = defs.iter().map(|def| def.word.transcription()).find(|ts| !ts.is_empty()) {
+            front.push_str(&format!(" [{}]", escape_html(ts.as_ipa())));
+        }
+        if let Some(file) = sound(&text) {
+            front.push_str(&format!(" [sound:{}]", escape_html(&file)));
+        }
+        let mut back = Vec::new();
+        for def in defs {
+            let trans: Vec<String> = def.trans.iter().map(|tr| escape_html(&tr.text)).collect();
+            match def.word.pos {
+                Some(ref pos) => back.push(format!("<i>{}</i> {}", escape_html(pos.as_str()), trans.join(", "))),
+                None => back.push(trans.join(", ")),
+            }
+            for ex in def.trans.iter().flat_map(|tr| &tr.ex) {
+                back.push(format!("{} \u{2014} {}", escape_html(&ex.text), escape_html(&ex.tr.join("; "))));
+            }
+        }
+        tsv_line(&[&front, &back.join("<br>")])
+    }).collect()
+}
+
+// Deck ready for File > Import of Anki.
+pub fn to_anki(defs: &[Def]) -> String {
+    let mut out = ANKI_HEADER.to_owned();
+    out.push('\n');
+    for note in anki_notes(defs) {
+        out.push_str(&note);
+        out.push('\n');
+    }
+    out
+}
+
+const HTML_STYLE: &'static str = "body { max-width: 40em; margin: 2em auto; line-height: 1.6; }\n\
+    .word { border-bottom: 1px dotted gray; cursor: help; }";
+
+// Hint of a word: translations grouped by definitions, e.g.
+// "noun: ржавчина, ржа; verb: ржаветь".
+fn hint(defs: &[Def]) -> String {
+    let groups: Vec<String> = defs.iter().filter(|def| !def.trans.is_empty()).map(|def| {
+        let trans: Vec<&str> = def.trans.iter().map(|tr| tr.text.as_str()).collect();
+        match def.word.pos {
+            Some(ref pos) => format!("{}: {}", pos, trans.join(", ")),
+            None => trans.join(", "),
+        }
+    }).collect();
+    groups.join("; ")
+}
+
+// Escapes text between words, keeping its line breaks and paragraphs.
+fn html_text(text: &str) -> String {
+    let text = escape_html(text).replace("\r\n", "\n");
+    let paragraphs: Vec<String> = text.split("\n\n").map(|p| p.replace('\n', "<br>\n")).collect();
+    paragraphs.join("</p>\n<p>")
+}
+
+// Standalone HTML page of annotated `text` for reading: words with
+// translations show them on hover. Blank lines separate paragraphs.
+pub fn to_html(title: &str, text: &str, annotation: &Annotation) -> String {
+    to_html_with(title, text, annotation, TranscriptionStyle::Hidden)
+}
+
+// Same as `to_html` with the transcription of a word in `style` leading
+// its hint, like "/rʌst/ noun: ржавчина".
+pub fn to_html_with(title: &str, text: &str, annotation: &Annotation, style: TranscriptionStyle) -> String {
+    let mut body = String::new();
+    let mut last = 0;
+    for token in &annotation.tokens {
+        let mut hint = hint(&token.defs);
+        if hint.is_empty() {
+            continue;
+        }
+        let ts = token.defs.iter().map(|def| style.render(&def.word.transcription())).find(|ts| !ts.is_empty());
+        if let Some(ts) = ts {
+            hint = format!("{} {}", ts, hint);
+        }
+        body.push_str(&html_text(&text[last..token.start]));
+        body.push_str(&format!("<span class=\"word\" title=\"{}\">{}</span>",
+                               escape_html(&hint).replace('"', "&quot;"), escape_html(&token.text)));
+        last = token.end;
+    }
+    body.push_str(&html_text(text[last..].trim_end()));
+    format!("<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{}</title>\n\
+             <style>\n{}\n</style>\n</head>\n<body>\n<p>{}</p>\n</body>\n</html>\n",
+            escape_html(title), HTML_STYLE, body)
+}
+
+#[cfg(test)]
+mod tests {
+
+    use serde_json;
+    use super::super::Def;
+    use annotate::{Annotation, Token};
+    use super::super::TranscriptionStyle;
+    use super::{to_csv, to_tsv, to_markdown, to_markdown_with, to_json, to_anki, to_html, to_html_with, anki_notes_with_sounds};
+
+    fn defs() -> Vec<Def> {
+        let json = r#"[
+            {"text": "rust", "pos": "noun", "ts": "rʌst", "tr": [
+                {"text": "ржавчина"}, {"text": "rust, \"red\""}
+            ]},
+            {"text": "rust", "pos": "verb", "tr": []}
+        ]"#;
+        serde_json::from_str(json).unwrap()
+    }
+
+    #[test]
+    fn check_export_csv() {
+        assert_eq!(to_csv(&defs()), "word,pos,transcription,translation\n\
+                                     rust,noun,rʌst,ржавчина\n\
+                                     rust,noun,rʌst,\"rust, \"\"red\"\"\"\n\
+                                     rust,verb,,\n");
+    }
+
+    #[test]
+    fn check_export_tsv() {
+        assert_eq!(to_tsv(&defs()), "word\tpos\ttranscription\ttranslation\n\
+                                     rust\tnoun\trʌst\tржавчина\n\
+                                     rust\tnoun\trʌst\trust, \"red\"\n\
+                                     rust\tverb\t\t\n");
+    }
+
+    #[test]
+    fn check_export_markdown() {
+        let table = to_markdown(&defs());
+        let lines: Vec<&str> = table.lines().collect();
+        assert_eq!(lines[0], "| word | pos | transcription | translation |");
+        assert_eq!(lines[1], "| --- | --- | --- | --- |");
+        assert_eq!(lines[2], "| rust | noun | rʌst | ржавчина |");
+        assert_eq!(lines.len(), 5);
+    }
+
+    #[test]
+    fn check_export_json() {
+        let defs = defs();
+        let parsed: Vec<Def> = serde_json::from_str(&to_json(&defs)).unwrap();
+        assert_eq!(parsed.len(), 2);
+        assert_eq!(parsed[0].trans[1].text, "rust, \"red\"");
+    }
+
+    #[test]
+    fn check_export_anki() {
+        let json = r#"[
+            {"text": "rust", "pos": "noun", "ts": "rʌst", "tr": [
+                {"text": "ржавчина", "ex": [{"text": "rust stain", "tr": [{"text": "ржавое пятно"}]}]},
+                {"text": "коррозия"}
+            ]},
+            {"text": "rust", "pos": "verb", "tr": [{"text": "ржаветь"}]},
+            {"text": "a<b", "tr": [{"text": "a&b"}]}
+        ]"#;
+        let defs: Vec<Def> = serde_json::from_str(json).unwrap();
+        assert_eq!(to_anki(&defs), "#separator:tab\n#html:true\n\
+                                    rust [rʌst]\t<i>noun</i> ржавчина, коррозия<br>\
+                                    rust stain \u{2014} ржавое пятно<br><i>verb</i> ржаветь\n\
+                                    a&lt;b\ta&amp;b\n");
+        let notes = anki_notes_with_sounds(&defs, |text| if text == "rust" { Some("rust.mp3".to_owned()) } else { None });
+        assert!(notes[0].starts_with("rust [rʌst] [sound:rust.mp3]\t"));
+        assert!(notes[1].starts_with("a&lt;b\t"));
+    }
+
+    #[test]
+    fn check_export_html() {
+        let text = "Rust & iron.\n\nRust";
+        let rust = Token {
+            text: "Rust".to_owned(),
+            start: 0,
+            end: 4,
+            defs: defs(),
+        };
+        let annotation = Annotation {
+            tokens: vec![rust.clone(), Token {
+                text: "iron".to_owned(),
+                start: 7,
+                end: 11,
+                defs: Vec::new(),
+            }, Token {
+                start: 14,
+                end: 18,
+                ..rust
+            }],
+            errors: Vec::new(),
+        };
+        let html = to_html("<Notes>", text, &annotation);
+        assert!(html.contains("<title>&lt;Notes&gt;</title>"));
+        let word = "<span class=\"word\" title=\"noun: ржавчина, rust, &quot;red&quot;\">Rust</span>";
+        assert!(html.contains(&format!("<p>{} &amp; iron.</p>\n<p>{}</p>", word, word)));
+
+        let html = to_html_with("Notes", text, &annotation, TranscriptionStyle::Square);
+        assert!(html.contains("title=\"[rʌst] noun: ржавчина, rust, &quot;red&quot;\">Rust</span>"));
+    }
+
+    #[test]
+    fn check_export_transcription_style() {
+        let defs: Vec<Def> = serde_json::from_str(r#"[
+            {"text": "rust", "ts": " rʌst ", "tr": [{"text": "ржа"}]},
+            {"text": "ржа", "tr": [{"text": "rust"}]}
+        ]"#).unwrap();
+        assert_eq!(to_markdown_with(&defs, TranscriptionStyle::Slashes),
+                   "| word | pos | transcription | translation |\n| --- | --- | --- | --- |\n\
+                    | rust |  | /rʌst/ | ржа |\n| ржа |  |  | rust |\n");
+        assert_eq!(to_markdown_with(&defs, TranscriptionStyle::Square).lines().nth(2), Some("| rust |  | [rʌst] | ржа |"));
+        assert_eq!(to_markdown_with(&defs, TranscriptionStyle::Hidden),
+                   "| word | pos | translation |\n| --- | --- | --- |\n| rust |  | ржа |\n| ржа |  | rust |\n");
+    }
+}
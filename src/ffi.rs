@@ -0,0 +1,155 @@
+// C interface for desktop apps, see `include/yadict.h`. Build the library
+// with `cargo rustc --release --features ffi --crate-type cdylib`.
+//
+// Strings are UTF-8 and NUL-terminated. Results are JSON strings owned by
+// the caller, they are freed with `yadict_string_free`. A handle may be used
+// from several threads at once.
+
+// The pointer contracts are documented in the header.
+#![allow(clippy::missing_safety_doc)]
+
+use std::ffi::{CStr, CString};
+use std::os::raw::{c_char, c_int};
+use std::panic::{self, AssertUnwindSafe};
+use std::ptr;
+use serde_json;
+use super::{Api, LangPair, RequestError};
+
+pub const YADICT_OK: c_int = 0;
+// Null pointer or a string which isn't UTF-8.
+pub const YADICT_INVALID_ARGUMENT: c_int = 1;
+pub const YADICT_KEY_INVALID: c_int = 2;
+pub const YADICT_KEY_BLOCKED: c_int = 3;
+pub const YADICT_DAILY_LIMIT_EXCEEDED: c_int = 4;
+pub const YADICT_TEXT_TOO_LONG: c_int = 5;
+pub const YADICT_LANG_NOT_SUPPORTED: c_int = 6;
+// Network failures, timeouts and unexpected answers.
+pub const YADICT_REQUEST_FAILED: c_int = 7;
+pub const YADICT_PANIC: c_int = 8;
+
+fn error_code(error: &RequestError) -> c_int {
+    match *error.root() {
+        RequestError::KeyInvalid => YADICT_KEY_INVALID,
+        RequestError::KeyBlocked => YADICT_KEY_BLOCKED,
+        RequestError::DailyLimitExceeded => YADICT_DAILY_LIMIT_EXCEEDED,
+        RequestError::TextTooLong => YADICT_TEXT_TOO_LONG,
+        RequestError::LangNotSupported => YADICT_LANG_NOT_SUPPORTED,
+        _ => YADICT_REQUEST_FAILED,
+    }
+}
+
+unsafe fn to_str<'a>(s: *const c_char) -> Option<&'a str> {
+    if s.is_null() {
+        return None;
+    }
+    CStr::from_ptr(s).to_str().ok()
+}
+
+// Strings of JSON have no NUL bytes, messages are cut at the first one.
+fn to_c_string(s: String) -> *mut c_char {
+    let s = match s.find('\0') {
+        Some(pos) => s[..pos].to_owned(),
+        None => s,
+    };
+    CString::new(s).map(CString::into_raw).unwrap_or(ptr::null_mut())
+}
+
+// Returns a handle for `token`, NULL if the token is NULL or empty.
+#[no_mangle]
+pub unsafe extern "C" fn yadict_new(token: *const c_char) -> *mut Api {
+    match to_str(token) {
+        Some(token) if !token.is_empty() => match Api::from_token(token) {
+            Ok(api) => Box::into_raw(Box::new(api)),
+            Err(_) => ptr::null_mut(),
+        },
+        _ => ptr::null_mut(),
+    }
+}
+
+// Looks up `text` in direction `lang` like "en-ru". On success `*out` is
+// set to a JSON array of definitions, otherwise to a JSON object with
+// "error" and "message" fields if `out` isn't NULL, and the code is returned.
+#[no_mangle]
+pub unsafe extern "C" fn yadict_lookup_json(api: *const Api, lang: *const c_char, text: *const c_char,
+                                            out: *mut *mut c_char) -> c_int {
+    if !out.is_null() {
+        *out = ptr::null_mut();
+    }
+    let (api, lang, text) = match (api.as_ref(), to_str(lang), to_str(text)) {
+        (Some(api), Some(lang), Some(text)) => (api, lang, text),
+        _ => return YADICT_INVALID_ARGUMENT,
+    };
+    let lang = match lang.parse::<LangPair>() {
+        Ok(lang) => lang,
+        Err(_) => return YADICT_INVALID_ARGUMENT,
+    };
+    let result = panic::catch_unwind(AssertUnwindSafe(|| api.lookup_def(lang, text)));
+    let (code, json) = match result {
+        Ok(Ok(defs)) => (YADICT_OK, serde_json::to_value(&defs).unwrap_or_default()),
+        Ok(Err(e)) => (error_code(&e), serde_json::json!({"error": e.kind(), "message": e.to_string()})),
+        Err(_) => (YADICT_PANIC, serde_json::json!({"error": "panic", "message": "lookup panicked"})),
+    };
+    if !out.is_null() {
+        *out = to_c_string(json.to_string());
+    }
+    code
+}
+
+// Frees a string returned by the library, NULL is ignored.
+#[no_mangle]
+pub unsafe extern "C" fn yadict_string_free(s: *mut c_char) {
+    if !s.is_null() {
+        drop(CString::from_raw(s));
+    }
+}
+
+// Frees a handle of `yadict_new`, NULL is ignored.
+#[no_mangle]
+pub unsafe extern "C" fn yadict_free(api: *mut Api) {
+    if !api.is_null() {
+        drop(Box::from_raw(api));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use std::ffi::{CStr, CString};
+    use std::ptr;
+    use serde_json::{self, Value};
+    use super::*;
+    use super::super::Api;
+    use testing::{MockTransport, FIXTURE_LOOKUP_RUST};
+
+    #[test]
+    fn check_ffi_lookup() {
+        let api = Box::into_raw(Box::new(Api::from_token("token").unwrap()
+            .with_transport(MockTransport::new()
+                .on("text=down", 503, "")
+                .on("text=rust", 200, FIXTURE_LOOKUP_RUST))));
+        let lang = CString::new("en-ru").unwrap();
+        let lookup = |text: &str| unsafe {
+            let text = CString::new(text).unwrap();
+            let mut out = ptr::null_mut();
+            let code = yadict_lookup_json(api, lang.as_ptr(), text.as_ptr(), &mut out);
+            let json: Value = serde_json::from_str(CStr::from_ptr(out).to_str().unwrap()).unwrap();
+            yadict_string_free(out);
+            (code, json)
+        };
+        let (code, json) = lookup("rust");
+        assert_eq!(code, YADICT_OK);
+        assert_eq!(json.as_array().unwrap().len(), 3);
+        let (code, json) = lookup("down");
+        assert_eq!(code, YADICT_REQUEST_FAILED);
+        assert_eq!(json["error"], "http_status");
+        unsafe {
+            let mut out = ptr::null_mut();
+            assert_eq!(yadict_lookup_json(api, ptr::null(), lang.as_ptr(), &mut out), YADICT_INVALID_ARGUMENT);
+            assert!(out.is_null());
+            assert!(yadict_new(ptr::null()).is_null());
+            let token = CString::new("token").unwrap();
+            yadict_free(yadict_new(token.as_ptr()));
+            yadict_free(api);
+        }
+    }
+}
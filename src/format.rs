@@ -0,0 +1,172 @@
+// Human-readable rendering of definitions for terminals.
+
+use std::env;
+use std::fmt;
+use std::io::{self, IsTerminal};
+use super::{Def, TranscriptionStyle, Word};
+
+const BOLD: &'static str = "\x1b[1m";
+const DIM: &'static str = "\x1b[2m";
+const GREEN: &'static str = "\x1b[32m";
+const RESET: &'static str = "\x1b[0m";
+
+// Renders a headword line with transcription and part of speech followed
+// by numbered translations with synonyms, meanings and examples.
+pub struct Terminal {
+    pub color: bool,
+    pub transcription: TranscriptionStyle,
+}
+
+impl Terminal {
+    pub fn plain() -> Terminal {
+        Terminal {
+            color: false,
+            transcription: TranscriptionStyle::Square,
+        }
+    }
+
+    // Colors only if stdout is a terminal and NO_COLOR isn't set.
+    pub fn auto() -> Terminal {
+        Terminal {
+            color: io::stdout().is_terminal() && env::var_os("NO_COLOR").is_none(),
+            transcription: TranscriptionStyle::Square,
+        }
+    }
+
+    pub fn with_transcription(mut self, style: TranscriptionStyle) -> Terminal {
+        self.transcription = style;
+        self
+    }
+
+    fn paint(&self, style: &str, text: &str) -> String {
+        if self.color {
+            format!("{}{}{}", style, text, RESET)
+        } else {
+            text.to_owned()
+        }
+    }
+
+    pub fn render(&self, defs: &[Def]) -> String {
+        let mut out = String::new();
+        for def in defs {
+            out.push_str(&self.paint(BOLD, &def.word.text));
+            let ts = self.transcription.render(&def.word.transcription());
+            if !ts.is_empty() {
+                out.push(' ');
+                out.push_str(&self.paint(DIM, &ts));
+            }
+            if let Some(ref pos) = def.word.pos {
+                out.push(' ');
+                out.push_str(&self.paint(GREEN, pos.as_str()));
+            }
+            out.push('\n');
+            // Numbers are right-aligned, examples start under translations
+            let width = def.trans.len().to_string().len();
+            let indent = " ".repeat(width + 4);
+            for (idx, tr) in def.trans.iter().enumerate() {
+                let mut line = tr.text.clone();
+                for syn in &tr.syn {
+                    line.push_str(&format!(", {}", syn.text));
+                }
+                if !tr.mean.is_empty() {
+                    let means: Vec<&str> = tr.mean.iter().map(|m| m.text.as_str()).collect();
+                    line.push_str(&format!(" ({})", means.join(", ")));
+                }
+                out.push_str(&format!("  {:>width$}. {}\n", idx + 1, line, width = width));
+                for ex in &tr.ex {
+                    let example = format!("{} \u{2014} {}", ex.text, ex.tr.join("; "));
+                    out.push_str(&format!("{}{}\n", indent, self.paint(DIM, &example)));
+                }
+            }
+        }
+        out
+    }
+}
+
+// Plain multi-line rendering, like `Terminal::plain` does.
+pub fn pretty(defs: &[Def]) -> String {
+    Terminal::plain().render(defs)
+}
+
+// One line like "rust /rʌst/ (noun)".
+impl fmt::Display for Word {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.text)?;
+        let ts = self.transcription();
+        if !ts.is_empty() {
+            write!(f, " {}", ts)?;
+        }
+        if let Some(ref pos) = self.pos {
+            write!(f, " ({})", pos)?;
+        }
+        Ok(())
+    }
+}
+
+// One line like "rust /rʌst/ (noun) — ржавчина; ржа".
+impl fmt::Display for Def {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.word)?;
+        for (idx, tr) in self.trans.iter().enumerate() {
+            f.write_str(if idx == 0 { " \u{2014} " } else { "; " })?;
+            f.write_str(&tr.text)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use serde_json;
+    use super::{Terminal, pretty};
+    use super::super::{Def, TranscriptionStyle};
+    use testing::FIXTURE_LOOKUP_RUST;
+
+    fn defs() -> Vec<Def> {
+        let json: serde_json::Value = serde_json::from_str(FIXTURE_LOOKUP_RUST).unwrap();
+        serde_json::from_value(json["def"].clone()).unwrap()
+    }
+
+    #[test]
+    fn check_render_plain() {
+        let text = Terminal::plain().render(&defs()[..1]);
+        assert_eq!(text, "rust [r\u{28c}st] noun\n  \
+                          1. \u{440}\u{436}\u{430}\u{432}\u{447}\u{438}\u{43d}\u{430}, \
+                          \u{43a}\u{43e}\u{440}\u{440}\u{43e}\u{437}\u{438}\u{44f} (corrosion)\n     \
+                          rust stain \u{2014} \u{43f}\u{44f}\u{442}\u{43d}\u{43e} \
+                          \u{440}\u{436}\u{430}\u{432}\u{447}\u{438}\u{43d}\u{44b}\n  \
+                          2. \u{440}\u{436}\u{430}\n");
+    }
+
+    #[test]
+    fn check_display() {
+        let defs = defs();
+        assert_eq!(defs[0].word.to_string(), "rust /r\u{28c}st/ (noun)");
+        assert_eq!(defs[0].to_string(), "rust /r\u{28c}st/ (noun) \u{2014} \
+                                         \u{440}\u{436}\u{430}\u{432}\u{447}\u{438}\u{43d}\u{430}; \
+                                         \u{440}\u{436}\u{430}");
+        assert_eq!(defs[0].trans[1].to_string(), "\u{440}\u{436}\u{430} (noun)");
+        assert_eq!(pretty(&defs), Terminal::plain().render(&defs));
+    }
+
+    #[test]
+    fn check_render_color() {
+        let text = Terminal { color: true, transcription: TranscriptionStyle::Square }.render(&defs()[..1]);
+        assert!(text.starts_with("\x1b[1mrust\x1b[0m \x1b[2m[r\u{28c}st]\x1b[0m \x1b[32mnoun\x1b[0m\n"));
+    }
+
+    #[test]
+    fn check_render_transcription() {
+        let defs: Vec<Def> = serde_json::from_str(r#"[
+            {"text": "rust", "ts": " rʌst ", "tr": [{"text": "ржа"}]},
+            {"text": "ржа", "tr": [{"text": "rust"}]}
+        ]"#).unwrap();
+        assert_eq!(defs[0].word.ts, Some("rʌst".to_owned()));
+        assert_eq!(defs[1].word.ts, None);
+        let render = |style| Terminal::plain().with_transcription(style).render(&defs);
+        assert_eq!(render(TranscriptionStyle::Square), "rust [rʌst]\n  1. ржа\nржа\n  1. rust\n");
+        assert_eq!(render(TranscriptionStyle::Slashes), "rust /rʌst/\n  1. ржа\nржа\n  1. rust\n");
+        assert_eq!(render(TranscriptionStyle::Hidden), "rust\n  1. ржа\nржа\n  1. rust\n");
+    }
+}
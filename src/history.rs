@@ -0,0 +1,162 @@
+use std::env;
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufRead, BufReader, Error as IOError, ErrorKind, Write};
+use std::path::{Path, PathBuf};
+use serde_json;
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct HistoryEntry {
+    // Seconds since Unix epoch.
+    pub time: u64,
+    pub lang: String,
+    pub text: String,
+}
+
+// Filter of `History::search`, empty one matches everything.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct HistoryQuery {
+    pub lang: Option<String>,
+    // Entries at this time or later, in seconds since Unix epoch.
+    pub since: Option<u64>,
+    // Case-insensitive part of the text.
+    pub text: Option<String>,
+}
+
+impl HistoryQuery {
+    pub fn matches(&self, entry: &HistoryEntry) -> bool {
+        self.lang.as_ref().is_none_or(|lang| *lang == entry.lang)
+            && self.since.is_none_or(|since| entry.time >= since)
+            && self.text.as_ref().is_none_or(|text| entry.text.to_lowercase().contains(&text.to_lowercase()))
+    }
+}
+
+// Journal of successful lookups, a JSON line per lookup appended to
+// the file. Lines of other writers stay whole, as every line is a single
+// append. Unreadable lines are skipped.
+pub struct History {
+    path: PathBuf,
+}
+
+impl History {
+    pub fn new<P: AsRef<Path>>(path: P) -> History {
+        History {
+            path: path.as_ref().to_owned(),
+        }
+    }
+
+    // `$XDG_DATA_HOME/yadict/history.jsonl` or `~/.local/share/yadict/history.jsonl`.
+    pub fn default_path() -> Option<PathBuf> {
+        let dir = match env::var_os("XDG_DATA_HOME") {
+            Some(ref dir) if !dir.is_empty() => PathBuf::from(dir),
+            _ => match env::var_os("HOME") {
+                Some(home) => PathBuf::from(home).join(".local").join("share"),
+                None => return None,
+            },
+        };
+        Some(dir.join("yadict").join("history.jsonl"))
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn append(&self, entry: &HistoryEntry) -> Result<(), IOError> {
+        if let Some(dir) = self.path.parent() {
+            if !dir.as_os_str().is_empty() {
+                fs::create_dir_all(dir)?;
+            }
+        }
+        let mut line = serde_json::to_vec(entry).map_err(IOError::from)?;
+        line.push(b'\n');
+        let mut file = OpenOptions::new().create(true).append(true).open(&self.path)?;
+        file.write_all(&line)
+    }
+
+    // Entries in order of lookups, no file means no history yet.
+    pub fn entries(&self) -> Result<Vec<HistoryEntry>, IOError> {
+        self.search(&HistoryQuery::default())
+    }
+
+    pub fn search(&self, query: &HistoryQuery) -> Result<Vec<HistoryEntry>, IOError> {
+        let file = match File::open(&self.path) {
+            Ok(file) => file,
+            Err(ref e) if e.kind() == ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e),
+        };
+        let mut entries = Vec::new();
+        for line in BufReader::new(file).lines() {
+            let line = line?;
+            if let Ok(entry) = serde_json::from_str::<HistoryEntry>(&line) {
+                if query.matches(&entry) {
+                    entries.push(entry);
+                }
+            }
+        }
+        Ok(entries)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use std::env;
+    use std::fs::{self, OpenOptions};
+    use std::io::Write;
+    use super::{History, HistoryEntry, HistoryQuery};
+    use super::super::Api;
+    use testing::{MockTransport, FIXTURE_KEY_INVALID, FIXTURE_LOOKUP_RUST};
+
+    fn entry(time: u64, lang: &str, text: &str) -> HistoryEntry {
+        HistoryEntry {
+            time: time,
+            lang: lang.to_owned(),
+            text: text.to_owned(),
+        }
+    }
+
+    #[test]
+    fn check_history_search() {
+        let dir = env::temp_dir().join("yadict-test-history");
+        let _ = fs::remove_dir_all(&dir);
+        let history = History::new(dir.join("history.jsonl"));
+        assert!(history.entries().unwrap().is_empty());
+        history.append(&entry(100, "de-en", "Haus")).unwrap();
+        history.append(&entry(200, "en-ru", "rust")).unwrap();
+        // A line cut by a crash doesn't hide the rest
+        OpenOptions::new().append(true).open(history.path()).unwrap().write_all(b"{\"time\": 3\n").unwrap();
+        history.append(&entry(300, "de-en", "Hausaufgabe")).unwrap();
+        assert_eq!(history.entries().unwrap().len(), 3);
+
+        let query = HistoryQuery {
+            lang: Some("de-en".to_owned()),
+            since: Some(150),
+            ..HistoryQuery::default()
+        };
+        assert_eq!(history.search(&query).unwrap(), vec![entry(300, "de-en", "Hausaufgabe")]);
+        let query = HistoryQuery {
+            text: Some("haus".to_owned()),
+            ..HistoryQuery::default()
+        };
+        assert_eq!(history.search(&query).unwrap().len(), 2);
+    }
+
+    #[test]
+    fn check_api_history() {
+        let dir = env::temp_dir().join("yadict-test-api-history");
+        let _ = fs::remove_dir_all(&dir);
+        let path = dir.join("history.jsonl");
+        let api = Api::from_token("token").unwrap()
+            .with_transport(MockTransport::new()
+                .on("text=blocked", 403, FIXTURE_KEY_INVALID)
+                .on("text=rust", 200, FIXTURE_LOOKUP_RUST))
+            .with_cache(10)
+            .with_history(&path);
+        api.lookup_def("en-ru", "rust").unwrap();
+        // Answers from the cache are lookups too
+        api.lookup_def("en-ru", "rust").unwrap();
+        assert!(api.lookup_def("en-ru", "blocked").is_err());
+        let entries = History::new(&path).entries().unwrap();
+        assert_eq!(entries.len(), 2);
+        assert!(entries.iter().all(|entry| entry.lang == "en-ru" && entry.text == "rust"));
+    }
+}
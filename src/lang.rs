@@ -0,0 +1,246 @@
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fmt;
+use std::str::FromStr;
+
+// Translation direction like "en-ru". Conversion from `&str` keeps the code
+// as is, use `parse()` to check that it's well-formed.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct LangPair(String);
+
+impl LangPair {
+    pub fn new(source: &str, target: &str) -> LangPair {
+        LangPair(format!("{}-{}", source, target))
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    pub fn source(&self) -> &str {
+        self.0.split('-').next().unwrap_or("")
+    }
+
+    pub fn target(&self) -> &str {
+        self.0.split_once('-').map(|(_, target)| target).unwrap_or("")
+    }
+
+    // The opposite direction: "ru-en" for "en-ru".
+    pub fn reversed(&self) -> LangPair {
+        LangPair::new(self.target(), self.source())
+    }
+
+    // Picks the direction between two languages by the script of `text`:
+    // Cyrillic text with ("en", "ru") gives "ru-en". When the scripts of
+    // both languages are the same or unknown, the first language is the source.
+    pub fn detect(langs: (&str, &str), text: &str) -> LangPair {
+        let (first, second) = langs;
+        match text_script(text) {
+            Some(script) if script != lang_script(first) && script == lang_script(second) => {
+                LangPair::new(second, first)
+            },
+            _ => LangPair::new(first, second),
+        }
+    }
+}
+
+// Directions of the service, e.g. for two dropdowns of languages.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Langs(Vec<LangPair>);
+
+impl Langs {
+    // Malformed codes are skipped.
+    pub fn from_codes<S: AsRef<str>>(codes: &[S]) -> Langs {
+        Langs(codes.iter().filter_map(|code| code.as_ref().parse().ok()).collect())
+    }
+
+    pub fn pairs(&self) -> &[LangPair] {
+        &self.0
+    }
+
+    pub fn contains(&self, pair: &LangPair) -> bool {
+        self.0.contains(pair)
+    }
+
+    // Distinct source languages in alphabetical order.
+    pub fn sources(&self) -> Vec<&str> {
+        self.by_source().into_keys().collect()
+    }
+
+    // Languages `source` translates into in alphabetical order.
+    pub fn targets_for(&self, source: &str) -> Vec<&str> {
+        self.by_source().remove(source).unwrap_or_default()
+    }
+
+    pub fn by_source(&self) -> BTreeMap<&str, Vec<&str>> {
+        let mut groups: BTreeMap<&str, Vec<&str>> = BTreeMap::new();
+        for pair in &self.0 {
+            groups.entry(pair.source()).or_default().push(pair.target());
+        }
+        for targets in groups.values_mut() {
+            targets.sort();
+            targets.dedup();
+        }
+        groups
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Script {
+    Latin,
+    Cyrillic,
+    Greek,
+    Armenian,
+    Georgian,
+    Hebrew,
+    Arabic,
+    Devanagari,
+    Thai,
+    Hangul,
+    Kana,
+    Han,
+}
+
+fn lang_script(code: &str) -> Script {
+    match code {
+        "ru" | "uk" | "be" | "bg" | "kk" | "mk" | "sr" | "tt" | "ky" | "tg" | "mn" | "ba" | "cv" => Script::Cyrillic,
+        "el" => Script::Greek,
+        "hy" => Script::Armenian,
+        "ka" => Script::Georgian,
+        "he" | "yi" => Script::Hebrew,
+        "ar" | "fa" | "ur" => Script::Arabic,
+        "hi" | "mr" | "ne" => Script::Devanagari,
+        "th" => Script::Thai,
+        "ko" => Script::Hangul,
+        "ja" => Script::Kana,
+        "zh" => Script::Han,
+        _ => Script::Latin,
+    }
+}
+
+fn char_script(c: char) -> Option<Script> {
+    let script = match c as u32 {
+        0x41..=0x5a | 0x61..=0x7a | 0xc0..=0x24f => Script::Latin,
+        0x370..=0x3ff => Script::Greek,
+        0x400..=0x52f => Script::Cyrillic,
+        0x530..=0x58f => Script::Armenian,
+        0x590..=0x5ff => Script::Hebrew,
+        0x600..=0x6ff => Script::Arabic,
+        0x900..=0x97f => Script::Devanagari,
+        0xe00..=0xe7f => Script::Thai,
+        0x10a0..=0x10ff => Script::Georgian,
+        0x3040..=0x30ff => Script::Kana,
+        0x4e00..=0x9fff => Script::Han,
+        0xac00..=0xd7af => Script::Hangul,
+        _ => return None,
+    };
+    Some(script)
+}
+
+// The most frequent script among letters of `text`.
+fn text_script(text: &str) -> Option<Script> {
+    let mut counts: Vec<(Script, usize)> = Vec::new();
+    for script in text.chars().filter_map(char_script) {
+        match counts.iter().position(|&(s, _)| s == script) {
+            Some(idx) => counts[idx].1 += 1,
+            None => counts.push((script, 1)),
+        }
+    }
+    counts.into_iter().max_by_key(|&(_, count)| count).map(|(script, _)| script)
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LangPairError(String);
+
+impl fmt::Display for LangPairError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "invalid language pair '{}', expected form like 'en-ru'", self.0)
+    }
+}
+
+impl Error for LangPairError {
+}
+
+fn is_lang_code(code: &str) -> bool {
+    !code.is_empty() && code.chars().all(|c| c.is_ascii_lowercase())
+}
+
+impl FromStr for LangPair {
+    type Err = LangPairError;
+
+    fn from_str(s: &str) -> Result<LangPair, LangPairError> {
+        let mut parts = s.split('-');
+        match (parts.next(), parts.next(), parts.next()) {
+            (Some(source), Some(target), None) if is_lang_code(source) && is_lang_code(target) => {
+                Ok(LangPair(s.to_owned()))
+            },
+            _ => Err(LangPairError(s.to_owned())),
+        }
+    }
+}
+
+impl fmt::Display for LangPair {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl<'a> From<&'a str> for LangPair {
+    fn from(s: &'a str) -> LangPair {
+        LangPair(s.to_owned())
+    }
+}
+
+impl From<String> for LangPair {
+    fn from(s: String) -> LangPair {
+        LangPair(s)
+    }
+}
+
+impl<'a> From<&'a LangPair> for LangPair {
+    fn from(pair: &'a LangPair) -> LangPair {
+        pair.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::{LangPair, Langs};
+
+    #[test]
+    fn check_parse_lang_pair() {
+        let pair: LangPair = "en-ru".parse().unwrap();
+        assert_eq!(pair.source(), "en");
+        assert_eq!(pair.target(), "ru");
+        assert_eq!(pair.to_string(), "en-ru");
+        assert_eq!(pair, LangPair::new("en", "ru"));
+        assert!("en_ru".parse::<LangPair>().is_err());
+        assert!("en-".parse::<LangPair>().is_err());
+        assert!("en-ru-de".parse::<LangPair>().is_err());
+        assert!("EN-RU".parse::<LangPair>().is_err());
+        assert_eq!(pair.reversed().as_str(), "ru-en");
+    }
+
+    #[test]
+    fn check_detect_direction() {
+        assert_eq!(LangPair::detect(("en", "ru"), "\u{43f}\u{440}\u{438}\u{432}\u{435}\u{442}").as_str(), "ru-en");
+        assert_eq!(LangPair::detect(("en", "ru"), "hello").as_str(), "en-ru");
+        assert_eq!(LangPair::detect(("ru", "en"), "hello").as_str(), "en-ru");
+        assert_eq!(LangPair::detect(("en", "de"), "hallo").as_str(), "en-de");
+        assert_eq!(LangPair::detect(("en", "el"), "\u{3b3}\u{3b5}\u{3b9}\u{3b1}").as_str(), "el-en");
+        assert_eq!(LangPair::detect(("en", "ru"), "123").as_str(), "en-ru");
+    }
+
+    #[test]
+    fn check_langs() {
+        let langs = Langs::from_codes(&["en-ru", "ru-en", "en-de", "bad", "ru-uk", "en-ru"]);
+        assert_eq!(langs.pairs().len(), 5);
+        assert_eq!(langs.sources(), vec!["en", "ru"]);
+        assert_eq!(langs.targets_for("en"), vec!["de", "ru"]);
+        assert_eq!(langs.targets_for("ru"), vec!["en", "uk"]);
+        assert!(langs.targets_for("de").is_empty());
+        assert!(langs.contains(&LangPair::new("ru", "uk")));
+        assert!(!langs.contains(&LangPair::new("uk", "ru")));
+    }
+}
@@ -0,0 +1,193 @@
+// Base forms of inflected words for lookups of "running" or "боялся",
+// which the dictionary often doesn't know. `Rules` strips common endings
+// of English and Russian, implement `Lemmatizer` to plug in a real
+// morphological analyzer.
+
+use std::sync::Arc;
+use super::{Api, Def, LangPair, RequestError};
+use unwind;
+
+// Candidates tried for a word, each one costs a lookup.
+pub const MAX_LEMMAS: usize = 4;
+
+pub trait Lemmatizer: Send + Sync {
+    // Possible base forms of `word` in language like "en", likely ones first.
+    fn lemmas(&self, lang: &str, word: &str) -> Vec<String>;
+}
+
+// Guesses by endings without a word list, so most candidates don't
+// exist and lookups sort them out.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Rules;
+
+const EN_IRREGULAR: [(&'static str, &'static str); 14] = [
+    ("was", "be"), ("were", "be"), ("been", "be"), ("went", "go"), ("gone", "go"),
+    ("ran", "run"), ("had", "have"), ("did", "do"), ("done", "do"), ("better", "good"),
+    ("men", "man"), ("women", "woman"), ("children", "child"), ("mice", "mouse"),
+];
+
+// Endings and their replacements in the base form, longer endings first.
+const EN_ENDINGS: [(&'static str, &'static [&'static str]); 7] = [
+    ("ies", &["y"]),
+    ("ied", &["y"]),
+    ("ing", &["", "e"]),
+    ("es", &["", "e"]),
+    ("ed", &["", "e"]),
+    ("er", &["", "e"]),
+    ("s", &[""]),
+];
+
+const RU_ENDINGS: [(&'static str, &'static [&'static str]); 30] = [
+    ("лась", &["ться"]), ("лось", &["ться"]), ("лись", &["ться"]), ("лся", &["ться"]),
+    ("ется", &["ться"]), ("ются", &["ться"]), ("ится", &["иться"]), ("ятся", &["иться"]),
+    ("ого", &["ый", "ий", "ой"]), ("его", &["ий", "ый"]), ("ому", &["ый", "ой"]),
+    ("ему", &["ий"]), ("ыми", &["ый"]), ("ими", &["ий"]), ("ами", &["а", ""]),
+    ("ями", &["я", "ь"]), ("ла", &["ть"]), ("ло", &["ть"]), ("ли", &["ть"]),
+    ("ет", &["ть"]), ("ая", &["ый", "ой"]), ("ое", &["ый", "ой"]), ("ые", &["ый"]),
+    ("ую", &["ый", "а"]), ("ой", &["а", "ый"]), ("ов", &[""]), ("ах", &["а", ""]),
+    ("л", &["ть"]), ("ы", &["", "а"]), ("и", &["ь", "я", "а"]),
+];
+
+// Shortest stem left after an ending, so "is" isn't reduced to "i".
+const MIN_STEM: usize = 2;
+
+fn is_vowel(c: char) -> bool {
+    "aeiouy".contains(c)
+}
+
+// "running" -> "runn" -> "run"
+fn undouble(stem: &str) -> Option<String> {
+    let chars: Vec<char> = stem.chars().collect();
+    match chars.len() {
+        len if len > MIN_STEM && chars[len - 1] == chars[len - 2] && !is_vowel(chars[len - 1]) =>
+            Some(chars[..len - 1].iter().collect()),
+        _ => None,
+    }
+}
+
+fn by_endings(word: &str, endings: &[(&str, &[&str])], english: bool) -> Vec<String> {
+    let mut lemmas = Vec::new();
+    for &(ending, replacements) in endings {
+        if !word.ends_with(ending) {
+            continue;
+        }
+        let stem = &word[..word.len() - ending.len()];
+        if stem.chars().count() < MIN_STEM {
+            continue;
+        }
+        if english && (ending == "ing" || ending == "ed" || ending == "er") {
+            lemmas.extend(undouble(stem));
+        }
+        lemmas.extend(replacements.iter().map(|replacement| format!("{}{}", stem, replacement)));
+    }
+    lemmas
+}
+
+impl Lemmatizer for Rules {
+    fn lemmas(&self, lang: &str, word: &str) -> Vec<String> {
+        let word = word.to_lowercase();
+        let mut lemmas = match lang {
+            "en" => {
+                let mut lemmas: Vec<String> = EN_IRREGULAR.iter()
+                    .filter(|&&(form, _)| form == word)
+                    .map(|&(_, lemma)| lemma.to_owned())
+                    .collect();
+                lemmas.extend(by_endings(&word, &EN_ENDINGS, true));
+                lemmas
+            },
+            "ru" => by_endings(&word, &RU_ENDINGS, false),
+            _ => Vec::new(),
+        };
+        let mut seen = vec![word];
+        lemmas.retain(|lemma| {
+            let new = !seen.contains(lemma);
+            seen.push(lemma.clone());
+            new
+        });
+        lemmas
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Lemmatized {
+    // Base form which was looked up, `None` if the text itself was found
+    // or nothing was.
+    pub lemma: Option<String>,
+    pub defs: Vec<Def>,
+}
+
+impl Api {
+    // Uses `lemmatizer` in `lookup_lemma`, `Rules` is a simple one.
+    pub fn with_lemmatizer<L: Lemmatizer + 'static>(mut self, lemmatizer: L) -> Api {
+        self.lemmatizer = Some(Arc::new(lemmatizer));
+        self
+    }
+
+    // Looks up `text` and, if nothing is found, up to `MAX_LEMMAS` of its
+    // base forms until one has definitions. Base forms cost no requests
+    // this way, and neither do inflections without a lemmatizer.
+    pub fn lookup_lemma<L: Into<LangPair>>(&self, lang: L, text: &str) -> Result<Lemmatized, RequestError> {
+        let lang = lang.into();
+        let defs = self.lookup_def(&lang, text)?;
+        if !defs.is_empty() {
+            return Ok(Lemmatized {
+                lemma: None,
+                defs: defs,
+            });
+        }
+        if let Some(ref lemmatizer) = self.lemmatizer {
+            let lemmas = unwind::catch("lemmatizer", || lemmatizer.lemmas(lang.source(), text))?;
+            for lemma in lemmas.into_iter().take(MAX_LEMMAS) {
+                let defs = self.lookup_def(&lang, &lemma)?;
+                if !defs.is_empty() {
+                    return Ok(Lemmatized {
+                        lemma: Some(lemma),
+                        defs: defs,
+                    });
+                }
+            }
+        }
+        Ok(Lemmatized {
+            lemma: None,
+            defs: defs,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::{Lemmatizer, Rules};
+    use super::super::Api;
+    use testing::{MockTransport, FIXTURE_LOOKUP_RUST, FIXTURE_NOT_FOUND};
+
+    #[test]
+    fn check_rules() {
+        assert_eq!(Rules.lemmas("en", "running")[0], "run");
+        assert_eq!(Rules.lemmas("en", "tried"), vec!["try", "tri", "trie"]);
+        assert_eq!(Rules.lemmas("en", "Cats"), vec!["cat"]);
+        assert_eq!(Rules.lemmas("en", "went"), vec!["go"]);
+        assert!(Rules.lemmas("en", "is").is_empty());
+        assert_eq!(Rules.lemmas("ru", "боялся"), vec!["бояться"]);
+        assert_eq!(Rules.lemmas("ru", "красного")[0], "красный");
+        assert!(Rules.lemmas("ru", "кот").is_empty());
+        assert!(Rules.lemmas("de", "Katzen").is_empty());
+    }
+
+    #[test]
+    fn check_lookup_lemma() {
+        let transport = MockTransport::new()
+            .on("text=rusted", 200, FIXTURE_NOT_FOUND)
+            .on("text=rust", 200, FIXTURE_LOOKUP_RUST)
+            .on("/lookup?", 200, FIXTURE_NOT_FOUND);
+        let api = Api::from_token("token").unwrap().with_transport(transport.clone());
+        assert!(api.lookup_lemma("en-ru", "rusted").unwrap().defs.is_empty());
+        let api = api.with_lemmatizer(Rules);
+        let lemmatized = api.lookup_lemma("en-ru", "rusted").unwrap();
+        assert_eq!(lemmatized.lemma.unwrap(), "rust");
+        assert_eq!(lemmatized.defs.len(), 3);
+        let lemmatized = api.lookup_lemma("en-ru", "rust").unwrap();
+        assert!(lemmatized.lemma.is_none());
+        assert_eq!(transport.requests().len(), 4);
+    }
+}
@@ -1,32 +1,567 @@
+// The crate spells out field names and `'static` of constants.
+#![allow(clippy::redundant_field_names, clippy::redundant_static_lifetimes)]
+
+#[macro_use]
+extern crate bitflags;
+#[cfg(feature = "hyper")]
 extern crate hyper;
-extern crate rustc_serialize;
+extern crate serde;
+#[macro_use]
+extern crate serde_derive;
+extern crate serde_json;
+extern crate toml;
+extern crate url;
+#[cfg(feature = "async")]
+extern crate futures;
+#[cfg(feature = "async")]
+extern crate reqwest;
+#[cfg(feature = "ureq")]
+extern crate ureq;
+#[cfg(feature = "store")]
+extern crate rusqlite;
+#[cfg(all(test, feature = "async"))]
+extern crate tokio;
+#[cfg(feature = "log")]
+#[macro_use]
+extern crate log;
+#[cfg(feature = "tracing")]
+extern crate tracing;
+#[cfg(feature = "python")]
+extern crate pyo3;
+#[cfg(feature = "node")]
+extern crate napi;
+#[cfg(feature = "node")]
+extern crate napi_derive;
+// Code generated by pyo3 and napi macros refers to `::core`.
+#[cfg(any(feature = "python", feature = "node"))]
+extern crate core;
+
+// Debug messages for the `log` crate. Without the feature arguments are
+// type checked, but never evaluated.
+#[cfg(feature = "log")]
+macro_rules! log_debug {
+    ($($arg:tt)*) => { debug!($($arg)*) }
+}
+
+#[cfg(not(feature = "log"))]
+macro_rules! log_debug {
+    ($($arg:tt)*) => { if false { let _ = format_args!($($arg)*); } }
+}
+
+// Same as `log_debug` for problems which don't fail a call.
+#[cfg(feature = "log")]
+macro_rules! log_warn {
+    ($($arg:tt)*) => { warn!($($arg)*) }
+}
+
+#[cfg(not(feature = "log"))]
+macro_rules! log_warn {
+    ($($arg:tt)*) => { if false { let _ = format_args!($($arg)*); } }
+}
+
+pub mod annotate;
+#[cfg(feature = "async")]
+pub mod async_api;
+mod batch;
+pub mod borrowed;
+pub mod cache;
+mod cancel;
+mod coalesce;
+pub mod config;
+mod dictionary;
+pub mod export;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+pub mod format;
+pub mod history;
+mod lang;
+pub mod lemma;
+#[cfg(feature = "node")]
+mod node;
+mod parse;
+mod pivot;
+pub mod metrics;
+pub mod middleware;
+mod pos;
+#[cfg(feature = "python")]
+mod python;
+mod quota;
+mod ratelimit;
+mod retry;
+pub mod review;
+mod sense;
+mod speller;
+mod suggest;
+pub mod stardict;
+pub mod stats;
+#[cfg(feature = "store")]
+pub mod store;
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
+pub mod translate;
+#[cfg(feature = "translit")]
+pub mod translit;
+#[cfg(feature = "tts")]
+pub mod tts;
+mod transcription;
+pub mod transport;
+mod unwind;
+mod warning;
 
+use cache::{CacheBackend, CacheKey, CachePolicy, FileCache, Freshness, LruCache};
+use coalesce::Coalescer;
+use history::{History, HistoryEntry};
+use lemma::Lemmatizer;
+use config::{Config, ConfigError};
+use metrics::Metrics;
+use middleware::{Counter, Logger, Middleware, Next, Recorder, Request, Response, Retrying, Throttle};
+use quota::Quota;
+use stats::{Stats, UsageLog};
+use ratelimit::RateLimiter;
+use suggest::Vocabulary;
+use transport::{HttpTransport, Validators};
+#[cfg(feature = "hyper")]
+use transport::HyperTransport;
+#[cfg(feature = "ureq")]
+use transport::UreqTransport;
+pub use batch::{BatchOptions, BatchPlan, BatchReport, BatchResult, DedupMode, LookupIter, MultiLookup, SkipReason};
+pub use cancel::CancellationToken;
+pub use dictionary::{Dictionary, Fallback};
+pub use lang::{LangPair, LangPairError, Langs};
+pub use parse::{LookupResponse, ParseMode, ParseWarning};
+pub use pivot::{PivotTranslation, Pivoted};
+pub use pos::PartOfSpeech;
+pub use ratelimit::{RateLimit, LimitAction};
+pub use retry::{RetryPolicy, is_transient};
+pub use sense::{SenseId, SenseIds};
+pub use speller::{Corrected, LookupOutcome};
+pub use transcription::{Brackets, Transcription, TranscriptionStyle};
+pub use warning::{Warning, Warnings};
+
+use std::cell::Cell;
+use std::cmp;
+use std::collections::HashMap;
 use std::env;
+use std::error::Error;
+use std::fmt;
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use std::sync::{mpsc, Arc, Mutex};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
 use std::str::Utf8Error;
-use std::io::{Read, Error as IOError};
+use std::io::Error as IOError;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+#[cfg(feature = "hyper")]
 use hyper::client::Client;
-use hyper::status::StatusCode;
+#[cfg(feature = "hyper")]
 use hyper::error::Error as HyperError;
-use rustc_serialize::json::{Json, Object, ParserError};
+use serde::{Deserialize, Deserializer};
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+use url::form_urlencoded;
 
 pub const API_URL : &'static str = "https://dictionary.yandex.net/api/v1/dicservice.json";
 
+bitflags! {
+    // Flags of `lookup` request, see API documentation for details.
+    #[derive(Default)]
+    pub struct LookupFlags: u32 {
+        // Filter out words unsuitable for children.
+        const FAMILY = 0x0001;
+        // Search word forms.
+        const MORPHO = 0x0004;
+        // Keep translations with the same part of speech as the word.
+        const POS_FILTER = 0x0008;
+    }
+}
+
+// Optional parameters of `lookup` request.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct LookupOptions {
+    pub flags: LookupFlags,
+    // Language of the interface. When set, `pos` fields of the result hold
+    // names of parts of speech in this language (Russian ones for "ru").
+    pub ui: Option<String>,
+}
+
+impl LookupOptions {
+    // These options over `defaults`, each field on its own: flags replace
+    // default ones unless empty, they aren't OR'd, and `ui` replaces the
+    // default one unless `None`. So options of a call can't turn default
+    // flags off, only swap them for others.
+    pub fn with_defaults(&self, defaults: &LookupOptions) -> LookupOptions {
+        LookupOptions {
+            flags: if self.flags.is_empty() { defaults.flags } else { self.flags },
+            ui: self.ui.clone().or_else(|| defaults.ui.clone()),
+        }
+    }
+}
+
+// The list of directions changes a few times a year at most.
+pub const DEFAULT_LANGS_TTL: Duration = Duration::from_secs(24 * 60 * 60);
+
+// Longer URLs are sent as POST, servers and proxies often cut off at 2 KiB.
+pub const DEFAULT_POST_THRESHOLD: usize = 2000;
+
+// Langs with the time they were fetched and the validators of the response.
+type CachedLangs = (Instant, Vec<String>, Validators);
+
+// Clones share the transport, caches, rate limit, budget and the current
+// token, so a clone per thread or a clone in server state is enough.
+#[derive(Clone)]
 pub struct Api {
-   token: String, 
+   tokens: Vec<String>,
+   // Index of the token in use, moves on when the service rejects it.
+   key: Arc<AtomicUsize>,
+   base_url: String,
+   transport: Arc<dyn HttpTransport>,
+   langs_file: Option<(PathBuf, Duration)>,
+   langs: Arc<Mutex<Option<CachedLangs>>>,
+   langs_ttl: Duration,
+   cache: Option<Arc<Mutex<Box<dyn CacheBackend>>>>,
+   cache_policy: CachePolicy,
+   retry: RetryPolicy,
+   limiter: Option<Arc<Mutex<RateLimiter>>>,
+   quota: Option<Arc<Mutex<Quota>>>,
+   concurrency: usize,
+   timeout: Option<Duration>,
+   metrics: Option<Arc<dyn Metrics>>,
+   warnings: Option<Arc<dyn Warnings>>,
+   // Counters of the session, see `stats`.
+   stats: Arc<Mutex<Stats>>,
+   usage: Option<Arc<UsageLog>>,
+   parse_mode: ParseMode,
+   // Lookups in progress, shared by clones to send one request per word.
+   inflight: Arc<Coalescer<CacheKey, Value>>,
+   history: Option<Arc<History>>,
+   // Sent with every request, e.g. User-Agent.
+   headers: Arc<Vec<(String, String)>>,
+   // Default options of lookups by language pair.
+   pair_options: Arc<HashMap<String, LookupOptions>>,
+   post_threshold: usize,
+   middlewares: Arc<Vec<Arc<dyn Middleware>>>,
+   // Words with definitions and word lists, for suggestions on misses.
+   vocabulary: Arc<Mutex<Vocabulary>>,
+   lemmatizer: Option<Arc<dyn Lemmatizer>>,
+   #[cfg(feature = "store")]
+   store: Option<Arc<store::Store>>,
+}
+
+// Tokens are never shown, e.g. in dumps of server state, and neither are
+// values of headers.
+impl fmt::Debug for Api {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Api")
+            .field("tokens", &format_args!("[{} redacted]", self.tokens.len()))
+            .field("base_url", &self.base_url)
+            .field("cache", &self.cache.is_some())
+            .field("cache_policy", &self.cache_policy)
+            .field("concurrency", &self.concurrency)
+            .field("timeout", &self.timeout)
+            .field("parse_mode", &self.parse_mode)
+            .field("headers", &header_names(&self.headers))
+            .field("pair_options", &self.pair_options)
+            .field("post_threshold", &self.post_threshold)
+            .field("middlewares", &self.middlewares.len())
+            .finish()
+    }
+}
+
+fn header_names(headers: &[(String, String)]) -> Vec<&str> {
+    headers.iter().map(|(name, _)| name.as_str()).collect()
 }
 
 #[derive(Debug)]
 pub enum ApiError {
     InvalidEnvironmentVar(env::VarError),
+    NoTokens,
+    InvalidConfig(ConfigError),
+}
+
+impl fmt::Display for ApiError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ApiError::InvalidEnvironmentVar(ref e) => write!(f, "can't read token from environment: {}", e),
+            ApiError::NoTokens => write!(f, "no API tokens given"),
+            ApiError::InvalidConfig(ref e) => e.fmt(f),
+        }
+    }
+}
+
+impl Error for ApiError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match *self {
+            ApiError::InvalidEnvironmentVar(ref e) => Some(e),
+            ApiError::NoTokens => None,
+            ApiError::InvalidConfig(ref e) => Some(e),
+        }
+    }
 }
 
 impl Api {
     pub fn from_token(token: &str) -> Result<Api, ApiError> {
+        Api::from_tokens(&[token])
+    }
+
+    // Uses the first token until the service reports it invalid, blocked
+    // or over the daily limit, then fails over to the next one. Requests
+    // fail only when every token was rejected.
+    pub fn from_tokens(tokens: &[&str]) -> Result<Api, ApiError> {
+        if tokens.is_empty() {
+            return Err(ApiError::NoTokens);
+        }
         Ok(Api {
-            token: token.to_owned(),
+            tokens: tokens.iter().map(|token| token.to_string()).collect(),
+            key: Arc::new(AtomicUsize::new(0)),
+            base_url: API_URL.to_owned(),
+            transport: transport::default_transport(),
+            langs_file: None,
+            langs: Arc::new(Mutex::new(None)),
+            langs_ttl: DEFAULT_LANGS_TTL,
+            cache: None,
+            cache_policy: CachePolicy::Forever,
+            retry: RetryPolicy::never(),
+            limiter: None,
+            quota: None,
+            concurrency: batch::DEFAULT_CONCURRENCY,
+            timeout: None,
+            metrics: None,
+            warnings: None,
+            stats: Arc::new(Mutex::new(Stats::default())),
+            usage: None,
+            parse_mode: ParseMode::Strict,
+            inflight: Arc::new(Coalescer::new()),
+            history: None,
+            headers: Arc::new(Vec::new()),
+            pair_options: Arc::new(HashMap::new()),
+            post_threshold: DEFAULT_POST_THRESHOLD,
+            middlewares: Arc::new(Vec::new()),
+            vocabulary: Arc::new(Mutex::new(Vocabulary::default())),
+            lemmatizer: None,
+            #[cfg(feature = "store")]
+            store: None,
         })
     }
 
+    // Throttles outgoing requests, retries included, to stay within `limit`.
+    pub fn with_rate_limit(mut self, limit: RateLimit) -> Api {
+        self.limiter = Some(Arc::new(Mutex::new(RateLimiter::new(limit))));
+        self
+    }
+
+    // Stops sending requests when `budget` requests are sent during the
+    // current UTC day, further ones fail with `RequestError::RateLimited`.
+    pub fn with_daily_budget(mut self, budget: u32) -> Api {
+        self.quota = Some(Arc::new(Mutex::new(Quota::new(budget))));
+        self
+    }
+
+    // Same as `with_daily_budget`, but the count is kept in a file, so it
+    // survives restarts and is shared with other processes using the file.
+    pub fn with_quota_file<P: AsRef<Path>>(mut self, path: P, budget: u32) -> Api {
+        self.quota = Some(Arc::new(Mutex::new(Quota::with_file(budget, path.as_ref()))));
+        self
+    }
+
+    // Adds counts of requests and cache use to the log at `path` by UTC
+    // day, see `stats::UsageLog` to read it.
+    pub fn with_usage_log<P: AsRef<Path>>(mut self, path: P) -> Api {
+        self.usage = Some(Arc::new(UsageLog::new(path)));
+        self
+    }
+
+    // Counters since the `Api` was created, shared by its clones.
+    pub fn stats(&self) -> Stats {
+        unwind::lock(&self.stats).clone()
+    }
+
+    fn count(&self, stats: Stats) {
+        unwind::lock(&self.stats).add(&stats);
+        if let Some(ref usage) = self.usage {
+            usage.add(unix_now(), &stats);
+        }
+    }
+
+    // Requests left of the daily budget, `None` without a budget. It's an
+    // estimate: the service may count the day differently.
+    pub fn remaining_estimate(&self) -> Option<u32> {
+        self.quota.as_ref().map(|quota| unwind::lock(quota).remaining(unix_now()))
+    }
+
+    // Repeats requests failed for transient reasons according to `policy`.
+    pub fn with_retry(mut self, policy: RetryPolicy) -> Api {
+        self.retry = policy;
+        self
+    }
+
+    // Remembers up to `capacity` recent answers of `lookup` and `lookup_def`.
+    pub fn with_cache(self, capacity: usize) -> Api {
+        let cache: LruCache<CacheKey, Value> = LruCache::new(capacity);
+        self.with_cache_backend(cache)
+    }
+
+    // Stores answers of `lookup` and `lookup_def` in a custom storage,
+    // e.g. `cache::FileCache` to keep them between runs.
+    pub fn with_cache_backend<C: CacheBackend + 'static>(mut self, cache: C) -> Api {
+        self.cache = Some(Arc::new(Mutex::new(Box::new(cache))));
+        self
+    }
+
+    // Sets how old cached answers are used, they never get old by default.
+    pub fn with_cache_policy(mut self, policy: CachePolicy) -> Api {
+        self.cache_policy = policy;
+        self
+    }
+
+    // Adds `words` of language `lang` to the vocabulary of suggestions,
+    // from the most frequent ones. Words found by lookups are added anyway.
+    pub fn with_word_list(self, lang: &str, words: &[&str]) -> Api {
+        {
+            let mut vocabulary = unwind::lock(&self.vocabulary);
+            for word in words {
+                vocabulary.add(lang, word);
+            }
+        }
+        self
+    }
+
+    // Sends requests to another endpoint instead of `API_URL`,
+    // e.g. to a mock server or a proxy.
+    pub fn with_base_url(mut self, base_url: &str) -> Api {
+        self.base_url = base_url.trim_end_matches('/').to_owned();
+        self
+    }
+
+    // Replaces the default transport with hyper client, e.g. to tune its
+    // connection pool. It speaks plain HTTP only, see `with_base_url`.
+    #[cfg(feature = "hyper")]
+    pub fn with_client(self, client: Client) -> Api {
+        self.with_transport(HyperTransport::from_client(client))
+    }
+
+    // Sends requests through HTTP proxy instead of the one set by
+    // HTTPS_PROXY or HTTP_PROXY environment variables.
+    #[cfg(feature = "ureq")]
+    pub fn with_proxy(self, host: &str, port: u16) -> Api {
+        self.with_transport(UreqTransport::with_proxy(host, port))
+    }
+
+    #[cfg(all(feature = "hyper", not(feature = "ureq")))]
+    pub fn with_proxy(self, host: &str, port: u16) -> Api {
+        self.with_transport(HyperTransport::with_proxy(host, port))
+    }
+
+    // Sends requests through a custom HTTP implementation.
+    pub fn with_transport<T: HttpTransport + 'static>(mut self, transport: T) -> Api {
+        self.transport = Arc::new(transport);
+        self
+    }
+
+    // Keeps `getLangs` result in a JSON file between runs. The file is reused
+    // while it's younger than `max_age` and rewritten after every fetch.
+    pub fn with_langs_file<P: AsRef<Path>>(mut self, path: P, max_age: Duration) -> Api {
+        self.langs_file = Some((path.as_ref().to_owned(), max_age));
+        self
+    }
+
+    // Bounds time of every request including connection, retries get
+    // the same time again. Without it a hung connection blocks forever.
+    pub fn with_timeout(mut self, timeout: Duration) -> Api {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    // Reports every request sent to `metrics`.
+    pub fn with_metrics<M: Metrics + 'static>(mut self, metrics: M) -> Api {
+        self.metrics = Some(Arc::new(metrics));
+        self
+    }
+
+    // Passes retries, key failovers, skipped entries and stale answers
+    // to `warnings`, e.g. a closure, as they happen.
+    pub fn with_warnings<W: Warnings + 'static>(mut self, warnings: W) -> Api {
+        self.warnings = Some(Arc::new(warnings));
+        self
+    }
+
+    // `warning` is built only if there is a sink. A panic of the sink is
+    // dropped, warnings don't fail calls.
+    fn warn<F: FnOnce() -> Warning>(&self, warning: F) {
+        if let Some(ref warnings) = self.warnings {
+            let _ = unwind::catch("warnings", || warnings.warn(&warning()));
+        }
+    }
+
+    // How long `get_langs` result is kept in memory, zero disables it.
+    pub fn with_langs_ttl(mut self, ttl: Duration) -> Api {
+        self.langs_ttl = ttl;
+        self
+    }
+
+    // Lenient mode keeps what's readable of answers of unexpected shape,
+    // see `lookup_def_with_report` for what's skipped.
+    pub fn with_parse_mode(mut self, mode: ParseMode) -> Api {
+        self.parse_mode = mode;
+        self
+    }
+
+    // Appends every successful lookup to the journal at `path`,
+    // see `history::History` to read it.
+    pub fn with_history<P: AsRef<Path>>(mut self, path: P) -> Api {
+        self.history = Some(Arc::new(History::new(path)));
+        self
+    }
+
+    // Sends the header with every request, e.g. to pass tracing IDs.
+    // A header of the same name set before is replaced, names are
+    // case-insensitive.
+    pub fn with_header(mut self, name: &str, value: &str) -> Api {
+        {
+            let headers = Arc::make_mut(&mut self.headers);
+            headers.retain(|(other, _)| !other.eq_ignore_ascii_case(name));
+            headers.push((name.to_owned(), value.to_owned()));
+        }
+        self
+    }
+
+    // Options of every lookup in `lang`, merged under options of a call
+    // as `LookupOptions::with_defaults` does. Single, batch and cache
+    // warming lookups alike get them. Options set before for the pair
+    // are replaced.
+    pub fn default_options_for<L: Into<LangPair>>(mut self, lang: L, options: LookupOptions) -> Api {
+        Arc::make_mut(&mut self.pair_options).insert(lang.into().as_str().to_owned(), options);
+        self
+    }
+
+    // Options of a call in `lang` with defaults of the pair.
+    fn options_for(&self, lang: &LangPair, options: &LookupOptions) -> LookupOptions {
+        match self.pair_options.get(lang.as_str()) {
+            Some(defaults) => options.with_defaults(defaults),
+            None => options.clone(),
+        }
+    }
+
+    // Identifies the client instead of the default User-Agent of the transport.
+    pub fn with_user_agent(self, user_agent: &str) -> Api {
+        self.with_header("User-Agent", user_agent)
+    }
+
+    // Requests with URLs longer than `threshold` bytes, e.g. lookups of
+    // long texts, are sent as POST with urlencoded form instead.
+    pub fn with_post_threshold(mut self, threshold: usize) -> Api {
+        self.post_threshold = threshold;
+        self
+    }
+
+    // Runs every request through `middleware`. Middlewares added later are
+    // nearer to the transport, all of them go before the built-in retries,
+    // throttling, logging and metrics, so they see a request once.
+    pub fn with_middleware<M: Middleware + 'static>(mut self, middleware: M) -> Api {
+        Arc::make_mut(&mut self.middlewares).push(Arc::new(middleware));
+        self
+    }
+
     pub fn from_env(var: &str) -> Result<Api, ApiError> {
         let token = match env::var(var) {
             Ok(tok) => tok,
@@ -34,9 +569,61 @@ impl Api {
         };
         Self::from_token(&token)
     }
+
+    // Uses `~/.config/yadict/config.toml` and the environment,
+    // see `config::Config::load`.
+    pub fn from_config() -> Result<Api, ApiError> {
+        let config = Config::load().map_err(ApiError::InvalidConfig)?;
+        Api::from_settings(&config)
+    }
+
+    // Configured by the environment alone, see `config::Config::from_env`.
+    pub fn from_env_config() -> Result<Api, ApiError> {
+        let config = Config::from_env().map_err(ApiError::InvalidConfig)?;
+        Api::from_settings(&config)
+    }
+
+    // Takes the token, the cache directory, the history, the endpoint,
+    // the timeout and the budget of `config`. The default direction and
+    // flags are for callers, see `Config::lang_pair` and `Config::options`.
+    pub fn from_settings(config: &Config) -> Result<Api, ApiError> {
+        let token = match config.token {
+            Some(ref token) => token,
+            None => return Err(ApiError::NoTokens),
+        };
+        let mut api = Api::from_token(token)?;
+        if let Some(ref dir) = config.cache_dir {
+            api = api.with_cache_backend(FileCache::new(dir));
+        }
+        if let Some(days) = config.cache_max_age_days {
+            api = api.with_cache_policy(CachePolicy::StaleIfError(Duration::from_secs(days * 86400)));
+        }
+        if let Some(ref url) = config.base_url {
+            api = api.with_base_url(url);
+        }
+        if let Some(timeout) = config.timeout_ms {
+            api = api.with_timeout(Duration::from_millis(timeout));
+        }
+        if let Some(budget) = config.daily_budget {
+            api = api.with_daily_budget(budget);
+        }
+        #[cfg(feature = "store")]
+        {
+            if let Some(path) = store::Store::default_path().filter(|_| config.store) {
+                // Like the history, the store must not stop lookups
+                match store::Store::open(&path) {
+                    Ok(store) => api = api.with_store(store),
+                    Err(e) => log_debug!("can't open dictionary store {}: {}", path.display(), e),
+                }
+            }
+        }
+        Ok(match config.history_path() {
+            Some(path) => api.with_history(path),
+            None => api,
+        })
+    }
 }
 
-#[derive(Debug)]
 pub enum RequestError {
     KeyInvalid,
     KeyBlocked,
@@ -46,10 +633,205 @@ pub enum RequestError {
 
     InvalidDataFormat,
     UnknownError(u64),
+    // Unsuccessful HTTP status without an API error code, e.g. from a proxy.
+    HttpStatus(u16),
+    // Request was not sent because of the client-side rate limit.
+    RateLimited,
+    // No response in time set by `Api::with_timeout` or `LookupRequest::timeout`.
+    Timeout,
+    // The call was stopped with `CancellationToken`.
+    Cancelled,
+    // Not sent while `middleware::CircuitBreaker` sees the service failing.
+    CircuitOpen,
+    // A word of a batch wasn't looked up, see `BatchOptions`.
+    Skipped(SkipReason),
+    // A middleware, cache backend, transport or other user hook panicked,
+    // with what it was and the message of the panic.
+    CallbackPanicked(String),
+    #[cfg(feature = "hyper")]
     HyperError(HyperError),
     IOError(IOError),
     EncodingError(Utf8Error),
-    ParseError(ParserError),
+    ParseError(serde_json::Error),
+    #[cfg(feature = "async")]
+    ReqwestError(reqwest::Error),
+    #[cfg(feature = "ureq")]
+    UreqError(Box<ureq::Transport>),
+    // The error with the request it happened to, see `RequestError::root`.
+    WithContext(Box<ErrorContext>, Box<RequestError>),
+}
+
+// What was requested when an error happened.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ErrorContext {
+    // API method like "lookup" or "getLangs".
+    pub endpoint: String,
+    pub lang: Option<String>,
+    pub text: Option<String>,
+    // HTTP status if a response was received.
+    pub status: Option<u16>,
+}
+
+impl fmt::Display for ErrorContext {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.endpoint)?;
+        if let Some(ref text) = self.text {
+            write!(f, " of '{}'", text)?;
+        }
+        if let Some(ref lang) = self.lang {
+            write!(f, " in {}", lang)?;
+        }
+        if let Some(status) = self.status {
+            write!(f, " (HTTP {})", status)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for RequestError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            RequestError::KeyInvalid => write!(f, "API key is invalid"),
+            RequestError::KeyBlocked => write!(f, "API key is blocked"),
+            RequestError::DailyLimitExceeded => write!(f, "daily limit of requests is exceeded"),
+            RequestError::TextTooLong => write!(f, "text is too long"),
+            RequestError::LangNotSupported => write!(f, "language pair is not supported"),
+            RequestError::InvalidDataFormat => write!(f, "unexpected format of response"),
+            RequestError::UnknownError(code) => write!(f, "unknown error with code {}", code),
+            RequestError::HttpStatus(status) => write!(f, "unexpected HTTP status {}", status),
+            RequestError::RateLimited => write!(f, "rate limit of the client is reached"),
+            RequestError::Timeout => write!(f, "request timed out"),
+            RequestError::Cancelled => write!(f, "request was cancelled"),
+            RequestError::CircuitOpen => write!(f, "requests are paused after repeated failures"),
+            RequestError::Skipped(reason) => write!(f, "skipped: {}", reason),
+            RequestError::CallbackPanicked(ref message) => write!(f, "callback panicked: {}", message),
+            #[cfg(feature = "hyper")]
+            RequestError::HyperError(ref e) => write!(f, "HTTP error: {}", e),
+            RequestError::IOError(ref e) => write!(f, "I/O error: {}", e),
+            RequestError::EncodingError(ref e) => write!(f, "encoding error: {}", e),
+            RequestError::ParseError(ref e) => write!(f, "JSON error: {}", e),
+            #[cfg(feature = "async")]
+            RequestError::ReqwestError(ref e) => write!(f, "HTTP error: {}", e),
+            #[cfg(feature = "ureq")]
+            // Not the error itself, its URL contains the key
+            RequestError::UreqError(ref e) => match e.message() {
+                Some(message) => write!(f, "HTTP error: {}: {}", e.kind(), message),
+                None => write!(f, "HTTP error: {}", e.kind()),
+            },
+            RequestError::WithContext(ref context, ref e) => write!(f, "{}: {}", context, e),
+        }
+    }
+}
+
+// Like derived one, but errors of ureq are shown without their URLs,
+// which hold the key.
+impl fmt::Debug for RequestError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            RequestError::KeyInvalid => f.write_str("KeyInvalid"),
+            RequestError::KeyBlocked => f.write_str("KeyBlocked"),
+            RequestError::DailyLimitExceeded => f.write_str("DailyLimitExceeded"),
+            RequestError::TextTooLong => f.write_str("TextTooLong"),
+            RequestError::LangNotSupported => f.write_str("LangNotSupported"),
+            RequestError::InvalidDataFormat => f.write_str("InvalidDataFormat"),
+            RequestError::UnknownError(code) => f.debug_tuple("UnknownError").field(&code).finish(),
+            RequestError::HttpStatus(status) => f.debug_tuple("HttpStatus").field(&status).finish(),
+            RequestError::RateLimited => f.write_str("RateLimited"),
+            RequestError::Timeout => f.write_str("Timeout"),
+            RequestError::Cancelled => f.write_str("Cancelled"),
+            RequestError::CircuitOpen => f.write_str("CircuitOpen"),
+            RequestError::Skipped(reason) => f.debug_tuple("Skipped").field(&reason).finish(),
+            RequestError::CallbackPanicked(ref message) => f.debug_tuple("CallbackPanicked").field(message).finish(),
+            #[cfg(feature = "hyper")]
+            RequestError::HyperError(ref e) => f.debug_tuple("HyperError").field(e).finish(),
+            RequestError::IOError(ref e) => f.debug_tuple("IOError").field(e).finish(),
+            RequestError::EncodingError(ref e) => f.debug_tuple("EncodingError").field(e).finish(),
+            RequestError::ParseError(ref e) => f.debug_tuple("ParseError").field(e).finish(),
+            #[cfg(feature = "async")]
+            RequestError::ReqwestError(ref e) => f.debug_tuple("ReqwestError").field(e).finish(),
+            #[cfg(feature = "ureq")]
+            RequestError::UreqError(_) => f.debug_tuple("UreqError").field(&format_args!("{}", self)).finish(),
+            RequestError::WithContext(ref context, ref e) =>
+                f.debug_tuple("WithContext").field(context).field(e).finish(),
+        }
+    }
+}
+
+impl RequestError {
+    // Short stable name of the variant, e.g. for metric labels.
+    pub fn kind(&self) -> &'static str {
+        match *self {
+            RequestError::WithContext(_, ref e) => e.kind(),
+            RequestError::KeyInvalid => "key_invalid",
+            RequestError::KeyBlocked => "key_blocked",
+            RequestError::DailyLimitExceeded => "daily_limit_exceeded",
+            RequestError::TextTooLong => "text_too_long",
+            RequestError::LangNotSupported => "lang_not_supported",
+            RequestError::InvalidDataFormat => "invalid_data_format",
+            RequestError::UnknownError(_) => "unknown_error",
+            RequestError::HttpStatus(_) => "http_status",
+            RequestError::RateLimited => "rate_limited",
+            RequestError::Timeout => "timeout",
+            RequestError::Cancelled => "cancelled",
+            RequestError::CircuitOpen => "circuit_open",
+            RequestError::Skipped(_) => "skipped",
+            RequestError::CallbackPanicked(_) => "callback_panicked",
+            #[cfg(feature = "hyper")]
+            RequestError::HyperError(_) => "http",
+            RequestError::IOError(_) => "io",
+            RequestError::EncodingError(_) => "encoding",
+            RequestError::ParseError(_) => "parse",
+            #[cfg(feature = "async")]
+            RequestError::ReqwestError(_) => "http",
+            #[cfg(feature = "ureq")]
+            RequestError::UreqError(_) => "http",
+        }
+    }
+
+    // Attaches what was requested, the context the error had is replaced.
+    pub fn with_context(self, context: ErrorContext) -> RequestError {
+        RequestError::WithContext(Box::new(context), Box::new(self.into_root()))
+    }
+
+    pub fn context(&self) -> Option<&ErrorContext> {
+        match *self {
+            RequestError::WithContext(ref context, _) => Some(context),
+            _ => None,
+        }
+    }
+
+    // The error itself without its context, e.g. to match the variant.
+    pub fn root(&self) -> &RequestError {
+        match *self {
+            RequestError::WithContext(_, ref e) => e.root(),
+            ref e => e,
+        }
+    }
+
+    fn into_root(self) -> RequestError {
+        match self {
+            RequestError::WithContext(_, e) => e.into_root(),
+            e => e,
+        }
+    }
+}
+
+impl Error for RequestError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match *self {
+            #[cfg(feature = "hyper")]
+            RequestError::HyperError(ref e) => Some(e),
+            RequestError::IOError(ref e) => Some(e),
+            RequestError::EncodingError(ref e) => Some(e),
+            RequestError::ParseError(ref e) => Some(e),
+            #[cfg(feature = "async")]
+            RequestError::ReqwestError(ref e) => Some(e),
+            #[cfg(feature = "ureq")]
+            RequestError::UreqError(ref e) => Some(&**e),
+            RequestError::WithContext(_, ref e) => Some(&**e),
+            _ => None,
+        }
+    }
 }
 
 impl From<u64> for RequestError {
@@ -66,20 +848,29 @@ impl From<u64> for RequestError {
     }
 }
 
+#[cfg(feature = "hyper")]
 impl From<HyperError> for RequestError {
     fn from(e: HyperError) -> Self {
         RequestError::HyperError(e)
     }
 }
 
+#[cfg(feature = "async")]
+impl From<reqwest::Error> for RequestError {
+    // The URL of the request holds the key
+    fn from(e: reqwest::Error) -> Self {
+        RequestError::ReqwestError(e.without_url())
+    }
+}
+
 impl From<IOError> for RequestError {
     fn from(e: IOError) -> Self {
         RequestError::IOError(e)
     }
 }
 
-impl From<ParserError> for RequestError {
-    fn from(e: ParserError) -> Self {
+impl From<serde_json::Error> for RequestError {
+    fn from(e: serde_json::Error) -> Self {
         RequestError::ParseError(e)
     }
 }
@@ -90,140 +881,1828 @@ impl From<Utf8Error> for RequestError {
     }
 }
 
+// Field names follow the API schema, so results can be re-emitted as JSON
+// of the same shape the service returns.
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct Def {
+    #[serde(flatten)]
     pub word: Word,
+    #[serde(rename = "tr")]
     pub trans: Vec<Word>,
 }
 
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct Word {
     pub text: String,
-    pub pos: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pos: Option<PartOfSpeech>,
+    #[serde(default, skip_serializing_if = "Option::is_none", deserialize_with = "deserialize_ts")]
     pub ts: Option<String>,
+    // Grammar of translations as the service reports it in the language of
+    // the translation: gender ("ж"), aspect of verbs ("сов") and plural form.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub gen: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub asp: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub num: Option<String>,
+    // Frequency ranking, bigger is more common.
+    #[serde(default, skip_serializing_if = "Option::is_none", deserialize_with = "deserialize_fr")]
+    pub fr: Option<u32>,
+    // Synonyms, meanings and examples are filled for translations only.
+    #[serde(default, skip_serializing_if = "Vec::is_empty", deserialize_with = "deserialize_list")]
+    pub syn: Vec<Word>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty", deserialize_with = "deserialize_list")]
+    pub mean: Vec<Word>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty", deserialize_with = "deserialize_list")]
+    pub ex: Vec<Example>,
 }
 
-fn json_to_word(object: &Object) -> Result<Word, RequestError> {
-    let text = match object.get("text") {
-        Some(&Json::String(ref s)) => s.to_owned(),
-        _ => return Err(RequestError::InvalidDataFormat),
-    };
-    let pos = match object.get("pos") {
-        Some(&Json::String(ref s)) => Some(s.to_owned()),
-        _ => None,
-    };
-    let ts = match object.get("ts") {
-        Some(&Json::String(ref s)) => Some(s.to_owned()),
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Example {
+    pub text: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty", with = "text_list")]
+    pub tr: Vec<String>,
+}
+
+// Blank transcriptions are treated as missing.
+fn deserialize_ts<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<String>, D::Error> {
+    let ts: Option<String> = Option::deserialize(deserializer)?;
+    Ok(ts.and_then(|s| {
+        let s = s.trim();
+        if s.is_empty() { None } else { Some(s.to_owned()) }
+    }))
+}
+
+// Unknown fields are ignored everywhere, as the service adds them from
+// time to time. Optional lists may come as `null` too.
+fn deserialize_list<'de, D, T>(deserializer: D) -> Result<Vec<T>, D::Error>
+    where D: Deserializer<'de>, T: Deserialize<'de> {
+    let list: Option<Vec<T>> = Option::deserialize(deserializer)?;
+    Ok(list.unwrap_or_default())
+}
+
+// Frequencies seen as `10`, `10.0` and `"10"`, anything else is dropped
+// rather than failing the whole answer.
+fn deserialize_fr<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<u32>, D::Error> {
+    let fr = Value::deserialize(deserializer)?;
+    let fr = match fr {
+        Value::Number(ref n) => n.as_u64().or_else(|| n.as_f64().filter(|f| *f >= 0.0).map(|f| f.round() as u64)),
+        Value::String(ref s) => s.trim().parse().ok(),
         _ => None,
     };
-    Ok(Word {
-        text: text,
-        pos: pos,
-        ts: ts,
-    })
+    Ok(fr.filter(|fr| *fr <= u32::MAX as u64).map(|fr| fr as u32))
 }
 
-impl Api {
+// Translations of examples come as `[{"text": ...}]`, but only text matters.
+mod text_list {
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
 
-    fn fetch_json(&self, url: &str) -> Result<Json, RequestError> {
-        let url = format!("{}/{}", API_URL, url);
-        let client = Client::new();
-        let mut response = try!(client.get(&url).send());
-        let mut s = String::new();
-        try!(response.read_to_string(&mut s));
-        let json = try!(Json::from_str(&s));
-        if response.status != StatusCode::Ok {
-            let object = try!(json.as_object().ok_or(RequestError::InvalidDataFormat));
-            let code_object = try!(object.get("code").ok_or(RequestError::InvalidDataFormat));
-            let code = try!(code_object.as_u64().ok_or(RequestError::InvalidDataFormat));
-            Err(RequestError::from(code))
-        } else {
-            Ok(json)
+    #[derive(Serialize, Deserialize)]
+    struct Text<T> {
+        text: T,
+    }
+
+    pub fn serialize<S: Serializer>(list: &[String], serializer: S) -> Result<S::Ok, S::Error> {
+        let items: Vec<Text<&str>> = list.iter().map(|s| Text { text: s.as_str() }).collect();
+        items.serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D)
+        -> Result<Vec<String>, D::Error> {
+        let items: Option<Vec<Text<String>>> = Option::deserialize(deserializer)?;
+        Ok(items.unwrap_or_default().into_iter().map(|item| item.text).collect())
+    }
+}
+
+#[derive(Deserialize)]
+struct LookupBody {
+    def: Vec<Def>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct LangsFile {
+    fetched: u64,
+    langs: Vec<String>,
+    #[serde(default)]
+    validators: Validators,
+}
+
+fn normalize_headword(text: &str) -> String {
+    text.trim().to_lowercase()
+}
+
+// Merges definitions of several lookups into one list. Definitions are grouped
+// by normalized headword and part of speech, translations are deduplicated
+// by their normalized text and part of speech.
+// Order of groups and translations follows the first appearance in `defs`.
+pub fn merge_definitions(defs: &[Def]) -> Vec<Def> {
+    let mut result: Vec<Def> = Vec::new();
+    for def in defs {
+        let key = normalize_headword(&def.word.text);
+        let position = result.iter().position(|d| {
+            normalize_headword(&d.word.text) == key && d.word.pos == def.word.pos
+        });
+        let merged = match position {
+            Some(idx) => &mut result[idx],
+            None => {
+                result.push(Def {
+                    word: def.word.clone(),
+                    trans: Vec::new(),
+                });
+                result.last_mut().unwrap()
+            },
+        };
+        if merged.word.ts.is_none() {
+            merged.word.ts = def.word.ts.clone();
+        }
+        for tr in &def.trans {
+            let key = normalize_headword(&tr.text);
+            let found = merged.trans.iter_mut().find(|t| normalize_headword(&t.text) == key && t.pos == tr.pos);
+            match found {
+                Some(merged_tr) => absorb_translation(merged_tr, tr),
+                None => merged.trans.push(tr.clone()),
+            }
         }
     }
+    result
+}
 
-    pub fn get_langs(&self) -> Result<Vec<String>, RequestError> {
-        let url = format!("getLangs?key={}", &self.token);
-        let json = try!(self.fetch_json(&url));
-        let array = try!(json.as_array().ok_or(RequestError::InvalidDataFormat));
-        let mut result = Vec::new();
-        for obj in array {
-            if let Some(s) = obj.as_string() {
-                result.push(s.to_owned());
-            }
-        }
-        Ok(result)
-    }
-
-    pub fn lookup(&self, lang: &str, text: &str) -> Result<Json, RequestError> {
-        let url = format!("lookup?key={}&lang={}&text={}", &self.token, lang, text);
-        let json = try!(self.fetch_json(&url));
-        let object = try!(json.as_object().ok_or(RequestError::InvalidDataFormat));
-        Ok(Json::Object(object.to_owned()))
-    }
-
-    pub fn lookup_def(&self, lang: &str, text: &str) -> Result<Vec<Def>, RequestError> {
-        let json = try!(self.lookup(lang, text));
-        let mut result = Vec::new();
-        let object = try!(json.as_object().ok_or(RequestError::InvalidDataFormat));
-        let def_obj = try!(object.get("def").ok_or(RequestError::InvalidDataFormat));
-        let def_arr = try!(def_obj.as_array().ok_or(RequestError::InvalidDataFormat));
-        for item in def_arr {
-            let item = try!(item.as_object().ok_or(RequestError::InvalidDataFormat));
-            let word = try!(json_to_word(item));
-            let mut trans = Vec::new();
-            let trans_obj = try!(item.get("tr").ok_or(RequestError::InvalidDataFormat));
-            let trans_arr = try!(trans_obj.as_array().ok_or(RequestError::InvalidDataFormat));
-            for tr in trans_arr {
-                let tr_obj = try!(tr.as_object().ok_or(RequestError::InvalidDataFormat));
-                let tr_word = try!(json_to_word(tr_obj));
-                trans.push(tr_word);
-            }
-            let def = Def {
-                word: word,
-                trans: trans,
-            };
-            result.push(def);
+// Adds synonyms, meanings and examples of `tr` missing in `merged` and
+// keeps the higher frequency of the two.
+fn absorb_translation(merged: &mut Word, tr: &Word) {
+    merged.fr = cmp::max(merged.fr, tr.fr);
+    for syn in &tr.syn {
+        if !merged.syn.iter().any(|s| normalize_headword(&s.text) == normalize_headword(&syn.text)) {
+            merged.syn.push(syn.clone());
+        }
+    }
+    for mean in &tr.mean {
+        if !merged.mean.iter().any(|m| normalize_headword(&m.text) == normalize_headword(&mean.text)) {
+            merged.mean.push(mean.clone());
+        }
+    }
+    for ex in &tr.ex {
+        if !merged.ex.iter().any(|e| normalize_headword(&e.text) == normalize_headword(&ex.text)) {
+            merged.ex.push(ex.clone());
         }
-        Ok(result)
     }
 }
 
-#[cfg(test)]
-mod tests {
+// All definitions of a lookup as one list of translations, see `Def::merge`.
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct MergedEntry {
+    // Headword and transcription of the first definition which has them.
+    pub text: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ts: Option<String>,
+    // Parts of speech of the definitions in order of appearance.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub pos: Vec<PartOfSpeech>,
+    #[serde(rename = "tr")]
+    pub trans: Vec<Word>,
+}
+
+impl Def {
+    // Unlike `merge_definitions`, which keeps a definition per part of
+    // speech, puts translations of all `defs` together. A translation met
+    // under several definitions is kept once with synonyms, meanings and
+    // examples of all of them and the highest `fr`. The most frequent
+    // translations go first, ties keep the order of the answer.
+    pub fn merge(defs: &[Def]) -> MergedEntry {
+        let mut entry = MergedEntry {
+            text: defs.first().map(|def| def.word.text.clone()).unwrap_or_default(),
+            ts: defs.iter().filter_map(|def| def.word.ts.clone()).next(),
+            pos: Vec::new(),
+            trans: Vec::new(),
+        };
+        for def in defs {
+            if let Some(ref pos) = def.word.pos {
+                if !entry.pos.contains(pos) {
+                    entry.pos.push(pos.clone());
+                }
+            }
+            for tr in &def.trans {
+                let key = normalize_headword(&tr.text);
+                match entry.trans.iter_mut().find(|t| normalize_headword(&t.text) == key) {
+                    Some(merged) => {
+                        merged.fr = cmp::max(merged.fr, tr.fr);
+                        absorb_translation(merged, tr);
+                    },
+                    None => entry.trans.push(tr.clone()),
+                }
+            }
+        }
+        entry.trans.sort_by_key(|tr| cmp::Reverse(tr.fr.unwrap_or(0)));
+        entry
+    }
+}
 
-    use super::Api;
+// Upper bound of per-word requests made by `lookup_phrase` fallback.
+pub const MAX_PHRASE_WORDS: usize = 8;
 
-    #[test]
-    fn check_get_langs() {
-        let api = Api::from_env("YANDEX_DICTIONARY_TOKEN").unwrap();
-        let langs = api.get_langs().unwrap();
-        assert!(langs.contains(&"en-ru".to_string()));
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum PhraseMode {
+    Phrase,
+    Words,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PhraseLookup {
+    pub mode: PhraseMode,
+    // Definitions of the whole phrase, empty in `Words` mode.
+    pub defs: Vec<Def>,
+    // Definitions of distinct words of the phrase, empty in `Phrase` mode.
+    pub words: Vec<(String, Vec<Def>)>,
+}
+
+// What the service thinks of a key, see `Api::validate_key`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum KeyStatus {
+    Valid,
+    Invalid,
+    Blocked,
+}
+
+// Builds URL of API `method` with percent-encoded query `params`.
+fn api_url<V: AsRef<str>>(base_url: &str, method: &str, params: &[(&str, V)]) -> String {
+    let query = form_urlencoded::Serializer::new(String::new())
+        .extend_pairs(params)
+        .finish();
+    format!("{}/{}?{}", base_url, method, query)
+}
+
+// Unset options are omitted to leave API defaults in effect.
+fn lookup_params(token: &str, lang: &str, text: &str, options: &LookupOptions)
+    -> Vec<(&'static str, String)> {
+    let mut params = vec![
+        ("key", token.to_owned()),
+        ("lang", lang.to_owned()),
+        ("text", text.to_owned()),
+    ];
+    if !options.flags.is_empty() {
+        params.push(("flags", options.flags.bits().to_string()));
     }
+    if let Some(ref ui) = options.ui {
+        params.push(("ui", ui.to_owned()));
+    }
+    params
+}
 
-    #[test]
-    fn check_lookup() {
-        let api = Api::from_env("YANDEX_DICTIONARY_TOKEN").unwrap();
-        api.lookup("en-ru", "rust").unwrap();
+// Error responses of the API carry the reason in the `code` field of JSON body.
+fn response_to_json(status: u16, body: &str) -> Result<Value, RequestError> {
+    if status != 200 {
+        let code = serde_json::from_str::<Value>(body).ok()
+            .and_then(|json| json.get("code").and_then(Value::as_u64));
+        return match code {
+            Some(code) => Err(RequestError::from(code)),
+            None => Err(RequestError::HttpStatus(status)),
+        };
     }
+    let json: Value = serde_json::from_str(body)?;
+    Ok(json)
+}
 
-    #[test]
-    fn check_lookup_def() {
-        let api = Api::from_env("YANDEX_DICTIONARY_TOKEN").unwrap();
-        for def in api.lookup_def("en-ru", "rust").unwrap() {
-            assert_eq!(def.word.text, "rust");
-            assert_eq!(def.word.ts.as_ref().unwrap(), "r\u{28c}st");
-            assert!(def.trans.len() > 0);
-            let text = &def.trans[0].text;
-            let pos = def.word.pos.as_ref().unwrap();
-            if pos == "noun" {
-                assert_eq!(text, "\u{440}\u{436}\u{430}\u{432}\u{447}\u{438}\u{43d}\u{430}");
-            } else if pos == "verb" {
-                assert_eq!(text, "\u{440}\u{436}\u{430}\u{432}\u{435}\u{442}\u{44c}");
-            } else if pos == "adjective" {
-                assert_eq!(text, "\u{440}\u{436}\u{430}\u{432}\u{44b}\u{439}");
-            } else {
-                panic!("Unknown pos of 'rust' word: {}", pos);
-            }
+// Valid JSON of unexpected shape is reported as `InvalidDataFormat`.
+fn decode<T: DeserializeOwned>(json: Value) -> Result<T, RequestError> {
+    serde_json::from_value(json).map_err(|_| RequestError::InvalidDataFormat)
+}
+
+fn json_to_langs(json: Value) -> Result<Vec<String>, RequestError> {
+    decode(json)
+}
+
+fn json_to_defs(json: Value) -> Result<Vec<Def>, RequestError> {
+    let body: LookupBody = decode(json)?;
+    Ok(body.def)
+}
+
+// Calls the cache backend. If it panics, the call fails and the cache is
+// cleared, as the backend may have been left half-updated.
+fn with_cache<T, F>(cache: &Mutex<Box<dyn CacheBackend>>, call: F) -> Result<T, RequestError>
+    where F: FnOnce(&mut dyn CacheBackend) -> T {
+    let clear = |cache: &mut Box<dyn CacheBackend>| {
+        let _ = unwind::catch("cache backend", || cache.clear());
+    };
+    let mut cache = unwind::lock_or_reset(cache, clear);
+    let result = unwind::catch("cache backend", || call(&mut **cache));
+    if result.is_err() {
+        clear(&mut cache);
+    }
+    result
+}
+
+// Sends URLs longer than `post_threshold` as POST with the query in the
+// body. Such requests aren't conditional, only short `getLangs` ones are.
+fn deliver(transport: &dyn HttpTransport, url: &str, headers: &[(String, String)], cached: &Validators,
+           post_threshold: usize) -> Result<(u16, String, Validators), RequestError> {
+    if url.len() > post_threshold {
+        if let Some(pos) = url.find('?') {
+            return unwind::catch("transport", || transport.post_form(&url[..pos], &url[pos + 1..], headers))?
+                .map(|(status, body)| (status, body, Validators::default()));
         }
     }
+    unwind::catch("transport", || transport.get_with_headers(url, headers, cached))?
+}
+
+// Hides the value of `key` parameter of API URL to keep it out of logs.
+fn redact_key(url: &str) -> String {
+    let start = match url.find("?key=").or_else(|| url.find("&key=")) {
+        Some(pos) => pos + 5,
+        None => return url.to_owned(),
+    };
+    let end = url[start..].find('&').map_or(url.len(), |pos| start + pos);
+    format!("{}***{}", &url[..start], &url[end..])
+}
+
+fn is_token_rejected(error: &RequestError) -> bool {
+    matches!(*error, RequestError::KeyInvalid | RequestError::KeyBlocked | RequestError::DailyLimitExceeded)
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0)
+}
+
+// Reads langs stored by `save_langs_file`. Any unreadable or corrupt file
+// is treated as absent.
+fn load_langs_file(path: &Path) -> Option<(u64, Vec<String>, Validators)> {
+    let file = match File::open(path) {
+        Ok(file) => file,
+        Err(_) => return None,
+    };
+    match serde_json::from_reader::<_, LangsFile>(file) {
+        Ok(stored) => Some((stored.fetched, stored.langs, stored.validators)),
+        Err(_) => None,
+    }
 }
 
+fn save_langs_file(path: &Path, fetched: u64, langs: &[String], validators: &Validators) -> Result<(), IOError> {
+    let stored = LangsFile {
+        fetched: fetched,
+        langs: langs.to_owned(),
+        validators: validators.clone(),
+    };
+    let data = serde_json::to_vec(&stored)?;
+    cache::write_file_atomically(path, &data)
+}
+
+// Answer to a conditional request.
+enum Fetched {
+    Modified(Value, Validators),
+    NotModified,
+}
+
+// How long a single call may wait for the service and where it reports.
+#[derive(Clone, Copy, Default)]
+struct Limits<'a> {
+    timeout: Option<Duration>,
+    cancel: Option<&'a CancellationToken>,
+    // Receives HTTP status of every response, e.g. for `ErrorContext`.
+    status: Option<&'a Cell<Option<u16>>>,
+}
+
+impl Api {
+
+    fn limits(&self) -> Limits<'static> {
+        Limits {
+            timeout: self.timeout,
+            cancel: None,
+            status: None,
+        }
+    }
+
+    fn fetch_json<V: AsRef<str>>(&self, method: &str, params: &[(&str, V)], limits: Limits)
+        -> Result<Value, RequestError> {
+        match self.fetch_json_conditional(method, params, limits, &Validators::default())? {
+            Fetched::Modified(json, _) => Ok(json),
+            Fetched::NotModified => Err(RequestError::HttpStatus(304)),
+        }
+    }
+
+    // Asks for the answer unless it still matches `cached` validators.
+    fn fetch_json_conditional<V: AsRef<str>>(&self, method: &str, params: &[(&str, V)], limits: Limits,
+                                             cached: &Validators) -> Result<Fetched, RequestError> {
+        let url = api_url(&self.base_url, method, params);
+        let response = self.execute(method, &url, limits, cached)?;
+        if response.status == 304 && !cached.is_empty() {
+            return Ok(Fetched::NotModified);
+        }
+        response_to_json(response.status, &response.body).map(|json| Fetched::Modified(json, response.validators))
+    }
+
+    // Repeats the request with the next tokens while they are rejected.
+    fn with_token<T, F: Fn(&str) -> Result<T, RequestError>>(&self, request: F) -> Result<T, RequestError> {
+        let mut idx = self.key.load(Ordering::SeqCst);
+        let mut tried = 1;
+        loop {
+            match request(&self.tokens[idx]) {
+                Err(ref e) if tried < self.tokens.len() && is_token_rejected(e) => {
+                    log_debug!("token #{} rejected: {}", idx, e);
+                    self.warn(|| Warning::KeyRejected {
+                        index: idx,
+                        error: e.to_string(),
+                    });
+                    let next = (idx + 1) % self.tokens.len();
+                    // Another thread may have switched it already
+                    let _ = self.key.compare_exchange(idx, next, Ordering::SeqCst, Ordering::SeqCst);
+                    idx = next;
+                    tried += 1;
+                },
+                result => return result,
+            }
+        }
+    }
+
+    // Sends the request through the middlewares of the user and then
+    // the built-in ones: retries, throttling, logging and metrics.
+    fn execute(&self, method: &str, url: &str, limits: Limits, cached: &Validators)
+        -> Result<Response, RequestError> {
+        let throttle = Throttle(self);
+        let logger = Logger(self.post_threshold);
+        let recorder = self.metrics.as_ref().map(|metrics| Recorder(&**metrics));
+        let mut chain: Vec<&dyn Middleware> = self.middlewares.iter().map(|m| &**m as &dyn Middleware).collect();
+        let retrying = Retrying(self);
+        chain.push(&retrying);
+        chain.push(&throttle);
+        chain.push(&logger);
+        if let Some(ref recorder) = recorder {
+            chain.push(recorder);
+        }
+        let counter = Counter(self);
+        chain.push(&counter);
+        let endpoint = |request: Request| {
+            let response = self.send(&request, limits);
+            if let (Some(cell), Ok(response)) = (limits.status, response.as_ref()) {
+                cell.set(Some(response.status));
+            }
+            response
+        };
+        Next::new(&chain, &endpoint).run(Request::new(method, url, &self.headers, cached, limits.cancel))
+    }
+
+    // Applies the quota and the rate limit before a request.
+    fn throttle(&self, cancel: Option<&CancellationToken>) -> Result<(), RequestError> {
+        if let Some(cancel) = cancel {
+            cancel.check()?;
+        }
+        if let Some(ref quota) = self.quota {
+            unwind::lock(quota).spend(unix_now())?;
+        }
+        if let Some(ref limiter) = self.limiter {
+            // Sleep without the lock, the permit is reserved already
+            let wait = unwind::lock(limiter).acquire(Instant::now())?;
+            if wait > Duration::from_secs(0) {
+                cancel::pause(wait, cancel)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn send(&self, request: &Request, limits: Limits) -> Result<Response, RequestError> {
+        let result = match (limits.timeout, limits.cancel) {
+            (None, None) => deliver(&*self.transport, &request.url, &request.headers, &request.validators,
+                                    self.post_threshold),
+            _ => self.get_interruptible(request, limits),
+        };
+        result.map(|(status, body, validators)| Response {
+            status: status,
+            body: body,
+            validators: validators,
+        })
+    }
+
+    // Transports can't be interrupted, so with a timeout or a cancellation
+    // token the request runs in its own thread. It's abandoned when the
+    // call gives up and ends by timeouts of the transport, see
+    // `transport::DEFAULT_READ_TIMEOUT`.
+    fn get_interruptible(&self, request: &Request, limits: Limits)
+        -> Result<(u16, String, Validators), RequestError> {
+        if limits.timeout.is_none() && limits.cancel.is_none() {
+            return deliver(&*self.transport, &request.url, &request.headers, &request.validators, self.post_threshold);
+        }
+        let (sender, receiver) = mpsc::channel();
+        let transport = self.transport.clone();
+        let url = request.url.clone();
+        let cached = request.validators.clone();
+        let headers = request.headers.clone();
+        let post_threshold = self.post_threshold;
+        thread::spawn(move || {
+            let _ = sender.send(deliver(&*transport, &url, &headers, &cached, post_threshold));
+        });
+        let deadline = limits.timeout.map(|timeout| Instant::now() + timeout);
+        loop {
+            let left = deadline.map(|deadline| deadline.saturating_duration_since(Instant::now()));
+            let wait = match (left, limits.cancel) {
+                (Some(left), Some(_)) => left.min(cancel::CANCEL_POLL),
+                (Some(left), None) => left,
+                (None, _) => cancel::CANCEL_POLL,
+            };
+            match receiver.recv_timeout(wait) {
+                Ok(result) => return result,
+                Err(mpsc::RecvTimeoutError::Timeout) => {
+                    if let Some(cancel) = limits.cancel {
+                        cancel.check()?;
+                    }
+                    if left.is_some_and(|left| left <= wait) {
+                        return Err(RequestError::Timeout);
+                    }
+                },
+                // `deliver` catches panics, but the thread may die otherwise
+                Err(mpsc::RecvTimeoutError::Disconnected) =>
+                    return Err(RequestError::CallbackPanicked("transport: worker thread ended without an answer".to_owned())),
+            }
+        }
+    }
+
+    // Answers from memory while the list fetched before is younger than
+    // the TTL set by `with_langs_ttl`. An outdated list is revalidated with
+    // a conditional request if the service gave it ETag or Last-Modified.
+    pub fn get_langs(&self) -> Result<Vec<String>, RequestError> {
+        let previous = unwind::lock(&self.langs).clone();
+        if let Some((fetched, ref langs, _)) = previous {
+            if fetched.elapsed() < self.langs_ttl {
+                return Ok(langs.clone());
+            }
+        }
+        let (langs, validators) = self.load_langs(previous.map(|(_, langs, validators)| (langs, validators)))?;
+        *unwind::lock(&self.langs) = Some((Instant::now(), langs.clone(), validators));
+        Ok(langs)
+    }
+
+    fn load_langs(&self, previous: Option<(Vec<String>, Validators)>)
+        -> Result<(Vec<String>, Validators), RequestError> {
+        if let Some((ref path, max_age)) = self.langs_file {
+            let stored = load_langs_file(path);
+            if let Some((fetched, ref langs, ref validators)) = stored {
+                if unix_now().saturating_sub(fetched) < max_age.as_secs() {
+                    return Ok((langs.clone(), validators.clone()));
+                }
+            }
+            let previous = previous.or(stored.map(|(_, langs, validators)| (langs, validators)));
+            let (langs, validators) = self.fetch_langs(previous)?;
+            // Failing to persist the list must not fail the call
+            let _ = save_langs_file(path, unix_now(), &langs, &validators);
+            Ok((langs, validators))
+        } else {
+            self.fetch_langs(previous)
+        }
+    }
+
+    // Checks the key in use with a cheap `getLangs` request, e.g. at
+    // startup instead of failing in the middle of a batch. Other tokens aren't tried
+    // and the cached list isn't used. Errors other than a rejected key
+    // are returned as is, including `DailyLimitExceeded` of a valid key.
+    pub fn validate_key(&self) -> Result<KeyStatus, RequestError> {
+        let token = &self.tokens[self.key.load(Ordering::SeqCst)];
+        match self.fetch_json("getLangs", &[("key", token)], self.limits()) {
+            Ok(_) => Ok(KeyStatus::Valid),
+            Err(RequestError::KeyInvalid) => Ok(KeyStatus::Invalid),
+            Err(RequestError::KeyBlocked) => Ok(KeyStatus::Blocked),
+            Err(e) => Err(e),
+        }
+    }
+
+    // Same list as `get_langs` parsed into pairs.
+    pub fn lang_pairs(&self) -> Result<Langs, RequestError> {
+        self.get_langs().map(|langs| Langs::from_codes(&langs))
+    }
+
+    // Checks that the pair is in the list of supported directions.
+    // Only the first call makes a request while the list is cached.
+    pub fn supports<L: Into<LangPair>>(&self, pair: L) -> Result<bool, RequestError> {
+        let pair = pair.into();
+        let langs = self.get_langs()?;
+        Ok(langs.iter().any(|lang| lang == pair.as_str()))
+    }
+
+    pub fn validate_pair(&self, pair: &LangPair) -> Result<bool, RequestError> {
+        self.supports(pair)
+    }
+
+    // Keeps `previous` list if the service confirms it's unchanged.
+    fn fetch_langs(&self, previous: Option<(Vec<String>, Validators)>)
+        -> Result<(Vec<String>, Validators), RequestError> {
+        let cached = previous.as_ref().map(|(_, validators)| validators.clone()).unwrap_or_default();
+        let fetched = self.with_token(|token| {
+            self.fetch_json_conditional("getLangs", &[("key", token)], self.limits(), &cached)
+        })?;
+        match (fetched, previous) {
+            (Fetched::Modified(json, validators), _) => Ok((json_to_langs(json)?, validators)),
+            (Fetched::NotModified, Some(previous)) => {
+                log_debug!("langs are not modified");
+                Ok(previous)
+            },
+            (Fetched::NotModified, None) => Err(RequestError::HttpStatus(304)),
+        }
+    }
+
+    pub fn lookup<L: Into<LangPair>>(&self, lang: L, text: &str) -> Result<Value, RequestError> {
+        self.lookup_with_flags(lang, text, LookupFlags::empty())
+    }
+
+    pub fn lookup_with_flags<L: Into<LangPair>>(&self, lang: L, text: &str, flags: LookupFlags)
+        -> Result<Value, RequestError> {
+        let options = LookupOptions {
+            flags: flags,
+            ui: None,
+        };
+        self.lookup_with_options(lang, text, &options)
+    }
+
+    pub fn lookup_with_options<L: Into<LangPair>>(&self, lang: L, text: &str, options: &LookupOptions)
+        -> Result<Value, RequestError> {
+        self.lookup_with_limits(&lang.into(), text, options, self.limits())
+    }
+
+    fn lookup_with_limits(&self, lang: &LangPair, text: &str, options: &LookupOptions, limits: Limits)
+        -> Result<Value, RequestError> {
+        self.lookup_fresh(lang, text, options, limits).map(|(json, _)| json)
+    }
+
+    fn lookup_fresh(&self, lang: &LangPair, text: &str, options: &LookupOptions, limits: Limits)
+        -> Result<(Value, Freshness), RequestError> {
+        let options = &self.options_for(lang, options);
+        let key = CacheKey {
+            lang: lang.as_str().to_owned(),
+            text: text.to_owned(),
+            flags: options.flags.bits(),
+            ui: options.ui.clone(),
+        };
+        // Old entry and its age, used if the service fails
+        let mut stale = None;
+        if let Some(ref cache) = self.cache {
+            let cached = with_cache(cache, |cache| cache.get(&key).map(|json| (json, cache.age(&key))))?;
+            if let Some((json, age)) = cached {
+                match (self.cache_policy.max_age(), age) {
+                    (Some(max_age), Some(age)) if age > max_age => {
+                        log_debug!("cache entry of {} '{}' is old", key.lang, key.text);
+                        stale = Some((json, age));
+                    },
+                    _ => {
+                        log_debug!("cache hit for {} '{}'", key.lang, key.text);
+                        self.count(Stats::cache(true));
+                        self.learn(lang, text, &json);
+                        self.remember(lang, text);
+                        self.keep(lang, text, &json);
+                        return Ok((json, Freshness::Fresh));
+                    },
+                }
+            } else {
+                log_debug!("cache miss for {} '{}'", key.lang, key.text);
+            }
+        }
+        if self.cache.is_some() {
+            self.count(Stats::cache(false));
+        }
+        let result = self.inflight.run(&key, || self.with_token(|token| {
+            let params = lookup_params(token, lang.as_str(), text, options);
+            self.fetch_json("lookup", &params, limits)
+        }));
+        let json = match (result, stale) {
+            (Ok(json), _) => json,
+            (Err(e), Some((json, age))) if self.serves_stale(&e) => {
+                log_debug!("stale cache entry of {} '{}' is used: {}", key.lang, key.text, e);
+                self.warn(|| Warning::StaleCache {
+                    lang: key.lang.clone(),
+                    text: key.text.clone(),
+                    age: age,
+                    error: e.to_string(),
+                });
+                self.learn(lang, text, &json);
+                self.remember(lang, text);
+                self.keep(lang, text, &json);
+                return Ok((json, Freshness::Stale(age)));
+            },
+            (Err(e), _) => return Err(e),
+        };
+        if !json.is_object() {
+            return Err(RequestError::InvalidDataFormat);
+        }
+        if let Some(ref cache) = self.cache {
+            with_cache(cache, |cache| cache.put(key, json.clone()))?;
+        }
+        self.learn(lang, text, &json);
+        self.remember(lang, text);
+        self.keep(lang, text, &json);
+        Ok((json, Freshness::Fresh))
+    }
+
+    // Failures of the service or the way to it, not of the request.
+    fn serves_stale(&self, error: &RequestError) -> bool {
+        if let CachePolicy::StaleIfError(_) = self.cache_policy {
+            match *error.root() {
+                RequestError::CircuitOpen | RequestError::RateLimited => true,
+                ref e => middleware::is_outage(e),
+            }
+        } else {
+            false
+        }
+    }
+
+    // Keeps words with definitions for suggestions.
+    fn learn(&self, lang: &LangPair, text: &str, json: &Value) {
+        if json.get("def").and_then(Value::as_array).is_some_and(|defs| !defs.is_empty()) {
+            unwind::lock(&self.vocabulary).add(lang.source(), text);
+        }
+    }
+
+    // Known words of the source language close to `text`, see `with_word_list`.
+    pub fn similar_words<L: Into<LangPair>>(&self, lang: L, text: &str) -> Vec<String> {
+        unwind::lock(&self.vocabulary).suggest(lang.into().source(), text)
+    }
+
+    fn remember(&self, lang: &LangPair, text: &str) {
+        if let Some(ref history) = self.history {
+            let entry = HistoryEntry {
+                time: unix_now(),
+                lang: lang.as_str().to_owned(),
+                text: text.to_owned(),
+            };
+            // Like the cache, the journal must not fail the lookup
+            if let Err(e) = history.append(&entry) {
+                log_debug!("can't write history {}: {}", history.path().display(), e);
+            }
+        }
+    }
+
+    // Records words with definitions in the store, see `with_store`.
+    #[cfg(feature = "store")]
+    fn keep(&self, lang: &LangPair, text: &str, json: &Value) {
+        if let Some(ref store) = self.store {
+            let defs = json.get("def").cloned().and_then(|defs| serde_json::from_value::<Vec<Def>>(defs).ok());
+            if let Some(defs) = defs.filter(|defs| !defs.is_empty()) {
+                if let Err(e) = store.record(lang.as_str(), text, &defs, unix_now()) {
+                    log_debug!("can't write dictionary store {}: {}", store.path().display(), e);
+                }
+            }
+        }
+    }
+
+    #[cfg(not(feature = "store"))]
+    fn keep(&self, _lang: &LangPair, _text: &str, _json: &Value) {
+    }
+
+    // Looks up `text` in the direction between two languages which suits
+    // the script of the text, see `LangPair::detect`.
+    pub fn lookup_auto(&self, langs: (&str, &str), text: &str) -> Result<Vec<Def>, RequestError> {
+        self.lookup_def(LangPair::detect(langs, text), text)
+    }
+
+    // Looks up `text` in both directions of the pair, definitions of the
+    // given direction go first. Unsupported opposite direction is skipped.
+    pub fn lookup_both<L: Into<LangPair>>(&self, lang: L, text: &str) -> Result<Vec<Def>, RequestError> {
+        let lang = lang.into();
+        let mut defs = self.lookup_def(&lang, text)?;
+        match self.lookup_def(lang.reversed(), text) {
+            Ok(reversed) => defs.extend(reversed),
+            Err(RequestError::LangNotSupported) => (),
+            Err(e) => return Err(e),
+        }
+        Ok(merge_definitions(&defs))
+    }
+
+    // Returns the answer as is, e.g. to archive it or to debug parsing.
+    // Neither the cache nor the status are checked, only failures to get
+    // a response are errors.
+    pub fn lookup_raw<L: Into<LangPair>>(&self, lang: L, text: &str, options: &LookupOptions)
+        -> Result<RawResponse, RequestError> {
+        let lang = lang.into();
+        let token = &self.tokens[self.key.load(Ordering::SeqCst)];
+        let params = lookup_params(token, lang.as_str(), text, &self.options_for(&lang, options));
+        let url = api_url(&self.base_url, "lookup", &params);
+        let response = self.execute("lookup", &url, self.limits(), &Validators::default())?;
+        Ok(RawResponse {
+            status: response.status,
+            body: response.body,
+        })
+    }
+
+    pub fn lookup_def<L: Into<LangPair>>(&self, lang: L, text: &str)
+        -> Result<Vec<Def>, RequestError> {
+        self.lookup_def_with_flags(lang, text, LookupFlags::empty())
+    }
+
+    pub fn lookup_def_with_flags<L: Into<LangPair>>(&self, lang: L, text: &str, flags: LookupFlags)
+        -> Result<Vec<Def>, RequestError> {
+        let json = self.lookup_with_flags(lang, text, flags)?;
+        self.parse_defs(json)
+    }
+
+    pub fn lookup_def_with_options<L: Into<LangPair>>(&self, lang: L, text: &str, options: &LookupOptions)
+        -> Result<Vec<Def>, RequestError> {
+        let json = self.lookup_with_options(lang, text, options)?;
+        self.parse_defs(json)
+    }
+
+    // Same as `lookup_def_with_options`, but tells if the definitions are
+    // an old cached answer, see `CachePolicy::StaleIfError`.
+    pub fn lookup_def_with_freshness<L: Into<LangPair>>(&self, lang: L, text: &str, options: &LookupOptions)
+        -> Result<(Vec<Def>, Freshness), RequestError> {
+        let (json, freshness) = self.lookup_fresh(&lang.into(), text, options, self.limits())?;
+        self.parse_defs(json).map(|defs| (defs, freshness))
+    }
+
+    // Definitions with the entries skipped in lenient mode, the report
+    // is always complete in strict mode.
+    pub fn lookup_def_with_report<L: Into<LangPair>>(&self, lang: L, text: &str, options: &LookupOptions)
+        -> Result<LookupResponse, RequestError> {
+        let json = self.lookup_with_options(lang, text, options)?;
+        parse::parse_defs(json, self.parse_mode)
+    }
+
+    fn parse_defs(&self, json: Value) -> Result<Vec<Def>, RequestError> {
+        let response = parse::parse_defs(json, self.parse_mode)?;
+        for warning in response.warnings() {
+            log_warn!("skipped {}", warning);
+        }
+        if !response.is_complete() {
+            self.warn(|| Warning::SkippedEntries(response.warnings().to_vec()));
+        }
+        Ok(response.into_defs())
+    }
+
+    pub fn lookup_request<'a, L: Into<LangPair>>(&'a self, lang: L, text: &str) -> LookupRequest<'a> {
+        LookupRequest {
+            api: self,
+            lang: lang.into(),
+            text: text.to_owned(),
+            options: LookupOptions::default(),
+            pos: Vec::new(),
+            max_translations: None,
+            timeout: self.timeout,
+            cancel: None,
+        }
+    }
+
+    // Looks up the whole phrase and falls back to its separate words
+    // if the dictionary knows nothing about the phrase.
+    pub fn lookup_phrase<L: Into<LangPair>>(&self, lang: L, phrase: &str)
+        -> Result<PhraseLookup, RequestError> {
+        let lang = lang.into();
+        let defs = self.lookup_def(&lang, phrase)?;
+        if !defs.is_empty() {
+            return Ok(PhraseLookup {
+                mode: PhraseMode::Phrase,
+                defs: defs,
+                words: Vec::new(),
+            });
+        }
+        let mut words: Vec<(String, Vec<Def>)> = Vec::new();
+        for token in phrase.split_whitespace() {
+            if words.len() == MAX_PHRASE_WORDS {
+                break;
+            }
+            if words.iter().any(|(w, _)| w == token) {
+                continue;
+            }
+            let defs = self.lookup_def(&lang, token)?;
+            words.push((token.to_owned(), defs));
+        }
+        Ok(PhraseLookup {
+            mode: PhraseMode::Words,
+            defs: Vec::new(),
+            words: words,
+        })
+    }
+}
+
+// Answer of `Api::lookup_raw` as the service gave it.
+#[derive(Clone, Debug)]
+pub struct RawResponse {
+    pub status: u16,
+    pub body: String,
+}
+
+// Builder of `lookup` request created by `Api::lookup_request`.
+pub struct LookupRequest<'a> {
+    api: &'a Api,
+    lang: LangPair,
+    text: String,
+    options: LookupOptions,
+    // Applied by `send_def` to the response, the request itself is the same.
+    pos: Vec<PartOfSpeech>,
+    max_translations: Option<usize>,
+    timeout: Option<Duration>,
+    cancel: Option<CancellationToken>,
+}
+
+impl<'a> LookupRequest<'a> {
+    pub fn ui(mut self, ui: &str) -> LookupRequest<'a> {
+        self.options.ui = Some(ui.to_owned());
+        self
+    }
+
+    pub fn flags(mut self, flags: LookupFlags) -> LookupRequest<'a> {
+        self.options.flags = flags;
+        self
+    }
+
+    pub fn options(mut self, options: LookupOptions) -> LookupRequest<'a> {
+        self.options = options;
+        self
+    }
+
+    // Keeps definitions of the given part of speech only, can be called
+    // several times to keep a few of them.
+    pub fn pos(mut self, pos: PartOfSpeech) -> LookupRequest<'a> {
+        self.pos.push(pos);
+        self
+    }
+
+    // Keeps at most `max` first translations of every definition.
+    pub fn max_translations(mut self, max: usize) -> LookupRequest<'a> {
+        self.max_translations = Some(max);
+        self
+    }
+
+    // Overrides the timeout of `Api` for this request.
+    pub fn timeout(mut self, timeout: Duration) -> LookupRequest<'a> {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    // Fails with `RequestError::Cancelled` once `cancel` is cancelled.
+    pub fn cancel(mut self, cancel: &CancellationToken) -> LookupRequest<'a> {
+        self.cancel = Some(cancel.clone());
+        self
+    }
+
+    pub fn send(&self) -> Result<Value, RequestError> {
+        let limits = Limits {
+            timeout: self.timeout,
+            cancel: self.cancel.as_ref(),
+            status: None,
+        };
+        self.api.lookup_with_limits(&self.lang, &self.text, &self.options, limits)
+    }
+
+    pub fn send_def(&self) -> Result<Vec<Def>, RequestError> {
+        let defs = self.api.parse_defs(self.send()?)?;
+        Ok(filter_defs(defs, &self.pos, self.max_translations))
+    }
+}
+
+fn filter_defs(defs: Vec<Def>, pos: &[PartOfSpeech], max_translations: Option<usize>) -> Vec<Def> {
+    defs.into_iter()
+        .filter(|def| pos.is_empty() || def.word.pos.as_ref().is_some_and(|p| pos.contains(p)))
+        .map(|mut def| {
+            if let Some(max) = max_translations {
+                def.trans.truncate(max);
+            }
+            def
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+
+    use std::collections::HashSet;
+    use std::env;
+    use std::fs::{self, File};
+    use std::io::Write;
+    use std::path::PathBuf;
+    use std::thread;
+    use std::time::{Duration, Instant};
+    use super::{Api, CancellationToken, Def, Word, PartOfSpeech, PhraseMode, merge_definitions, json_to_defs};
+    use super::{load_langs_file, save_langs_file, api_url, lookup_params, response_to_json, redact_key};
+    use super::{KeyStatus, LookupFlags, LookupOptions, RequestError, RetryPolicy};
+    use cache::{CacheKey, FileCache};
+    use testing::{MockTransport, FIXTURE_GET_LANGS, FIXTURE_KEY_INVALID, FIXTURE_LOOKUP_RUST, FIXTURE_NOT_FOUND};
+    use transport::{HttpTransport, Validators};
+    use std::sync::{Arc, Mutex};
+    use std::error::Error;
+    use serde_json::{self, Value};
+
+    fn word(text: &str, pos: Option<&str>, ts: Option<&str>) -> Word {
+        Word {
+            text: text.to_owned(),
+            pos: pos.map(PartOfSpeech::from),
+            ts: ts.map(|s| s.to_owned()),
+            gen: None,
+            asp: None,
+            num: None,
+            fr: None,
+            syn: Vec::new(),
+            mean: Vec::new(),
+            ex: Vec::new(),
+        }
+    }
+
+    fn mock_api() -> Api {
+        Api::from_token("token").unwrap().with_transport(MockTransport::with_fixtures())
+    }
+
+    #[test]
+    fn check_get_langs() {
+        let langs = mock_api().get_langs().unwrap();
+        assert!(langs.contains(&"en-ru".to_string()));
+    }
+
+    #[test]
+    fn check_lookup() {
+        let json = mock_api().lookup("en-ru", "rust").unwrap();
+        assert_eq!(json["def"].as_array().unwrap().len(), 3);
+    }
+
+    #[test]
+    fn check_lookup_def() {
+        let defs = mock_api().lookup_def("en-ru", "rust").unwrap();
+        assert_eq!(defs.len(), 3);
+        for def in defs {
+            assert_eq!(def.word.text, "rust");
+            assert_eq!(def.word.ts.as_ref().unwrap(), "r\u{28c}st");
+            assert!(!def.trans.is_empty());
+            let text = &def.trans[0].text;
+            match *def.word.pos.as_ref().unwrap() {
+                PartOfSpeech::Noun => {
+                    assert_eq!(text, "\u{440}\u{436}\u{430}\u{432}\u{447}\u{438}\u{43d}\u{430}");
+                },
+                PartOfSpeech::Verb => {
+                    assert_eq!(text, "\u{440}\u{436}\u{430}\u{432}\u{435}\u{442}\u{44c}");
+                },
+                PartOfSpeech::Adjective => {
+                    assert_eq!(text, "\u{440}\u{436}\u{430}\u{432}\u{44b}\u{439}");
+                },
+                ref pos => panic!("Unknown pos of 'rust' word: {}", pos),
+            }
+        }
+    }
+
+    #[test]
+    fn check_merge_definitions() {
+        let defs = vec![
+            Def {
+                word: word("Run", Some("verb"), None),
+                trans: vec![word("бежать", Some("verb"), None), word("работать", Some("verb"), None)],
+            },
+            Def {
+                word: word("run", Some("noun"), Some("r\u{28c}n")),
+                trans: vec![word("пробег", Some("noun"), None)],
+            },
+            Def {
+                word: word(" run ", Some("verb"), Some("r\u{28c}n")),
+                trans: vec![word("бежать", Some("verb"), None), word("управлять", Some("verb"), None)],
+            },
+        ];
+        let merged = merge_definitions(&defs);
+        assert_eq!(merged.len(), 2);
+        assert_eq!(merged[0].word.text, "Run");
+        assert_eq!(merged[0].word.pos, Some(PartOfSpeech::Verb));
+        assert_eq!(merged[0].word.ts.as_ref().unwrap(), "r\u{28c}n");
+        let texts: Vec<&str> = merged[0].trans.iter().map(|t| t.text.as_str()).collect();
+        assert_eq!(texts, vec!["бежать", "работать", "управлять"]);
+        assert_eq!(merged[1].word.pos, Some(PartOfSpeech::Noun));
+        assert_eq!(merged[1].trans.len(), 1);
+    }
+
+    #[test]
+    fn check_merge_definitions_translations() {
+        let mut rare = word("Ржавчина", Some("noun"), None);
+        rare.fr = Some(2);
+        let mut frequent = word("ржавчина ", Some("noun"), None);
+        frequent.fr = Some(10);
+        frequent.syn = vec![word("коррозия", Some("noun"), None)];
+        let defs = vec![
+            Def {
+                word: word("rust", Some("noun"), None),
+                trans: vec![rare],
+            },
+            Def {
+                word: word("rust", Some("noun"), None),
+                trans: vec![frequent, word("налёт", Some("noun"), None)],
+            },
+        ];
+        let merged = merge_definitions(&defs);
+        assert_eq!(merged.len(), 1);
+        let texts: Vec<&str> = merged[0].trans.iter().map(|t| t.text.as_str()).collect();
+        assert_eq!(texts, vec!["Ржавчина", "налёт"]);
+        assert_eq!(merged[0].trans[0].fr, Some(10));
+        assert_eq!(merged[0].trans[0].syn.len(), 1);
+    }
+
+    #[test]
+    fn check_data_traits() {
+        let defs = json_to_defs(serde_json::from_str(FIXTURE_LOOKUP_RUST).unwrap()).unwrap();
+        let json = serde_json::to_string(&defs).unwrap();
+        let parsed: Vec<Def> = serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed, defs);
+        let mut words: HashSet<Word> = defs.iter().map(|def| def.word.clone()).collect();
+        assert!(!words.insert(defs[0].word.clone()));
+        assert!(format!("{:?}", defs[0]).starts_with("Def { word: Word { text: \"rust\""));
+    }
+
+    #[test]
+    fn check_def_merge() {
+        let mut with_syn = word("бежать", Some("verb"), None);
+        with_syn.fr = Some(10);
+        with_syn.syn = vec![word("мчаться", Some("verb"), None)];
+        let mut frequent = word("Бежать", Some("verb"), None);
+        frequent.fr = Some(5);
+        frequent.syn = vec![word("мчаться", Some("verb"), None), word("нестись", Some("verb"), None)];
+        let mut rare = word("пробег", Some("noun"), None);
+        rare.fr = Some(1);
+        let defs = vec![
+            Def {
+                word: word("run", Some("noun"), None),
+                trans: vec![rare, word("забег", Some("noun"), None)],
+            },
+            Def {
+                word: word("run", Some("verb"), Some("r\u{28c}n")),
+                trans: vec![frequent, with_syn],
+            },
+        ];
+        let entry = Def::merge(&defs);
+        assert_eq!(entry.text, "run");
+        assert_eq!(entry.ts.as_ref().unwrap(), "r\u{28c}n");
+        assert_eq!(entry.pos, vec![PartOfSpeech::Noun, PartOfSpeech::Verb]);
+        let texts: Vec<&str> = entry.trans.iter().map(|t| t.text.as_str()).collect();
+        assert_eq!(texts, vec!["Бежать", "пробег", "забег"]);
+        assert_eq!(entry.trans[0].fr, Some(10));
+        let syns: Vec<&str> = entry.trans[0].syn.iter().map(|s| s.text.as_str()).collect();
+        assert_eq!(syns, vec!["мчаться", "нестись"]);
+        assert!(Def::merge(&[]).trans.is_empty());
+    }
+
+    #[test]
+    fn check_transcription() {
+        let word: Word = serde_json::from_str(r#"{"text": "rust", "ts": " r\u028cst  "}"#).unwrap();
+        let ts = word.transcription();
+        assert!(!ts.is_empty());
+        assert_eq!(ts.as_ipa(), "r\u{28c}st");
+        assert_eq!(ts.display_slashed(), "/r\u{28c}st/");
+
+        let word: Word = serde_json::from_str(r#"{"text": "rust", "ts": " "}"#).unwrap();
+        assert!(word.ts.is_none());
+        assert!(word.transcription().is_empty());
+        assert_eq!(word.transcription().display_slashed(), "");
+    }
+
+    fn temp_path(name: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("yadict-test-{}", name));
+        let _ = fs::remove_dir_all(&dir);
+        dir.join("langs.json")
+    }
+
+    #[test]
+    fn check_langs_file() {
+        let path = temp_path("langs-file");
+        assert!(load_langs_file(&path).is_none());
+        let langs = vec!["en-ru".to_owned(), "ru-en".to_owned()];
+        save_langs_file(&path, 1000, &langs, &Validators::default()).unwrap();
+        let (fetched, loaded, validators) = load_langs_file(&path).unwrap();
+        assert_eq!(fetched, 1000);
+        assert_eq!(loaded, langs);
+        assert!(validators.is_empty());
+        // Rewrite must replace the file, not append to it
+        let validators = Validators {
+            etag: Some("\"v2\"".to_owned()),
+            ..Validators::default()
+        };
+        save_langs_file(&path, 2000, &langs[..1], &validators).unwrap();
+        let (fetched, loaded, loaded_validators) = load_langs_file(&path).unwrap();
+        assert_eq!(fetched, 2000);
+        assert_eq!(loaded, vec!["en-ru".to_owned()]);
+        assert_eq!(loaded_validators, validators);
+        assert_eq!(fs::read_dir(path.parent().unwrap()).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn check_langs_file_corrupt() {
+        let path = temp_path("langs-corrupt");
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        File::create(&path).unwrap().write_all(b"{\"fetched\": 1, \"langs\": [").unwrap();
+        assert!(load_langs_file(&path).is_none());
+        File::create(&path).unwrap().write_all(b"{\"fetched\": 1, \"langs\": [1]}").unwrap();
+        assert!(load_langs_file(&path).is_none());
+    }
+
+    #[test]
+    fn check_get_langs_from_fresh_file() {
+        let path = temp_path("langs-fresh");
+        let langs = vec!["xx-yy".to_owned()];
+        save_langs_file(&path, super::unix_now(), &langs, &Validators::default()).unwrap();
+        let transport = MockTransport::with_fixtures();
+        let api = Api::from_token("token").unwrap()
+            .with_transport(transport.clone())
+            .with_langs_file(&path, Duration::from_secs(3600));
+        assert_eq!(api.get_langs().unwrap(), langs);
+        assert!(transport.requests().is_empty());
+    }
+
+    #[test]
+    fn check_lookup_phrase() {
+        let transport = MockTransport::new()
+            .on("text=take+off", 200, r#"{"def": [{"text": "take off", "pos": "verb", "tr": [{"text": "взлетать"}]}]}"#)
+            .on("text=rust", 200, FIXTURE_LOOKUP_RUST)
+            .on("/lookup?", 200, FIXTURE_NOT_FOUND);
+        let api = Api::from_token("token").unwrap().with_transport(transport.clone());
+        let phrase = api.lookup_phrase("en-ru", "take off").unwrap();
+        assert_eq!(phrase.mode, PhraseMode::Phrase);
+        assert_eq!(phrase.defs.len(), 1);
+        assert!(phrase.words.is_empty());
+        assert_eq!(transport.requests().len(), 1);
+        let fallback = api.lookup_phrase("en-ru", "qwzx rust rust").unwrap();
+        // The phrase and each distinct word once
+        assert_eq!(transport.requests().len(), 4);
+        assert_eq!(fallback.mode, PhraseMode::Words);
+        assert!(fallback.defs.is_empty());
+        assert_eq!(fallback.words.len(), 2);
+        assert_eq!(fallback.words[1].0, "rust");
+        assert_eq!(fallback.words[1].1.len(), 3);
+        assert!(fallback.words[0].1.is_empty());
+    }
+
+    #[test]
+    fn check_full_word() {
+        let word: Word = serde_json::from_str(r#"{
+            "text": "\u0440\u0436\u0430\u0432\u0447\u0438\u043d\u0430", "pos": "noun",
+            "syn": [{"text": "\u043a\u043e\u0440\u0440\u043e\u0437\u0438\u044f", "pos": "noun"}],
+            "mean": [{"text": "corrosion"}, {"text": "rusting"}],
+            "ex": [{"text": "rust stain", "tr": [{"text": "\u043f\u044f\u0442\u043d\u043e \u0440\u0436\u0430\u0432\u0447\u0438\u043d\u044b"}]}]
+        }"#).unwrap();
+        assert_eq!(word.syn.len(), 1);
+        assert_eq!(word.syn[0].text, "\u{43a}\u{43e}\u{440}\u{440}\u{43e}\u{437}\u{438}\u{44f}");
+        assert_eq!(word.syn[0].pos, Some(PartOfSpeech::Noun));
+        let means: Vec<&str> = word.mean.iter().map(|m| m.text.as_str()).collect();
+        assert_eq!(means, vec!["corrosion", "rusting"]);
+        assert_eq!(word.ex.len(), 1);
+        assert_eq!(word.ex[0].text, "rust stain");
+        assert_eq!(word.ex[0].tr.len(), 1);
+
+        assert!(serde_json::from_str::<Word>(r#"{"text": "rust", "syn": {"text": "oops"}}"#).is_err());
+    }
+
+    #[test]
+    fn check_api_url() {
+        let params = [("key", "k"), ("lang", "en-ru"), ("text", "rock & roll+\u{451}")];
+        let url = api_url(super::API_URL, "lookup", &params);
+        assert_eq!(url, format!("{}/lookup?key=k&lang=en-ru&text=rock+%26+roll%2B%D1%91", super::API_URL));
+        let api = Api::from_token("k").unwrap().with_base_url("http://localhost:8080/");
+        assert_eq!(api_url(&api.base_url, "getLangs", &[("key", "k")]), "http://localhost:8080/getLangs?key=k");
+    }
+
+    #[test]
+    fn check_lookup_options() {
+        let mut options = LookupOptions::default();
+        let params = lookup_params("k", "en-ru", "rust", &options);
+        assert_eq!(params.len(), 3);
+        options.flags = LookupFlags::FAMILY | LookupFlags::MORPHO;
+        options.ui = Some("ru".to_owned());
+        let params = lookup_params("k", "en-ru", "rust", &options);
+        assert_eq!(&params[3..], &[("flags", "5".to_owned()), ("ui", "ru".to_owned())]);
+    }
+
+    #[test]
+    fn check_lookup_ui() {
+        let transport = MockTransport::new()
+            .on("&ui=ru", 200, r#"{"def": [{"text": "rust", "pos": "существительное", "tr": [{"text": "ржавчина"}]}]}"#)
+            .on("/lookup?", 200, FIXTURE_LOOKUP_RUST);
+        let api = Api::from_token("token").unwrap().with_transport(transport.clone());
+        let options = LookupOptions {
+            flags: LookupFlags::empty(),
+            ui: Some("ru".to_owned()),
+        };
+        for def in api.lookup_def_with_options("en-ru", "rust", &options).unwrap() {
+            let pos = def.word.pos.unwrap();
+            assert!(!pos.as_str().is_ascii());
+        }
+        api.lookup_def("en-ru", "rust").unwrap();
+        let requests = transport.requests();
+        assert!(requests[0].ends_with("/lookup?key=token&lang=en-ru&text=rust&ui=ru"));
+        assert!(!requests[1].contains("ui="));
+    }
+
+    #[test]
+    fn check_pair_options() {
+        let transport = MockTransport::with_fixtures();
+        let api = Api::from_token("token").unwrap()
+            .with_transport(transport.clone())
+            .default_options_for("en-ru", LookupOptions {
+                flags: LookupFlags::FAMILY,
+                ui: Some("ru".to_owned()),
+            });
+        api.lookup_def("en-ru", "rust").unwrap();
+        api.lookup_def("de-ru", "rust").unwrap();
+        let call = LookupOptions {
+            flags: LookupFlags::MORPHO,
+            ui: Some("en".to_owned()),
+        };
+        api.lookup_def_with_options("en-ru", "iron", &call).unwrap();
+        let call = LookupOptions {
+            flags: LookupFlags::empty(),
+            ui: Some("en".to_owned()),
+        };
+        api.lookup_def_with_options("en-ru", "tin", &call).unwrap();
+        api.lookup_batch("en-ru", &["zinc"]);
+        api.lookup_iter("en-ru", vec!["lead"]).count();
+        api.lookup_raw("en-ru", "gold", &LookupOptions::default()).unwrap();
+        let requests = transport.requests();
+        assert_eq!(requests.len(), 7);
+        assert!(requests[0].ends_with("/lookup?key=token&lang=en-ru&text=rust&flags=1&ui=ru"));
+        assert!(requests[1].ends_with("/lookup?key=token&lang=de-ru&text=rust"));
+        assert!(requests[2].ends_with("/lookup?key=token&lang=en-ru&text=iron&flags=4&ui=en"));
+        assert!(requests[3].ends_with("/lookup?key=token&lang=en-ru&text=tin&flags=1&ui=en"));
+        assert!(requests[4].ends_with("/lookup?key=token&lang=en-ru&text=zinc&flags=1&ui=ru"));
+        assert!(requests[5].ends_with("/lookup?key=token&lang=en-ru&text=lead&flags=1&ui=ru"));
+        assert!(requests[6].ends_with("/lookup?key=token&lang=en-ru&text=gold&flags=1&ui=ru"));
+    }
+
+    #[test]
+    fn check_error_display() {
+        let e = RequestError::from(402);
+        assert_eq!(e.to_string(), "API key is blocked");
+        assert!(e.source().is_none());
+        let e = RequestError::from(serde_json::from_str::<Value>("{").unwrap_err());
+        assert!(e.to_string().starts_with("JSON error: "));
+        assert!(e.source().is_some());
+        let boxed: Box<dyn Error> = Box::new(RequestError::from(999));
+        assert_eq!(boxed.to_string(), "unknown error with code 999");
+    }
+
+    #[test]
+    fn check_defs_json() {
+        let body = r#"{"head": {}, "def": [{
+            "text": "rust", "pos": "noun", "ts": "r\u028cst",
+            "tr": [{"text": "\u0440\u0436\u0430\u0432\u0447\u0438\u043d\u0430", "pos": "noun",
+                    "ex": [{"text": "rust stain", "tr": [{"text": "\u043f\u044f\u0442\u043d\u043e"}]}]}]
+        }]}"#;
+        let defs = json_to_defs(serde_json::from_str(body).unwrap()).unwrap();
+        assert_eq!(defs.len(), 1);
+        assert_eq!(defs[0].word.text, "rust");
+        assert_eq!(defs[0].trans[0].ex[0].tr, vec!["\u{43f}\u{44f}\u{442}\u{43d}\u{43e}"]);
+        // Serialized definitions keep the schema of the API
+        let json = serde_json::to_value(&defs).unwrap();
+        assert_eq!(json[0]["text"], "rust");
+        assert_eq!(json[0]["tr"][0]["ex"][0]["tr"][0]["text"], "\u{43f}\u{44f}\u{442}\u{43d}\u{43e}");
+        assert!(json[0]["tr"][0].get("syn").is_none());
+        let defs: Vec<Def> = serde_json::from_value(json).unwrap();
+        assert_eq!(defs[0].word.ts.as_ref().unwrap(), "r\u{28c}st");
+
+        let body = r#"{"head": {}, "def": [{"text": "rust"}]}"#;
+        assert!(json_to_defs(serde_json::from_str(body).unwrap()).is_err());
+    }
+
+    #[test]
+    fn check_validate_pair() {
+        let transport = MockTransport::new().on("/getLangs?", 200, FIXTURE_GET_LANGS);
+        let api = Api::from_token("token").unwrap().with_transport(transport.clone());
+        assert!(api.validate_pair(&"en-ru".parse().unwrap()).unwrap());
+        assert!(!api.validate_pair(&"xx-yy".parse().unwrap()).unwrap());
+        // The list is kept in memory
+        assert_eq!(transport.requests().len(), 1);
+    }
+
+    #[test]
+    fn check_lookup_request() {
+        let api = Api::from_token("invalid").unwrap();
+        let request = api.lookup_request("en-ru", "rust")
+            .flags(LookupFlags::MORPHO)
+            .ui("ru");
+        assert_eq!(request.lang.as_str(), "en-ru");
+        assert_eq!(request.text, "rust");
+        assert_eq!(request.options.flags, LookupFlags::MORPHO);
+        assert_eq!(request.options.ui.as_ref().unwrap(), "ru");
+    }
+
+    #[test]
+    fn check_lookup_cache() {
+        let transport = MockTransport::with_fixtures();
+        let api = Api::from_token("token").unwrap().with_transport(transport.clone()).with_cache(10);
+        let body = r#"{"head": {}, "def": [{"text": "rust", "tr": [{"text": "rust"}]}]}"#;
+        let key = CacheKey {
+            lang: "en-ru".to_owned(),
+            text: "rust".to_owned(),
+            flags: 0,
+            ui: None,
+        };
+        api.cache.as_ref().unwrap().lock().unwrap().put(key, serde_json::from_str(body).unwrap());
+        let defs = api.lookup_def("en-ru", "rust").unwrap();
+        assert_eq!(defs[0].word.text, "rust");
+        assert!(transport.requests().is_empty());
+        // Another interface language isn't cached yet
+        let defs = api.lookup_request("en-ru", "rust").ui("ru").send_def().unwrap();
+        assert_eq!(defs.len(), 3);
+        let requests = transport.requests();
+        assert_eq!(requests.len(), 1);
+        assert!(requests[0].contains("ui=ru"));
+    }
+
+    #[test]
+    fn check_lookup_file_cache() {
+        let dir = env::temp_dir().join("yadict-test-lookup-file-cache");
+        let _ = fs::remove_dir_all(&dir);
+        let api = Api::from_token("invalid").unwrap().with_cache_backend(FileCache::new(&dir));
+        let key = CacheKey {
+            lang: "en-ru".to_owned(),
+            text: "rust".to_owned(),
+            flags: 0,
+            ui: None,
+        };
+        let body = r#"{"head": {}, "def": [{"text": "rust", "tr": [{"text": "rust"}]}]}"#;
+        api.cache.as_ref().unwrap().lock().unwrap().put(key, serde_json::from_str(body).unwrap());
+        // The fixture has 3 definitions, the cached answer has one
+        let api = Api::from_token("token").unwrap()
+            .with_transport(MockTransport::with_fixtures())
+            .with_cache_backend(FileCache::new(&dir));
+        assert_eq!(api.lookup_def("en-ru", "rust").unwrap().len(), 1);
+    }
+
+    #[test]
+    fn check_response_status() {
+        match response_to_json(403, r#"{"code": 402, "message": "blocked"}"#) {
+            Err(RequestError::KeyBlocked) => (),
+            other => panic!("unexpected result: {:?}", other),
+        }
+        match response_to_json(502, "<html>Bad Gateway</html>") {
+            Err(RequestError::HttpStatus(502)) => (),
+            other => panic!("unexpected result: {:?}", other),
+        }
+        assert!(response_to_json(200, "[]").is_ok());
+    }
+
+    #[test]
+    fn check_rate_limited_api() {
+        let limit = super::RateLimit {
+            per_day: Some(0),
+            ..Default::default()
+        };
+        let api = Api::from_token("invalid").unwrap().with_rate_limit(limit);
+        match api.lookup("en-ru", "rust") {
+            Err(RequestError::RateLimited) => (),
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+
+    // Serves queued responses one by one.
+    struct ScriptedTransport {
+        responses: Mutex<Vec<(u16, String)>>,
+    }
+
+    impl HttpTransport for ScriptedTransport {
+        fn get(&self, _url: &str) -> Result<(u16, String), RequestError> {
+            Ok(self.responses.lock().unwrap().remove(0))
+        }
+    }
+
+    #[test]
+    fn check_custom_transport() {
+        let transport = ScriptedTransport {
+            responses: Mutex::new(vec![
+                (200, r#"["en-ru", "ru-en"]"#.to_owned()),
+                (403, r#"{"code": 403, "message": "limit"}"#.to_owned()),
+            ]),
+        };
+        let api = Api::from_token("k").unwrap().with_base_url("http://mock").with_transport(transport);
+        assert_eq!(api.get_langs().unwrap(), vec!["en-ru", "ru-en"]);
+        match api.lookup("en-ru", "rust") {
+            Err(RequestError::DailyLimitExceeded) => (),
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn check_retry_with_transport() {
+        let transport = ScriptedTransport {
+            responses: Mutex::new(vec![
+                (503, "Service Unavailable".to_owned()),
+                (200, r#"["en-ru"]"#.to_owned()),
+            ]),
+        };
+        let policy = RetryPolicy {
+            base_delay: Duration::from_millis(1),
+            ..RetryPolicy::default()
+        };
+        let api = Api::from_token("k").unwrap().with_retry(policy).with_transport(transport);
+        assert_eq!(api.get_langs().unwrap(), vec!["en-ru"]);
+    }
+
+    #[test]
+    fn check_lookup_request_filter() {
+        let api = Api::from_token("token").unwrap().with_transport(MockTransport::with_fixtures());
+        let defs = api.lookup_request("en-ru", "rust")
+            .pos(PartOfSpeech::Noun)
+            .pos(PartOfSpeech::Adjective)
+            .max_translations(1)
+            .send_def()
+            .unwrap();
+        let pos: Vec<PartOfSpeech> = defs.iter().map(|def| def.word.pos.clone().unwrap()).collect();
+        assert_eq!(pos, vec![PartOfSpeech::Noun, PartOfSpeech::Adjective]);
+        assert!(defs.iter().all(|def| def.trans.len() == 1));
+        let defs = api.lookup_request("en-ru", "rust").send_def().unwrap();
+        assert_eq!(defs.len(), 3);
+        assert_eq!(defs[0].trans.len(), 2);
+    }
+
+    #[test]
+    fn check_langs_ttl() {
+        let transport = MockTransport::with_fixtures();
+        let api = Api::from_token("token").unwrap().with_transport(transport.clone());
+        assert!(api.supports("en-ru").unwrap());
+        assert!(!api.supports("xx-yy").unwrap());
+        assert!(api.get_langs().unwrap().contains(&"en-ru".to_owned()));
+        assert!(api.lang_pairs().unwrap().targets_for("en").contains(&"ru"));
+        assert_eq!(transport.requests().len(), 1);
+
+        let transport = MockTransport::with_fixtures();
+        let api = Api::from_token("token").unwrap()
+            .with_transport(transport.clone())
+            .with_langs_ttl(Duration::from_secs(0));
+        assert!(api.supports("en-ru").unwrap());
+        assert!(api.supports("en-ru").unwrap());
+        assert_eq!(transport.requests().len(), 2);
+    }
+
+    #[test]
+    fn check_custom_headers() {
+        let transport = MockTransport::with_fixtures();
+        let api = Api::from_token("token").unwrap()
+            .with_transport(transport.clone())
+            .with_user_agent("default/1.0")
+            .with_header("X-Request-Id", "42")
+            .with_user_agent("app/1.0");
+        api.lookup_def("en-ru", "rust").unwrap();
+        api.clone().with_timeout(Duration::from_secs(5)).get_langs().unwrap();
+        let expected = vec![
+            ("X-Request-Id".to_owned(), "42".to_owned()),
+            ("User-Agent".to_owned(), "app/1.0".to_owned()),
+        ];
+        assert_eq!(transport.headers(), vec![expected.clone(), expected]);
+    }
+
+    #[test]
+    fn check_post_long_text() {
+        let transport = MockTransport::with_fixtures();
+        let api = Api::from_token("token").unwrap()
+            .with_base_url("http://localhost")
+            .with_transport(transport.clone())
+            .with_post_threshold(100);
+        assert_eq!(api.lookup_def("en-ru", "rust").unwrap().len(), 3);
+        assert!(transport.forms().is_empty());
+        let text = "iron ".repeat(30);
+        assert!(api.lookup_def("en-ru", &text).unwrap().is_empty());
+        assert_eq!(transport.requests()[1], "http://localhost/lookup");
+        assert_eq!(transport.forms(), vec![format!("key=token&lang=en-ru&text={}", text.replace(' ', "+"))]);
+    }
+
+    // Serves langs with ETag and answers 304 when it's sent back.
+    #[derive(Clone, Default)]
+    struct EtagTransport {
+        requests: Arc<Mutex<Vec<Validators>>>,
+    }
+
+    impl HttpTransport for EtagTransport {
+        fn get(&self, url: &str) -> Result<(u16, String), RequestError> {
+            self.get_conditional(url, &Validators::default()).map(|(status, body, _)| (status, body))
+        }
+
+        fn get_conditional(&self, _url: &str, cached: &Validators)
+            -> Result<(u16, String, Validators), RequestError> {
+            self.requests.lock().unwrap().push(cached.clone());
+            let validators = Validators {
+                etag: Some("\"v1\"".to_owned()),
+                ..Validators::default()
+            };
+            if cached.etag == validators.etag {
+                Ok((304, String::new(), validators))
+            } else {
+                Ok((200, r#"["en-ru", "ru-en"]"#.to_owned(), validators))
+            }
+        }
+    }
+
+    #[test]
+    fn check_langs_not_modified() {
+        let transport = EtagTransport::default();
+        let api = Api::from_token("token").unwrap()
+            .with_transport(transport.clone())
+            .with_langs_ttl(Duration::from_secs(0));
+        assert_eq!(api.get_langs().unwrap().len(), 2);
+        assert_eq!(api.get_langs().unwrap().len(), 2);
+        let requests = transport.requests.lock().unwrap();
+        assert_eq!(requests.len(), 2);
+        assert!(requests[0].is_empty());
+        assert_eq!(requests[1].etag.as_ref().unwrap(), "\"v1\"");
+    }
+
+    // Answers with an empty lookup after a delay.
+    struct SlowTransport {
+        delay: Duration,
+    }
+
+    impl HttpTransport for SlowTransport {
+        fn get(&self, _url: &str) -> Result<(u16, String), RequestError> {
+            ::std::thread::sleep(self.delay);
+            Ok((200, r#"{"head": {}, "def": []}"#.to_owned()))
+        }
+    }
+
+    #[test]
+    fn check_timeout() {
+        let api = Api::from_token("token").unwrap()
+            .with_transport(SlowTransport { delay: Duration::from_millis(200) })
+            .with_timeout(Duration::from_millis(20));
+        match api.lookup_def("en-ru", "rust") {
+            Err(RequestError::Timeout) => (),
+            other => panic!("unexpected result: {:?}", other.map(|defs| defs.len())),
+        }
+        let defs = api.lookup_request("en-ru", "rust").timeout(Duration::from_secs(5)).send_def();
+        assert!(defs.unwrap().is_empty());
+    }
+
+    #[test]
+    fn check_cancel_request() {
+        let api = Api::from_token("token").unwrap()
+            .with_transport(SlowTransport { delay: Duration::from_secs(5) });
+        let cancel = CancellationToken::new();
+        let canceller = cancel.clone();
+        thread::spawn(move || {
+            thread::sleep(Duration::from_millis(50));
+            canceller.cancel();
+        });
+        let started = Instant::now();
+        match api.lookup_request("en-ru", "rust").cancel(&cancel).send_def() {
+            Err(RequestError::Cancelled) => (),
+            other => panic!("unexpected result: {:?}", other.map(|defs| defs.len())),
+        }
+        assert!(started.elapsed() < Duration::from_secs(2));
+    }
+
+    #[test]
+    fn check_lookup_raw() {
+        let transport = MockTransport::new()
+            .on("text=rust", 200, FIXTURE_LOOKUP_RUST)
+            .on("text=blocked", 403, FIXTURE_KEY_INVALID);
+        let api = Api::from_token("token").unwrap().with_transport(transport);
+        let raw = api.lookup_raw("en-ru", "rust", &LookupOptions::default()).unwrap();
+        assert_eq!(raw.status, 200);
+        assert_eq!(raw.body, FIXTURE_LOOKUP_RUST);
+        let raw = api.lookup_raw("en-ru", "blocked", &LookupOptions::default()).unwrap();
+        assert_eq!(raw.status, 403);
+        assert_eq!(raw.body, FIXTURE_KEY_INVALID);
+    }
+
+    #[test]
+    fn check_daily_budget() {
+        let transport = MockTransport::with_fixtures();
+        let api = Api::from_token("token").unwrap()
+            .with_transport(transport.clone())
+            .with_daily_budget(2);
+        assert_eq!(api.remaining_estimate(), Some(2));
+        assert!(api.lookup_def("en-ru", "rust").is_ok());
+        assert!(api.lookup_def("en-ru", "iron").is_ok());
+        assert_eq!(api.remaining_estimate(), Some(0));
+        match api.lookup_def("en-ru", "steel") {
+            Err(RequestError::RateLimited) => (),
+            other => panic!("unexpected result: {:?}", other.map(|defs| defs.len())),
+        }
+        assert_eq!(transport.requests().len(), 2);
+        assert_eq!(Api::from_token("token").unwrap().remaining_estimate(), None);
+    }
+
+    #[test]
+    fn check_tokens_failover() {
+        let transport = MockTransport::new()
+            .on("key=first", 403, FIXTURE_KEY_INVALID)
+            .on("key=second&", 200, FIXTURE_LOOKUP_RUST);
+        let api = Api::from_tokens(&["first", "second"]).unwrap().with_transport(transport.clone());
+        assert_eq!(api.lookup_def("en-ru", "rust").unwrap().len(), 3);
+        assert_eq!(api.lookup_def("en-ru", "rust").unwrap().len(), 3);
+        // The rejected token isn't tried again
+        assert_eq!(transport.requests().len(), 3);
+
+        let transport = MockTransport::new().on("/lookup?", 403, FIXTURE_KEY_INVALID);
+        let api = Api::from_tokens(&["first", "second"]).unwrap().with_transport(transport.clone());
+        match api.lookup_def("en-ru", "rust") {
+            Err(RequestError::KeyInvalid) => (),
+            other => panic!("unexpected result: {:?}", other.map(|defs| defs.len())),
+        }
+        assert_eq!(transport.requests().len(), 2);
+        assert!(Api::from_tokens(&[]).is_err());
+    }
+
+    #[test]
+    fn check_validate_key() {
+        let transport = MockTransport::new()
+            .on("key=valid", 200, FIXTURE_GET_LANGS)
+            .on("key=invalid", 403, FIXTURE_KEY_INVALID)
+            .on("key=blocked", 403, r#"{"code":402,"message":"API key is blocked"}"#)
+            .on("key=exhausted", 403, r#"{"code":403,"message":"Daily limit exceeded"}"#);
+        let status = |token| Api::from_token(token).unwrap().with_transport(transport.clone()).validate_key();
+        assert_eq!(status("valid").unwrap(), KeyStatus::Valid);
+        assert_eq!(status("invalid").unwrap(), KeyStatus::Invalid);
+        assert_eq!(status("blocked").unwrap(), KeyStatus::Blocked);
+        match status("exhausted") {
+            Err(RequestError::DailyLimitExceeded) => (),
+            other => panic!("unexpected result: {:?}", other),
+        }
+
+        // Only the token in use is checked
+        let api = Api::from_tokens(&["invalid", "valid"]).unwrap().with_transport(transport.clone());
+        assert_eq!(api.validate_key().unwrap(), KeyStatus::Invalid);
+        assert!(api.get_langs().is_ok());
+        assert_eq!(api.validate_key().unwrap(), KeyStatus::Valid);
+    }
+
+    #[test]
+    fn check_clone_shares_state() {
+        let transport = MockTransport::new()
+            .on("key=first", 403, FIXTURE_KEY_INVALID)
+            .on("key=second&", 200, FIXTURE_LOOKUP_RUST);
+        let api = Api::from_tokens(&["first", "second"]).unwrap()
+            .with_transport(transport.clone())
+            .with_cache(10);
+        let workers: Vec<_> = (0..4).map(|_| {
+            let api = api.clone();
+            thread::spawn(move || api.lookup_def("en-ru", "rust").unwrap().len())
+        }).collect();
+        for worker in workers {
+            assert_eq!(worker.join().unwrap(), 3);
+        }
+        // Clones see answers cached by others and know the rejected token
+        let requests = transport.requests().len();
+        assert_eq!(api.clone().lookup_def("en-ru", "rust").unwrap().len(), 3);
+        assert_eq!(transport.requests().len(), requests);
+        api.clone().lookup_def("en-ru", "iron").unwrap();
+        assert_eq!(transport.requests().len(), requests + 1);
+        assert!(transport.requests().last().unwrap().contains("key=second"));
+    }
+
+    #[test]
+    fn check_lookup_auto() {
+        let transport = MockTransport::with_fixtures();
+        let api = Api::from_token("token").unwrap().with_transport(transport.clone());
+        assert_eq!(api.lookup_auto(("ru", "en"), "rust").unwrap().len(), 3);
+        assert!(transport.requests()[0].contains("lang=en-ru&"));
+    }
+
+    #[test]
+    fn check_lookup_both() {
+        let transport = MockTransport::new()
+            .on("lang=en-ru&text=rust", 200, FIXTURE_LOOKUP_RUST)
+            .on("lang=ru-en&", 200, r#"{"head": {}, "def": [{"text": "rust", "pos": "noun", "tr": [{"text": "Rust"}]}]}"#);
+        let api = Api::from_token("token").unwrap().with_transport(transport);
+        let defs = api.lookup_both("en-ru", "rust").unwrap();
+        assert_eq!(defs.len(), 3);
+        assert_eq!(defs[0].trans.len(), 3);
+        assert_eq!(defs[0].trans[2].text, "Rust");
+    }
+
+    #[test]
+    fn check_error_context() {
+        let transport = MockTransport::new()
+            .on("text=blocked", 403, FIXTURE_KEY_INVALID)
+            .on("/lookup?", 200, FIXTURE_LOOKUP_RUST);
+        let api = Api::from_token("token").unwrap().with_transport(transport);
+        let results = api.lookup_batch("en-ru", &["rust", "blocked"]);
+        assert!(results[0].1.is_ok());
+        let error = results[1].1.as_ref().err().unwrap();
+        match *error.root() {
+            RequestError::KeyInvalid => (),
+            ref other => panic!("unexpected error: {}", other),
+        }
+        assert_eq!(error.kind(), "key_invalid");
+        assert_eq!(error.context().unwrap().status, Some(403));
+        assert_eq!(error.to_string(), "lookup of 'blocked' in en-ru (HTTP 403): API key is invalid");
+    }
+
+    #[test]
+    fn check_redact_key() {
+        assert_eq!(redact_key("https://host/lookup?key=secret&lang=en-ru"), "https://host/lookup?key=***&lang=en-ru");
+        assert_eq!(redact_key("https://host/getLangs?key=secret"), "https://host/getLangs?key=***");
+        assert_eq!(redact_key("https://host/checkText?text=monkey=1"), "https://host/checkText?text=monkey=1");
+    }
+
+    #[test]
+    fn check_key_not_shown() {
+        use config::Config;
+        use middleware::Request;
+        let api = Api::from_tokens(&["secret-a", "secret-b"]).unwrap()
+            .with_header("Authorization", "secret-c");
+        let config = Config {
+            token: Some("secret-d".to_owned()),
+            tts_key: Some("secret-e".to_owned()),
+            ..Config::default()
+        };
+        let url = api_url(super::API_URL, "lookup", &[("key", "secret-f"), ("lang", "en-ru"), ("text", "rust")]);
+        let request = Request::new("lookup", &url, &api.headers, &Validators::default(), None);
+        let shown = vec![
+            format!("{:?}", api),
+            format!("{:?}", api.translator("secret-g")),
+            format!("{:?}", config),
+            format!("{:?}", request),
+        ];
+        for text in shown {
+            assert!(!text.contains("secret"), "key is shown in {}", text);
+        }
+    }
+
+    #[cfg(feature = "ureq")]
+    #[test]
+    fn check_ureq_error_hides_key() {
+        use transport::UreqTransport;
+        let e = UreqTransport::new().get("http://127.0.0.1:1/lookup?key=secret").unwrap_err();
+        assert!(!format!("{:?}", e).contains("secret"));
+        assert!(!e.to_string().contains("secret"));
+    }
+}
@@ -0,0 +1,59 @@
+use std::time::Duration;
+
+// Outcome of a single request to the service.
+pub struct RequestEvent<'a> {
+    // API method like "lookup" or "getLangs".
+    pub method: &'a str,
+    pub latency: Duration,
+    // HTTP status if a response was received.
+    pub status: Option<u16>,
+    // `RequestError::kind` of the failure.
+    pub error: Option<&'static str>,
+}
+
+// Receives an event per request sent, including every retry. Implement it
+// to feed Prometheus, statsd and alike, it's called on the requesting thread.
+pub trait Metrics: Send + Sync {
+    fn record(&self, event: &RequestEvent);
+}
+
+#[cfg(test)]
+mod tests {
+
+    use std::sync::{Arc, Mutex};
+    use super::{Metrics, RequestEvent};
+    use super::super::Api;
+    use testing::{MockTransport, FIXTURE_KEY_INVALID};
+
+    // Method, status and error of a request.
+    type Event = (String, Option<u16>, Option<&'static str>);
+
+    #[derive(Clone, Default)]
+    struct Recorder {
+        events: Arc<Mutex<Vec<Event>>>,
+    }
+
+    impl Metrics for Recorder {
+        fn record(&self, event: &RequestEvent) {
+            self.events.lock().unwrap().push((event.method.to_owned(), event.status, event.error));
+        }
+    }
+
+    #[test]
+    fn check_metrics() {
+        let recorder = Recorder::default();
+        let transport = MockTransport::new()
+            .on("text=blocked", 403, FIXTURE_KEY_INVALID)
+            .on("/getLangs?", 200, r#"["en-ru"]"#);
+        let api = Api::from_token("token").unwrap()
+            .with_transport(transport)
+            .with_metrics(recorder.clone());
+        api.get_langs().unwrap();
+        assert!(api.lookup("en-ru", "blocked").is_err());
+        let events = recorder.events.lock().unwrap();
+        assert_eq!(*events, vec![
+            ("getLangs".to_owned(), Some(200), None),
+            ("lookup".to_owned(), Some(403), Some("key_invalid")),
+        ]);
+    }
+}
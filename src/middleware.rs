@@ -0,0 +1,471 @@
+// Stages every HTTP request of `Api` goes through on its way to the
+// transport. Retries, the quota and the rate limit, logging and metrics
+// are stages too, the ones added with `Api::with_middleware` go before them.
+
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use cancel::{self, CancellationToken};
+use metrics::{Metrics, RequestEvent};
+use retry::{is_transient, parse_retry_after};
+use stats::Stats;
+use transport::Validators;
+use unwind;
+use super::{Api, RequestError, RetryPolicy, Warning, header_names, redact_key, response_to_json, unix_now};
+
+#[derive(Clone)]
+pub struct Request<'a> {
+    // API method like "lookup" or "getLangs".
+    pub method: String,
+    // Full URL with the key in the query.
+    pub url: String,
+    pub headers: Vec<(String, String)>,
+    // Validators of a cached answer for a conditional request.
+    pub validators: Validators,
+    cancel: Option<&'a CancellationToken>,
+}
+
+// The key in the URL and values of headers are hidden.
+impl<'a> fmt::Debug for Request<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Request")
+            .field("method", &self.method)
+            .field("url", &redact_key(&self.url))
+            .field("headers", &header_names(&self.headers))
+            .field("validators", &self.validators)
+            .finish()
+    }
+}
+
+impl<'a> Request<'a> {
+    // Token of the call, waits of stages should end when it's cancelled.
+    pub fn cancel(&self) -> Option<&'a CancellationToken> {
+        self.cancel
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Response {
+    pub status: u16,
+    pub body: String,
+    pub validators: Validators,
+}
+
+impl Response {
+    // Error the service reports with the response, if any.
+    pub fn error(&self) -> Option<RequestError> {
+        match self.status {
+            200 | 304 => None,
+            status => response_to_json(status, &self.body).err(),
+        }
+    }
+
+    // Wait asked by Retry-After header, e.g. of 429 or 503 response.
+    pub fn retry_after(&self) -> Option<Duration> {
+        self.validators.retry_after.as_ref().and_then(|value| parse_retry_after(value, unix_now()))
+    }
+}
+
+pub trait Middleware: Send + Sync {
+    // Passes the request on with `next.run`, possibly changed, or
+    // answers it without sending.
+    fn handle(&self, request: Request, next: Next) -> Result<Response, RequestError>;
+}
+
+// Rest of the chain after a stage, it can be run many times, e.g. to retry.
+#[derive(Clone, Copy)]
+pub struct Next<'a> {
+    chain: &'a [&'a dyn Middleware],
+    endpoint: &'a dyn Fn(Request) -> Result<Response, RequestError>,
+}
+
+impl<'a> Next<'a> {
+    pub(crate) fn new(chain: &'a [&'a dyn Middleware], endpoint: &'a dyn Fn(Request) -> Result<Response, RequestError>)
+        -> Next<'a> {
+        Next {
+            chain: chain,
+            endpoint: endpoint,
+        }
+    }
+
+    // A panic of a stage fails the request with `RequestError::CallbackPanicked`.
+    pub fn run(self, request: Request) -> Result<Response, RequestError> {
+        match self.chain.split_first() {
+            Some((stage, rest)) => {
+                let next = Next::new(rest, self.endpoint);
+                unwind::catch("middleware", || stage.handle(request, next))?
+            },
+            None => (self.endpoint)(request),
+        }
+    }
+}
+
+impl<'a> Request<'a> {
+    pub(crate) fn new(method: &str, url: &str, headers: &[(String, String)], validators: &Validators,
+                      cancel: Option<&'a CancellationToken>) -> Request<'a> {
+        Request {
+            method: method.to_owned(),
+            url: url.to_owned(),
+            headers: headers.to_vec(),
+            validators: validators.clone(),
+            cancel: cancel,
+        }
+    }
+}
+
+impl Middleware for RetryPolicy {
+    fn handle(&self, request: Request, next: Next) -> Result<Response, RequestError> {
+        self.run(request, next, &|_, _, _| ())
+    }
+}
+
+type Attempt = Result<Response, RequestError>;
+
+impl RetryPolicy {
+    // Calls `on_retry` with the number of the failed attempt, the delay
+    // and the failure before every retry.
+    fn run(&self, request: Request, next: Next, on_retry: &dyn Fn(u32, Duration, &Attempt))
+        -> Result<Response, RequestError> {
+        let mut attempt = 1;
+        loop {
+            let result = next.run(request.clone());
+            let failed = match result {
+                Ok(ref response) => response.error().is_some_and(|e| (self.retry_if)(&e)),
+                Err(ref e) => (self.retry_if)(e),
+            };
+            if !failed || attempt >= self.max_attempts {
+                return result;
+            }
+            let delay = match result.as_ref().ok().and_then(Response::retry_after) {
+                Some(wait) if wait > self.max_retry_after => return result,
+                Some(wait) => wait,
+                None => self.delay(attempt),
+            };
+            log_debug!("attempt {} of {} failed, retrying in {:?}", attempt, request.method, delay);
+            on_retry(attempt, delay, &result);
+            cancel::pause(delay, request.cancel)?;
+            attempt += 1;
+        }
+    }
+}
+
+// Retries of `Api` by its policy, which are reported as warnings.
+pub(crate) struct Retrying<'a>(pub &'a Api);
+
+impl<'a> Middleware for Retrying<'a> {
+    fn handle(&self, request: Request, next: Next) -> Result<Response, RequestError> {
+        let method = request.method.clone();
+        self.0.retry.run(request, next, &|attempt, delay, result| self.0.warn(|| Warning::Retry {
+            method: method.clone(),
+            attempt: attempt,
+            delay: delay,
+            error: match *result {
+                Ok(ref response) => response.error().map_or_else(|| response.status.to_string(), |e| e.to_string()),
+                Err(ref e) => e.to_string(),
+            },
+        }))
+    }
+}
+
+// Applies the quota and the rate limit of `Api`.
+pub(crate) struct Throttle<'a>(pub &'a Api);
+
+impl<'a> Middleware for Throttle<'a> {
+    fn handle(&self, request: Request, next: Next) -> Result<Response, RequestError> {
+        self.0.throttle(request.cancel)?;
+        next.run(request)
+    }
+}
+
+// Logs requests of `Api`, longer URLs than its threshold go as POST.
+pub(crate) struct Logger(pub usize);
+
+impl Middleware for Logger {
+    fn handle(&self, request: Request, next: Next) -> Result<Response, RequestError> {
+        #[cfg(feature = "tracing")]
+        let _span = ::tracing::debug_span!("yadict_request", method = request.method.as_str()).entered();
+        let started = Instant::now();
+        let url = request.url.clone();
+        let post = url.len() > self.0;
+        let result = next.run(request);
+        log_debug!("{} {} -> {} in {:?}", if post { "POST" } else { "GET" }, redact_key(&url), match result {
+            Ok(ref response) => response.status.to_string(),
+            Err(ref e) => e.to_string(),
+        }, started.elapsed());
+        result
+    }
+}
+
+pub(crate) struct Recorder<'a>(pub &'a dyn Metrics);
+
+impl<'a> Middleware for Recorder<'a> {
+    fn handle(&self, request: Request, next: Next) -> Result<Response, RequestError> {
+        let started = Instant::now();
+        let method = request.method.clone();
+        let result = next.run(request);
+        let error = match result {
+            Ok(ref response) => response.error().map(|e| e.kind()),
+            Err(ref e) => Some(e.kind()),
+        };
+        self.0.record(&RequestEvent {
+            method: &method,
+            latency: started.elapsed(),
+            status: result.as_ref().ok().map(|response| response.status),
+            error: error,
+        });
+        result
+    }
+}
+
+// Counts requests in `Api::stats`.
+pub(crate) struct Counter<'a>(pub &'a Api);
+
+impl<'a> Middleware for Counter<'a> {
+    fn handle(&self, request: Request, next: Next) -> Result<Response, RequestError> {
+        let started = Instant::now();
+        let result = next.run(request);
+        let (bytes, error) = match result {
+            Ok(ref response) => (response.body.len(), response.error().map(|e| e.kind())),
+            Err(ref e) => (0, Some(e.kind())),
+        };
+        self.0.count(Stats::request(started.elapsed(), bytes, error));
+        result
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CircuitState {
+    Closed,
+    // Calls fail with `RequestError::CircuitOpen` until the cooldown ends.
+    Open,
+    // The cooldown is over, one call tries the service.
+    HalfOpen,
+}
+
+#[derive(Debug)]
+enum Circuit {
+    Closed { failures: u32 },
+    Open { until: Instant },
+    // A trial call is in progress.
+    Trial,
+}
+
+// Fails calls fast after `threshold` consecutive failures, e.g. to stop
+// a batch job when the service is down or the key is blocked. After
+// `cooldown` one call is let through: its success closes the circuit,
+// a failure opens it again. Add it with `Api::with_middleware`, clones
+// share the state, so a kept clone can show it.
+#[derive(Clone)]
+pub struct CircuitBreaker {
+    threshold: u32,
+    cooldown: Duration,
+    fail_if: fn(&RequestError) -> bool,
+    circuit: Arc<Mutex<Circuit>>,
+}
+
+impl CircuitBreaker {
+    pub fn new(threshold: u32, cooldown: Duration) -> CircuitBreaker {
+        CircuitBreaker {
+            threshold: threshold,
+            cooldown: cooldown,
+            fail_if: is_outage,
+            circuit: Arc::new(Mutex::new(Circuit::Closed { failures: 0 })),
+        }
+    }
+
+    // Errors which count as failures, `is_outage` by default. Others
+    // neither count nor reset the count.
+    pub fn with_fail_if(mut self, fail_if: fn(&RequestError) -> bool) -> CircuitBreaker {
+        self.fail_if = fail_if;
+        self
+    }
+
+    pub fn state(&self) -> CircuitState {
+        match *unwind::lock(&self.circuit) {
+            Circuit::Closed { .. } => CircuitState::Closed,
+            Circuit::Open { until } if until > Instant::now() => CircuitState::Open,
+            Circuit::Open { .. } | Circuit::Trial => CircuitState::HalfOpen,
+        }
+    }
+
+    // Whether a call may go on, it becomes the trial one after the cooldown.
+    fn admit(&self) -> bool {
+        let mut circuit = unwind::lock(&self.circuit);
+        match *circuit {
+            Circuit::Closed { .. } => true,
+            Circuit::Open { until } if until <= Instant::now() => {
+                *circuit = Circuit::Trial;
+                true
+            },
+            Circuit::Open { .. } | Circuit::Trial => false,
+        }
+    }
+
+    fn settle(&self, failed: Option<bool>) {
+        let mut circuit = unwind::lock(&self.circuit);
+        let next = match (&*circuit, failed) {
+            (&Circuit::Closed { failures }, Some(true)) if failures + 1 >= self.threshold => None,
+            (&Circuit::Closed { failures }, Some(true)) => Some(Circuit::Closed { failures: failures + 1 }),
+            (&Circuit::Trial, Some(true)) => None,
+            (_, Some(false)) => Some(Circuit::Closed { failures: 0 }),
+            // Other errors of the trial call don't tell anything
+            (&Circuit::Trial, None) => Some(Circuit::Open { until: Instant::now() }),
+            _ => return,
+        };
+        *circuit = next.unwrap_or_else(|| {
+            log_debug!("circuit is open for {:?}", self.cooldown);
+            Circuit::Open { until: Instant::now() + self.cooldown }
+        });
+    }
+}
+
+impl Middleware for CircuitBreaker {
+    fn handle(&self, request: Request, next: Next) -> Result<Response, RequestError> {
+        if !self.admit() {
+            return Err(RequestError::CircuitOpen);
+        }
+        let result = next.run(request);
+        let error = match result {
+            Ok(ref response) => response.error().map(|e| (self.fail_if)(&e)),
+            Err(ref e) => Some((self.fail_if)(e)),
+        };
+        // Errors which don't count tell nothing
+        self.settle(match error {
+            None => Some(false),
+            Some(true) => Some(true),
+            Some(false) => None,
+        });
+        result
+    }
+}
+
+// Failures of the service or of the network, and rejected keys: further
+// calls would fail the same way.
+pub fn is_outage(error: &RequestError) -> bool {
+    match *error.root() {
+        RequestError::KeyInvalid | RequestError::KeyBlocked | RequestError::DailyLimitExceeded => true,
+        ref e => is_transient(e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use std::sync::{Arc, Mutex};
+    use std::time::{Duration, Instant};
+    use super::{CircuitBreaker, CircuitState, Middleware, Next, Request, Response};
+    use super::super::{Api, RequestError, RetryPolicy};
+    use testing::{MockTransport, FIXTURE_LOOKUP_RUST};
+
+    // Adds a header and remembers methods of requests.
+    #[derive(Clone, Default)]
+    struct Tagger {
+        methods: Arc<Mutex<Vec<String>>>,
+    }
+
+    impl Middleware for Tagger {
+        fn handle(&self, mut request: Request, next: Next) -> Result<Response, RequestError> {
+            self.methods.lock().unwrap().push(request.method.clone());
+            request.headers.push(("X-Tag".to_owned(), "1".to_owned()));
+            next.run(request)
+        }
+    }
+
+    // Answers lookups of "offline" without the service.
+    struct Offline;
+
+    impl Middleware for Offline {
+        fn handle(&self, request: Request, next: Next) -> Result<Response, RequestError> {
+            if !request.url.contains("text=offline") {
+                return next.run(request);
+            }
+            Ok(Response {
+                status: 200,
+                body: FIXTURE_LOOKUP_RUST.to_owned(),
+                validators: Default::default(),
+            })
+        }
+    }
+
+    #[test]
+    fn check_middleware() {
+        let transport = MockTransport::new()
+            .on("text=flaky", 503, "")
+            .on("text=rust", 200, FIXTURE_LOOKUP_RUST);
+        let tagger = Tagger::default();
+        let api = Api::from_token("token").unwrap()
+            .with_transport(transport.clone())
+            .with_retry(RetryPolicy {
+                jitter: false,
+                base_delay: Default::default(),
+                ..RetryPolicy::default()
+            })
+            .with_middleware(tagger.clone())
+            .with_middleware(Offline);
+        assert_eq!(api.lookup_def("en-ru", "offline").unwrap().len(), 3);
+        assert!(transport.requests().is_empty());
+        api.lookup_def("en-ru", "rust").unwrap();
+        assert_eq!(transport.headers()[0], vec![("X-Tag".to_owned(), "1".to_owned())]);
+        // Retries happen after the middlewares of the user
+        assert!(api.lookup_def("en-ru", "flaky").is_err());
+        assert_eq!(transport.requests().len(), 4);
+        assert_eq!(*tagger.methods.lock().unwrap(), vec!["lookup", "lookup", "lookup"]);
+    }
+
+    #[test]
+    fn check_retry_after() {
+        let transport = MockTransport::new()
+            .on_retry_after("text=busy", 429, "0")
+            .on_retry_after("text=later", 503, "3600");
+        let api = Api::from_token("token").unwrap()
+            .with_transport(transport.clone())
+            .with_retry(RetryPolicy {
+                max_attempts: 2,
+                jitter: false,
+                base_delay: Duration::from_secs(10),
+                ..RetryPolicy::default()
+            });
+        // Retry-After replaces the delay of the policy
+        let started = Instant::now();
+        match api.lookup_def("en-ru", "busy") {
+            Err(RequestError::HttpStatus(429)) => (),
+            other => panic!("unexpected result: {:?}", other),
+        }
+        assert!(started.elapsed() < Duration::from_secs(5));
+        assert_eq!(transport.requests().len(), 2);
+        // Waits over `max_retry_after` aren't worth it
+        assert!(api.lookup_def("en-ru", "later").is_err());
+        assert_eq!(transport.requests().len(), 3);
+    }
+
+    #[test]
+    fn check_circuit_breaker() {
+        let transport = MockTransport::new()
+            .on("text=down", 503, "")
+            .on("text=rust", 200, FIXTURE_LOOKUP_RUST);
+        let breaker = CircuitBreaker::new(2, Duration::from_millis(100));
+        let api = Api::from_token("token").unwrap()
+            .with_transport(transport.clone())
+            .with_middleware(breaker.clone());
+        assert!(api.lookup_def("en-ru", "down").is_err());
+        api.lookup_def("en-ru", "rust").unwrap();
+        assert!(api.lookup_def("en-ru", "down").is_err());
+        assert_eq!(breaker.state(), CircuitState::Closed);
+        assert!(api.lookup_def("en-ru", "down").is_err());
+        assert_eq!(breaker.state(), CircuitState::Open);
+        match api.lookup_def("en-ru", "rust") {
+            Err(RequestError::CircuitOpen) => (),
+            other => panic!("unexpected result: {:?}", other),
+        }
+        assert_eq!(transport.requests().len(), 4);
+        ::std::thread::sleep(Duration::from_millis(150));
+        assert_eq!(breaker.state(), CircuitState::HalfOpen);
+        // Failed trial opens the circuit again, a successful one closes it
+        assert!(api.lookup_def("en-ru", "down").is_err());
+        assert_eq!(breaker.state(), CircuitState::Open);
+        ::std::thread::sleep(Duration::from_millis(150));
+        api.lookup_def("en-ru", "rust").unwrap();
+        assert_eq!(breaker.state(), CircuitState::Closed);
+        assert_eq!(transport.requests().len(), 6);
+    }
+}
@@ -0,0 +1,106 @@
+// Node.js addon for Electron apps and scripts, built with napi-rs:
+//
+//     const { Dictionary } = require('./yadict.node')
+//     const dict = new Dictionary(process.env.YADICT_TOKEN)
+//     const defs = await dict.lookup('en-ru', 'rust')
+//
+// Lookups run on the thread pool of libuv with the blocking `Api`, so
+// they share its cache, rate limit and budget. Definitions are plain
+// objects of the same shape as JSON of the service.
+
+use napi;
+use napi::bindgen_prelude::AsyncTask;
+use napi::{Env, Error, JsUnknown, Status, Task};
+use napi_derive::napi;
+use std::time::Duration;
+use super::{Api, ApiError, Def, LangPair, RequestError};
+
+fn api_error(e: ApiError) -> Error {
+    Error::new(Status::InvalidArg, e.to_string())
+}
+
+fn request_error(e: RequestError) -> Error {
+    Error::new(Status::GenericFailure, e.to_string())
+}
+
+#[napi]
+pub struct Dictionary {
+    api: Api,
+}
+
+#[napi]
+impl Dictionary {
+    #[napi(constructor)]
+    pub fn new(token: String, timeout_ms: Option<u32>, cache_size: Option<u32>) -> napi::Result<Dictionary> {
+        let mut api = Api::from_token(&token).map_err(api_error)?;
+        if let Some(timeout) = timeout_ms {
+            api = api.with_timeout(Duration::from_millis(timeout as u64));
+        }
+        if let Some(capacity) = cache_size {
+            api = api.with_cache(capacity as usize);
+        }
+        Ok(Dictionary {
+            api: api,
+        })
+    }
+
+    // Configured by YADICT_* variables, see `Api::from_env_config`.
+    #[napi(factory)]
+    pub fn from_env() -> napi::Result<Dictionary> {
+        Api::from_env_config().map(|api| Dictionary { api: api }).map_err(api_error)
+    }
+
+    // Promise of definitions, an empty array if the word is unknown.
+    #[napi(ts_return_type = "Promise<Array<object>>")]
+    pub fn lookup(&self, lang: String, text: String) -> napi::Result<AsyncTask<Lookup>> {
+        let lang = lang.parse::<LangPair>().map_err(|e| Error::new(Status::InvalidArg, e.to_string()))?;
+        Ok(AsyncTask::new(Lookup {
+            api: self.api.clone(),
+            lang: lang,
+            text: text,
+        }))
+    }
+
+    #[napi(ts_return_type = "Promise<Array<string>>")]
+    pub fn get_langs(&self) -> AsyncTask<GetLangs> {
+        AsyncTask::new(GetLangs {
+            api: self.api.clone(),
+        })
+    }
+}
+
+pub struct Lookup {
+    api: Api,
+    lang: LangPair,
+    text: String,
+}
+
+impl Task for Lookup {
+    type Output = Vec<Def>;
+    type JsValue = JsUnknown;
+
+    fn compute(&mut self) -> napi::Result<Vec<Def>> {
+        self.api.lookup_def(&self.lang, &self.text).map_err(request_error)
+    }
+
+    fn resolve(&mut self, env: Env, output: Vec<Def>) -> napi::Result<JsUnknown> {
+        env.to_js_value(&output)
+    }
+}
+
+pub struct GetLangs {
+    api: Api,
+}
+
+impl Task for GetLangs {
+    type Output = Vec<String>;
+    type JsValue = Vec<String>;
+
+    fn compute(&mut self) -> napi::Result<Vec<String>> {
+        self.api.get_langs().map_err(request_error)
+    }
+
+    fn resolve(&mut self, _env: Env, output: Vec<String>) -> napi::Result<Vec<String>> {
+        Ok(output)
+    }
+}
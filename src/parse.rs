@@ -0,0 +1,293 @@
+// Decoding of lookup answers. Strict mode fails on the first entry of
+// unexpected shape, lenient one skips such entries and reports them.
+
+use std::fmt;
+use serde::Deserialize;
+use serde_json::Value;
+use super::{Def, RequestError, Word, json_to_defs};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[derive(Default)]
+pub enum ParseMode {
+    #[default]
+    Strict,
+    Lenient,
+}
+
+
+// An entry skipped by lenient parsing: where it is in the answer, what
+// was expected there and the start of what was found, like
+// "def[1].text: expected string, found nothing".
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct ParseWarning {
+    pub path: String,
+    pub expected: String,
+    pub found: String,
+}
+
+impl fmt::Display for ParseWarning {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: expected {}, found {}", self.path, self.expected, self.found)
+    }
+}
+
+// Longest snippet of found JSON in a warning, in chars.
+const SNIPPET_LEN: usize = 40;
+
+impl ParseWarning {
+    fn new(path: String, expected: &str, found: Option<&Value>) -> ParseWarning {
+        let found = match found {
+            Some(value) => {
+                let json = value.to_string();
+                match json.char_indices().nth(SNIPPET_LEN) {
+                    Some((end, _)) => format!("{}\u{2026}", &json[..end]),
+                    None => json,
+                }
+            },
+            None => "nothing".to_owned(),
+        };
+        ParseWarning {
+            path: path,
+            expected: expected.to_owned(),
+            found: found,
+        }
+    }
+}
+
+// Definitions of a lookup answer with the entries skipped in lenient
+// mode. Strict mode fails instead, so warnings are always empty there.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct LookupResponse {
+    defs: Vec<Def>,
+    warnings: Vec<ParseWarning>,
+}
+
+impl LookupResponse {
+    pub fn defs(&self) -> &[Def] {
+        &self.defs
+    }
+
+    pub fn into_defs(self) -> Vec<Def> {
+        self.defs
+    }
+
+    pub fn warnings(&self) -> &[ParseWarning] {
+        &self.warnings
+    }
+
+    pub fn is_complete(&self) -> bool {
+        self.warnings.is_empty()
+    }
+}
+
+pub fn parse_defs(json: Value, mode: ParseMode) -> Result<LookupResponse, RequestError> {
+    match mode {
+        ParseMode::Strict => json_to_defs(json).map(|defs| LookupResponse {
+            defs: defs,
+            warnings: Vec::new(),
+        }),
+        ParseMode::Lenient => parse_lenient(json),
+    }
+}
+
+// Only the list of definitions itself is required.
+fn parse_lenient(json: Value) -> Result<LookupResponse, RequestError> {
+    let entries = match json {
+        Value::Object(mut map) => match map.remove("def") {
+            Some(Value::Array(entries)) => entries,
+            _ => return Err(RequestError::InvalidDataFormat),
+        },
+        _ => return Err(RequestError::InvalidDataFormat),
+    };
+    let mut response = LookupResponse::default();
+    for (idx, entry) in entries.into_iter().enumerate() {
+        if let Some(def) = parse_def(entry, &format!("def[{}]", idx), &mut response.warnings) {
+            response.defs.push(def);
+        }
+    }
+    Ok(response)
+}
+
+// Translations are decoded one by one, so a bad one doesn't take
+// the whole definition with it.
+fn parse_def(entry: Value, path: &str, warnings: &mut Vec<ParseWarning>) -> Option<Def> {
+    let mut entry = match entry {
+        Value::Object(entry) => entry,
+        other => {
+            warnings.push(ParseWarning::new(path.to_owned(), "object", Some(&other)));
+            return None;
+        },
+    };
+    let trans = match entry.remove("tr") {
+        Some(Value::Array(trans)) => trans,
+        other => {
+            warnings.push(ParseWarning::new(format!("{}.tr", path), "array", other.as_ref()));
+            Vec::new()
+        },
+    };
+    let word: Word = match parse_word(Value::Object(entry), path) {
+        Ok(word) => word,
+        Err(warning) => {
+            warnings.push(warning);
+            return None;
+        },
+    };
+    let trans = trans.into_iter()
+        .enumerate()
+        .filter_map(|(idx, tr)| match parse_word(tr, &format!("{}.tr[{}]", path, idx)) {
+            Ok(tr) => Some(tr),
+            Err(warning) => {
+                warnings.push(warning);
+                None
+            },
+        })
+        .collect();
+    Some(Def {
+        word: word,
+        trans: trans,
+    })
+}
+
+// The headword is checked by hand to point at it, other fields rely
+// on messages of serde.
+fn parse_word(entry: Value, path: &str) -> Result<Word, ParseWarning> {
+    if !entry.is_object() {
+        return Err(ParseWarning::new(path.to_owned(), "object", Some(&entry)));
+    }
+    match entry.get("text") {
+        Some(&Value::String(_)) => (),
+        text => return Err(ParseWarning::new(format!("{}.text", path), "string", text)),
+    }
+    Word::deserialize(&entry).map_err(|e| {
+        ParseWarning::new(path.to_owned(), &format!("word ({})", e), Some(&entry))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+
+    use serde_json;
+    use super::{ParseMode, ParseWarning, parse_defs};
+    use super::super::{Api, LookupOptions, PartOfSpeech};
+    use testing::{MockTransport, FIXTURE_LOOKUP_RUST};
+
+    fn warning(path: &str, expected: &str, found: &str) -> ParseWarning {
+        ParseWarning {
+            path: path.to_owned(),
+            expected: expected.to_owned(),
+            found: found.to_owned(),
+        }
+    }
+
+    const CORRUPTED: &'static str = r#"{"def": [
+        {"text": "rust", "tr": "ржавчина"},
+        "a very long string which is cut in the warning",
+        {"text": "rust", "syn": 5, "tr": []}
+    ]}"#;
+
+    const MALFORMED: &'static str = r#"{"head": {}, "def": [
+        {"text": "rust", "pos": "noun", "tr": [{"text": "corrosion"}, {"pos": "noun"}, {"text": ["oxide"], "fr": 1}]},
+        {"pos": "verb", "tr": [{"text": "oxidize"}]},
+        {"text": "rust", "pos": "adjective"}
+    ]}"#;
+
+    #[test]
+    fn check_parse_modes() {
+        let json = serde_json::from_str(MALFORMED).unwrap();
+        assert!(parse_defs(json, ParseMode::Strict).is_err());
+        let json = serde_json::from_str(MALFORMED).unwrap();
+        let response = parse_defs(json, ParseMode::Lenient).unwrap();
+        let defs = response.defs();
+        assert_eq!(defs.len(), 2);
+        assert_eq!(defs[0].trans.len(), 1);
+        assert_eq!(defs[0].trans[0].text, "corrosion");
+        assert_eq!(defs[1].word.pos, Some(PartOfSpeech::Adjective));
+        assert!(defs[1].trans.is_empty());
+        assert_eq!(response.warnings(), &[
+            warning("def[0].tr[1].text", "string", "nothing"),
+            warning("def[0].tr[2].text", "string", r#"["oxide"]"#),
+            warning("def[1].text", "string", "nothing"),
+            warning("def[2].tr", "array", "nothing"),
+        ][..]);
+        assert_eq!(response.warnings()[1].to_string(), r#"def[0].tr[2].text: expected string, found ["oxide"]"#);
+
+        let json = serde_json::from_str(CORRUPTED).unwrap();
+        let response = parse_defs(json, ParseMode::Lenient).unwrap();
+        assert_eq!(response.defs().len(), 1);
+        assert!(response.defs()[0].trans.is_empty());
+        assert_eq!(response.warnings(), &[
+            warning("def[0].tr", "array", r#""ржавчина""#),
+            warning("def[1]", "object", "\"a very long string which is cut in the \u{2026}"),
+            warning("def[2]", "word (invalid type: integer `5`, expected a sequence)",
+                    r#"{"syn":5,"text":"rust"}"#),
+        ][..]);
+
+        let json = serde_json::from_str(r#"{"head": {}}"#).unwrap();
+        assert!(parse_defs(json, ParseMode::Lenient).is_err());
+    }
+
+    // Answers of older and newer shapes than the fixtures of `testing`:
+    // without optional fields, with fields unknown to us and with nulls.
+    const COMPAT_OLD: &'static str = include_str!("../fixtures/compat_old.json");
+    const COMPAT_EXTENDED: &'static str = include_str!("../fixtures/compat_extended.json");
+    const COMPAT_NULLS: &'static str = include_str!("../fixtures/compat_nulls.json");
+
+    #[test]
+    fn check_compat_variants() {
+        let variants = vec![
+            (FIXTURE_LOOKUP_RUST, 3, 4),
+            (COMPAT_OLD, 1, 2),
+            (COMPAT_EXTENDED, 2, 2),
+            (COMPAT_NULLS, 1, 3),
+        ];
+        for (fixture, defs_count, trans_count) in variants {
+            for mode in [ParseMode::Strict, ParseMode::Lenient] {
+                let json = serde_json::from_str(fixture).unwrap();
+                let response = parse_defs(json, mode).unwrap();
+                assert_eq!(response.defs().len(), defs_count);
+                assert_eq!(response.defs().iter().map(|def| def.trans.len()).sum::<usize>(), trans_count);
+                assert!(response.is_complete(), "{:?}", response);
+            }
+            let transport = MockTransport::new().on("/lookup?", 200, fixture);
+            let api = Api::from_token("token").unwrap().with_transport(transport);
+            assert_eq!(api.lookup_def("en-ru", "cat").unwrap().len(), defs_count);
+        }
+
+        let json = serde_json::from_str(COMPAT_OLD).unwrap();
+        let defs = parse_defs(json, ParseMode::Strict).unwrap().into_defs();
+        assert!(defs[0].word.pos.is_none() && defs[0].word.ts.is_none());
+        assert_eq!(defs[0].trans[1].mean[0].text, "tomcat");
+
+        let json = serde_json::from_str(COMPAT_EXTENDED).unwrap();
+        let defs = parse_defs(json, ParseMode::Strict).unwrap().into_defs();
+        let tr = &defs[0].trans[0];
+        assert_eq!((tr.fr, tr.syn.len(), tr.mean.len()), (Some(10), 1, 1));
+        assert_eq!(tr.ex[0].tr, vec!["чёрная кошка"]);
+        assert_eq!(defs[1].trans[0].asp.as_deref(), Some("несов"));
+
+        let json = serde_json::from_str(COMPAT_NULLS).unwrap();
+        let defs = parse_defs(json, ParseMode::Strict).unwrap().into_defs();
+        assert!(defs[0].word.pos.is_none() && defs[0].word.ts.is_none());
+        let trans = &defs[0].trans;
+        assert_eq!(trans.iter().map(|tr| tr.fr).collect::<Vec<_>>(), vec![Some(5), Some(2), None]);
+        assert!(trans[0].syn.is_empty() && trans[0].mean.is_empty());
+        assert!(trans[0].ex[0].tr.is_empty());
+        // Tolerated values come back in the usual shape
+        let json = serde_json::to_value(&defs).unwrap();
+        assert_eq!(json[0]["tr"][0]["fr"], 5);
+        assert!(json[0].get("pos").is_none());
+    }
+
+    #[test]
+    fn check_lenient_api() {
+        let transport = MockTransport::new().on("/lookup?", 200, MALFORMED);
+        let api = Api::from_token("token").unwrap().with_transport(transport);
+        assert!(api.lookup_def("en-ru", "rust").is_err());
+        let api = api.with_parse_mode(ParseMode::Lenient);
+        assert_eq!(api.lookup_def("en-ru", "rust").unwrap().len(), 2);
+        let response = api.lookup_def_with_report("en-ru", "rust", &LookupOptions::default()).unwrap();
+        assert_eq!(response.defs().len(), 2);
+        assert_eq!(response.warnings().len(), 4);
+        assert_eq!(response.warnings()[3], warning("def[2].tr", "array", "nothing"));
+    }
+}
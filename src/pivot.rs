@@ -0,0 +1,119 @@
+// Lookups through a third language for directions the service lacks,
+// like fi-pt through English: translations of the word into the pivot
+// language are looked up in turn. Meanings drift on the way, so results
+// are kept apart from direct ones and rated.
+
+use std::cmp::Ordering;
+use super::{Api, Def, LangPair, PartOfSpeech, RequestError, normalize_headword};
+
+// Translations into the pivot language which are looked up further.
+pub const MAX_PIVOT_WORDS: usize = 5;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct PivotTranslation {
+    pub text: String,
+    pub pos: Option<PartOfSpeech>,
+    // Words of the pivot language which translate to this one.
+    pub via: Vec<String>,
+    // Share of looked up pivot words which lead here, from 0 to 1. A
+    // translation reached by several of them likely keeps the meaning.
+    pub confidence: f32,
+}
+
+// Result of `Api::lookup_via`, never a translation of the direction itself.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Pivoted {
+    pub lang: LangPair,
+    // Language code the translations went through.
+    pub pivot: String,
+    // Definitions of the word in the direction to the pivot language.
+    pub defs: Vec<Def>,
+    // Most confident first, ties in order of the pivot words.
+    pub trans: Vec<PivotTranslation>,
+}
+
+impl Pivoted {
+    pub fn is_empty(&self) -> bool {
+        self.trans.is_empty()
+    }
+}
+
+impl Api {
+    // Looks up `text` from the source language of `lang` to `pivot`, then
+    // up to `MAX_PIVOT_WORDS` translations from `pivot` to the target
+    // language, as a batch. Costs a request per pivot word unless cached.
+    pub fn lookup_via<L: Into<LangPair>>(&self, lang: L, pivot: &str, text: &str) -> Result<Pivoted, RequestError> {
+        let lang = lang.into();
+        let defs = self.lookup_def(LangPair::new(lang.source(), pivot), text)?;
+        let mut words: Vec<&str> = Vec::new();
+        for tr in defs.iter().flat_map(|def| def.trans.iter()) {
+            if words.len() < MAX_PIVOT_WORDS && !words.iter().any(|word| normalize_headword(word) == normalize_headword(&tr.text)) {
+                words.push(&tr.text);
+            }
+        }
+        let mut trans: Vec<PivotTranslation> = Vec::new();
+        for (word, result) in self.lookup_batch(LangPair::new(pivot, lang.target()), &words) {
+            for tr in result?.iter().flat_map(|def| def.trans.iter()) {
+                let key = normalize_headword(&tr.text);
+                match trans.iter_mut().find(|found| normalize_headword(&found.text) == key) {
+                    Some(found) => {
+                        if !found.via.contains(&word) {
+                            found.via.push(word.clone());
+                        }
+                    },
+                    None => trans.push(PivotTranslation {
+                        text: tr.text.clone(),
+                        pos: tr.pos.clone(),
+                        via: vec![word.clone()],
+                        confidence: 0.0,
+                    }),
+                }
+            }
+        }
+        for tr in &mut trans {
+            tr.confidence = tr.via.len() as f32 / words.len() as f32;
+        }
+        // Stable, so ties keep the order of pivot words
+        trans.sort_by(|a, b| b.confidence.partial_cmp(&a.confidence).unwrap_or(Ordering::Equal));
+        Ok(Pivoted {
+            pivot: pivot.to_owned(),
+            lang: lang,
+            defs: defs,
+            trans: trans,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::super::Api;
+    use testing::{MockTransport, FIXTURE_NOT_FOUND};
+
+    const KISSA: &'static str = r#"{"def": [{"text": "kissa", "pos": "noun", "tr": [
+        {"text": "cat", "pos": "noun"}, {"text": "pussy", "pos": "noun"}, {"text": "Cat", "pos": "noun"}]}]}"#;
+    const CAT: &'static str = r#"{"def": [{"text": "cat", "pos": "noun", "tr": [
+        {"text": "gato", "pos": "noun"}, {"text": "gata", "pos": "noun"}]}]}"#;
+    const PUSSY: &'static str = r#"{"def": [{"text": "pussy", "pos": "noun", "tr": [
+        {"text": "gatinho", "pos": "noun"}, {"text": "Gato", "pos": "noun"}]}]}"#;
+
+    #[test]
+    fn check_lookup_via() {
+        let transport = MockTransport::new()
+            .on("lang=fi-en&text=kissa", 200, KISSA)
+            .on("lang=en-pt&text=cat", 200, CAT)
+            .on("lang=en-pt&text=pussy", 200, PUSSY)
+            .on("/lookup?", 200, FIXTURE_NOT_FOUND);
+        let api = Api::from_token("token").unwrap().with_transport(transport.clone());
+        let pivoted = api.lookup_via("fi-pt", "en", "kissa").unwrap();
+        assert_eq!(pivoted.pivot, "en");
+        assert_eq!(pivoted.lang.as_str(), "fi-pt");
+        assert_eq!(pivoted.defs.len(), 1);
+        let trans: Vec<(&str, f32)> = pivoted.trans.iter().map(|tr| (tr.text.as_str(), tr.confidence)).collect();
+        assert_eq!(trans, vec![("gato", 1.0), ("gata", 0.5), ("gatinho", 0.5)]);
+        assert_eq!(pivoted.trans[0].via, vec!["cat", "pussy"]);
+        // "Cat" is the same pivot word as "cat"
+        assert_eq!(transport.requests().len(), 3);
+        assert!(api.lookup_via("fi-pt", "en", "qwzx").unwrap().is_empty());
+    }
+}
@@ -0,0 +1,117 @@
+use std::fmt;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+// Part of speech as the service names it. Parsing never fails, names
+// unknown to this crate are kept in `Other`.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum PartOfSpeech {
+    Noun,
+    Verb,
+    Adjective,
+    Adverb,
+    Pronoun,
+    Numeral,
+    Preposition,
+    Conjunction,
+    Interjection,
+    Particle,
+    Participle,
+    AdverbialParticiple,
+    Predicative,
+    Abbreviation,
+    Other(String),
+}
+
+impl PartOfSpeech {
+    pub fn as_str(&self) -> &str {
+        match *self {
+            PartOfSpeech::Noun => "noun",
+            PartOfSpeech::Verb => "verb",
+            PartOfSpeech::Adjective => "adjective",
+            PartOfSpeech::Adverb => "adverb",
+            PartOfSpeech::Pronoun => "pronoun",
+            PartOfSpeech::Numeral => "numeral",
+            PartOfSpeech::Preposition => "preposition",
+            PartOfSpeech::Conjunction => "conjunction",
+            PartOfSpeech::Interjection => "interjection",
+            PartOfSpeech::Particle => "particle",
+            PartOfSpeech::Participle => "participle",
+            PartOfSpeech::AdverbialParticiple => "adverbial participle",
+            PartOfSpeech::Predicative => "predicative",
+            PartOfSpeech::Abbreviation => "abbreviation",
+            PartOfSpeech::Other(ref name) => name,
+        }
+    }
+}
+
+impl<'a> From<&'a str> for PartOfSpeech {
+    fn from(name: &'a str) -> PartOfSpeech {
+        match name {
+            "noun" => PartOfSpeech::Noun,
+            "verb" => PartOfSpeech::Verb,
+            "adjective" => PartOfSpeech::Adjective,
+            "adverb" => PartOfSpeech::Adverb,
+            "pronoun" => PartOfSpeech::Pronoun,
+            "numeral" => PartOfSpeech::Numeral,
+            "preposition" => PartOfSpeech::Preposition,
+            "conjunction" => PartOfSpeech::Conjunction,
+            "interjection" => PartOfSpeech::Interjection,
+            "particle" => PartOfSpeech::Particle,
+            "participle" => PartOfSpeech::Participle,
+            "adverbial participle" => PartOfSpeech::AdverbialParticiple,
+            "predicative" => PartOfSpeech::Predicative,
+            "abbreviation" => PartOfSpeech::Abbreviation,
+            other => PartOfSpeech::Other(other.to_owned()),
+        }
+    }
+}
+
+impl fmt::Display for PartOfSpeech {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl Serialize for PartOfSpeech {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.as_str())
+    }
+}
+
+impl<'de> Deserialize<'de> for PartOfSpeech {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<PartOfSpeech, D::Error> {
+        let name = String::deserialize(deserializer)?;
+        Ok(PartOfSpeech::from(name.as_str()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use serde_json;
+    use super::PartOfSpeech;
+
+    #[test]
+    fn check_pos_names() {
+        let all = vec![
+            PartOfSpeech::Noun, PartOfSpeech::Verb, PartOfSpeech::Adjective,
+            PartOfSpeech::Adverb, PartOfSpeech::Pronoun, PartOfSpeech::Numeral,
+            PartOfSpeech::Preposition, PartOfSpeech::Conjunction, PartOfSpeech::Interjection,
+            PartOfSpeech::Particle, PartOfSpeech::Participle, PartOfSpeech::AdverbialParticiple,
+            PartOfSpeech::Predicative, PartOfSpeech::Abbreviation,
+        ];
+        for pos in all {
+            assert_eq!(PartOfSpeech::from(pos.as_str()), pos);
+        }
+        let other = PartOfSpeech::from("foreign word");
+        assert_eq!(other, PartOfSpeech::Other("foreign word".to_owned()));
+        assert_eq!(other.to_string(), "foreign word");
+    }
+
+    #[test]
+    fn check_pos_serde() {
+        let pos: PartOfSpeech = serde_json::from_str("\"adverbial participle\"").unwrap();
+        assert_eq!(pos, PartOfSpeech::AdverbialParticiple);
+        assert_eq!(serde_json::to_string(&pos).unwrap(), "\"adverbial participle\"");
+    }
+}
@@ -0,0 +1,160 @@
+// Python extension module over the blocking `Api`, built with maturin:
+//
+//     import yadict
+//     api = yadict.Api.from_env()
+//     for d in api.lookup("en-ru", "rust"):
+//         print(d["text"], [tr["text"] for tr in d["tr"]])
+//
+// Definitions are dicts of the same shape as JSON of the service. Errors
+// of requests raise `yadict.YadictError`, invalid directions ValueError.
+// The GIL is released while requests are sent.
+
+use pyo3::create_exception;
+use pyo3::exceptions::{PyException, PyValueError};
+use pyo3::prelude::*;
+use pyo3::types::{PyDict, PyList};
+use pyo3::IntoPyObjectExt;
+use serde_json::{self, Value};
+use std::time::Duration;
+use super::{Api, ApiError, LangPair, LangPairError, RequestError};
+
+create_exception!(yadict, YadictError, PyException);
+
+fn request_error(e: RequestError) -> PyErr {
+    YadictError::new_err(e.to_string())
+}
+
+fn api_error(e: ApiError) -> PyErr {
+    YadictError::new_err(e.to_string())
+}
+
+fn lang_pair(lang: &str) -> PyResult<LangPair> {
+    lang.parse().map_err(|e: LangPairError| PyValueError::new_err(e.to_string()))
+}
+
+fn to_python<'py>(py: Python<'py>, value: &Value) -> PyResult<Bound<'py, PyAny>> {
+    match *value {
+        Value::Null => Ok(py.None().into_bound(py)),
+        Value::Bool(b) => b.into_bound_py_any(py),
+        Value::Number(ref n) => match n.as_i64() {
+            Some(n) => n.into_bound_py_any(py),
+            None => n.as_f64().unwrap_or_default().into_bound_py_any(py),
+        },
+        Value::String(ref s) => s.into_bound_py_any(py),
+        Value::Array(ref items) => {
+            let list = PyList::empty(py);
+            for item in items {
+                list.append(to_python(py, item)?)?;
+            }
+            Ok(list.into_any())
+        },
+        Value::Object(ref map) => {
+            let dict = PyDict::new(py);
+            for (key, item) in map {
+                dict.set_item(key, to_python(py, item)?)?;
+            }
+            Ok(dict.into_any())
+        },
+    }
+}
+
+#[pyclass(name = "Api", module = "yadict")]
+struct PyApi {
+    api: Api,
+}
+
+#[pymethods]
+impl PyApi {
+    #[new]
+    #[pyo3(signature = (token, base_url = None, timeout_ms = None, cache_size = None))]
+    fn new(token: &str, base_url: Option<&str>, timeout_ms: Option<u64>, cache_size: Option<usize>)
+        -> PyResult<PyApi> {
+        let mut api = Api::from_token(token).map_err(api_error)?;
+        if let Some(url) = base_url {
+            api = api.with_base_url(url);
+        }
+        if let Some(timeout) = timeout_ms {
+            api = api.with_timeout(Duration::from_millis(timeout));
+        }
+        if let Some(capacity) = cache_size {
+            api = api.with_cache(capacity);
+        }
+        Ok(PyApi {
+            api: api,
+        })
+    }
+
+    // Configured by YADICT_* variables, see `Api::from_env_config`.
+    #[staticmethod]
+    fn from_env() -> PyResult<PyApi> {
+        Api::from_env_config().map(|api| PyApi { api: api }).map_err(api_error)
+    }
+
+    fn get_langs(&self, py: Python) -> PyResult<Vec<String>> {
+        py.allow_threads(|| self.api.get_langs()).map_err(request_error)
+    }
+
+    // List of definitions, empty if the word is unknown.
+    fn lookup(&self, py: Python, lang: &str, text: &str) -> PyResult<PyObject> {
+        let lang = lang_pair(lang)?;
+        let defs = py.allow_threads(|| self.api.lookup_def(lang, text)).map_err(request_error)?;
+        let json = serde_json::to_value(&defs).map_err(|e| YadictError::new_err(e.to_string()))?;
+        to_python(py, &json).map(Bound::unbind)
+    }
+
+    // Dict of words and their definitions, looked up in parallel. The
+    // first failed word raises.
+    fn lookup_batch(&self, py: Python, lang: &str, words: Vec<String>) -> PyResult<PyObject> {
+        let lang = lang_pair(lang)?;
+        let results = py.allow_threads(|| {
+            let words: Vec<&str> = words.iter().map(String::as_str).collect();
+            self.api.lookup_batch(lang, &words)
+        });
+        let dict = PyDict::new(py);
+        for (word, result) in results {
+            let defs = result.map_err(request_error)?;
+            let json = serde_json::to_value(&defs).map_err(|e| YadictError::new_err(e.to_string()))?;
+            dict.set_item(word, to_python(py, &json)?)?;
+        }
+        Ok(dict.into_any().unbind())
+    }
+
+    fn __repr__(&self) -> String {
+        format!("{:?}", self.api)
+    }
+}
+
+#[pymodule]
+fn yadict(m: &Bound<PyModule>) -> PyResult<()> {
+    m.add_class::<PyApi>()?;
+    m.add("YadictError", m.py().get_type::<YadictError>())
+}
+
+#[cfg(test)]
+mod tests {
+
+    use pyo3::prelude::*;
+    use pyo3::types::PyDict;
+    use super::PyApi;
+    use testing::MockTransport;
+
+    #[test]
+    fn check_python_lookup() {
+        pyo3::prepare_freethreaded_python();
+        Python::with_gil(|py| {
+            let mut api = PyApi::new("token", None, Some(1000), Some(10)).unwrap();
+            api.api = api.api.clone().with_transport(MockTransport::with_fixtures());
+            let defs = api.lookup(py, "en-ru", "rust").unwrap();
+            let defs = defs.bind(py);
+            assert_eq!(defs.len().unwrap(), 3);
+            let first = defs.get_item(0).unwrap();
+            let first = first.downcast::<PyDict>().unwrap();
+            assert_eq!(first.get_item("text").unwrap().unwrap().extract::<String>().unwrap(), "rust");
+            assert_eq!(api.lookup(py, "en-ru", "qwzx").unwrap().bind(py).len().unwrap(), 0);
+            assert!(api.lookup(py, "english", "rust").unwrap_err().is_instance_of::<pyo3::exceptions::PyValueError>(py));
+            let batch = api.lookup_batch(py, "en-ru", vec!["rust".to_owned(), "qwzx".to_owned()]).unwrap();
+            assert_eq!(batch.bind(py).len().unwrap(), 2);
+            assert!(api.__repr__().contains("redacted"));
+        });
+    }
+}
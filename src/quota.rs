@@ -0,0 +1,151 @@
+use std::cmp;
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use serde_json;
+use cache;
+use super::RequestError;
+
+const DAY: u64 = 24 * 60 * 60;
+
+#[derive(Serialize, Deserialize)]
+struct QuotaFile {
+    day: u64,
+    used: u32,
+}
+
+// Counts requests of the current UTC day against a budget set by the user.
+// With a file the count survives restarts and is shared by processes using
+// it: the file is read again before every check, so only increments made at
+// the same moment by two processes may be counted once.
+pub struct Quota {
+    budget: u32,
+    day: u64,
+    used: u32,
+    path: Option<PathBuf>,
+}
+
+impl Quota {
+    pub fn new(budget: u32) -> Quota {
+        Quota {
+            budget: budget,
+            day: 0,
+            used: 0,
+            path: None,
+        }
+    }
+
+    pub fn with_file(budget: u32, path: &Path) -> Quota {
+        let mut quota = Quota::new(budget);
+        quota.path = Some(path.to_owned());
+        quota.load();
+        quota
+    }
+
+    // Takes the count of the file if it's newer than ours, or the larger
+    // one for the same day. Unreadable file changes nothing.
+    fn load(&mut self) {
+        let stored = self.path.as_ref()
+            .and_then(|path| File::open(path).ok())
+            .and_then(|file| serde_json::from_reader::<_, QuotaFile>(file).ok());
+        if let Some(stored) = stored {
+            if stored.day > self.day {
+                self.day = stored.day;
+                self.used = stored.used;
+            } else if stored.day == self.day {
+                self.used = cmp::max(self.used, stored.used);
+            }
+        }
+    }
+
+    fn save(&self) {
+        if let Some(ref path) = self.path {
+            let stored = QuotaFile {
+                day: self.day,
+                used: self.used,
+            };
+            // Failing to persist the count must not fail the request
+            if let Ok(data) = serde_json::to_vec(&stored) {
+                let _ = cache::write_file_atomically(path, &data);
+            }
+        }
+    }
+
+    fn roll(&mut self, now: u64) {
+        // Other processes may have counted requests since
+        self.load();
+        let day = now / DAY;
+        if day != self.day {
+            self.day = day;
+            self.used = 0;
+        }
+    }
+
+    // Requests left for today, `now` is in seconds since Unix epoch.
+    pub fn remaining(&mut self, now: u64) -> u32 {
+        self.roll(now);
+        self.budget.saturating_sub(self.used)
+    }
+
+    // Counts one request or fails if the budget is spent.
+    pub fn spend(&mut self, now: u64) -> Result<(), RequestError> {
+        if self.remaining(now) == 0 {
+            return Err(RequestError::RateLimited);
+        }
+        self.used += 1;
+        self.save();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use std::env;
+    use std::fs;
+    use super::{Quota, DAY};
+
+    #[test]
+    fn check_quota_budget() {
+        let mut quota = Quota::new(2);
+        let now = 100 * DAY + 10;
+        assert_eq!(quota.remaining(now), 2);
+        assert!(quota.spend(now).is_ok());
+        assert!(quota.spend(now + 1).is_ok());
+        assert_eq!(quota.remaining(now + 2), 0);
+        assert!(quota.spend(now + 2).is_err());
+        assert_eq!(quota.remaining(101 * DAY), 2);
+        assert!(quota.spend(101 * DAY).is_ok());
+    }
+
+    #[test]
+    fn check_quota_file() {
+        let dir = env::temp_dir().join("yadict-test-quota");
+        let _ = fs::remove_dir_all(&dir);
+        let path = dir.join("quota.json");
+        let now = 100 * DAY;
+        let mut quota = Quota::with_file(5, &path);
+        quota.spend(now).unwrap();
+        quota.spend(now).unwrap();
+        let mut quota = Quota::with_file(5, &path);
+        assert_eq!(quota.remaining(now), 3);
+        assert_eq!(quota.remaining(now + DAY), 5);
+    }
+
+    #[test]
+    fn check_quota_shared_file() {
+        let dir = env::temp_dir().join("yadict-test-quota-shared");
+        let _ = fs::remove_dir_all(&dir);
+        let path = dir.join("quota.json");
+        let now = 100 * DAY;
+        let mut first = Quota::with_file(5, &path);
+        let mut second = Quota::with_file(5, &path);
+        first.spend(now).unwrap();
+        second.spend(now).unwrap();
+        first.spend(now).unwrap();
+        assert_eq!(first.remaining(now), 2);
+        assert_eq!(second.remaining(now), 2);
+        second.spend(now).unwrap();
+        second.spend(now).unwrap();
+        assert!(first.spend(now).is_err());
+    }
+}
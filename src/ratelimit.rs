@@ -0,0 +1,156 @@
+use std::time::{Duration, Instant};
+use super::RequestError;
+
+const DAY: u64 = 24 * 60 * 60;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum LimitAction {
+    // Sleep until the request fits into the limit.
+    Wait,
+    // Fail with `RequestError::RateLimited` immediately.
+    Reject,
+}
+
+// Client-side limits of requests rate applied by `Api::with_rate_limit`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RateLimit {
+    // Sustained rate, short bursts up to one second worth of requests are allowed.
+    pub per_second: Option<f64>,
+    // Requests per 24 hours counted from the first request of the period.
+    // Exhausted daily limit always rejects, waiting for hours is never useful.
+    pub per_day: Option<u32>,
+    pub action: LimitAction,
+}
+
+impl Default for RateLimit {
+    fn default() -> RateLimit {
+        RateLimit {
+            per_second: None,
+            per_day: None,
+            action: LimitAction::Wait,
+        }
+    }
+}
+
+// Token bucket for the rate and a plain counter for the daily limit.
+pub struct RateLimiter {
+    limit: RateLimit,
+    tokens: f64,
+    refilled: Option<Instant>,
+    day_started: Option<Instant>,
+    day_count: u32,
+}
+
+impl RateLimiter {
+    pub fn new(limit: RateLimit) -> RateLimiter {
+        let tokens = limit.per_second.map(burst_size).unwrap_or(0.0);
+        RateLimiter {
+            limit: limit,
+            tokens: tokens,
+            refilled: None,
+            day_started: None,
+            day_count: 0,
+        }
+    }
+
+    // Takes a permit for one request and returns how long to wait before
+    // sending it. The permit is reserved, so waits of concurrent callers add up.
+    pub fn acquire(&mut self, now: Instant) -> Result<Duration, RequestError> {
+        if let Some(per_day) = self.limit.per_day {
+            let expired = self.day_started
+                .map(|started| now.duration_since(started) >= Duration::from_secs(DAY))
+                .unwrap_or(true);
+            if expired {
+                self.day_started = Some(now);
+                self.day_count = 0;
+            }
+            if self.day_count >= per_day {
+                return Err(RequestError::RateLimited);
+            }
+        }
+        let mut wait = Duration::from_secs(0);
+        if let Some(rate) = self.limit.per_second {
+            if let Some(refilled) = self.refilled {
+                let elapsed = now.duration_since(refilled);
+                let elapsed = elapsed.as_secs() as f64 + elapsed.subsec_nanos() as f64 / 1e9;
+                self.tokens = (self.tokens + elapsed * rate).min(burst_size(rate));
+            }
+            self.refilled = Some(now);
+            if self.tokens < 1.0 {
+                if self.limit.action == LimitAction::Reject {
+                    return Err(RequestError::RateLimited);
+                }
+                let secs = (1.0 - self.tokens) / rate;
+                wait = Duration::new(secs as u64, (secs.fract() * 1e9) as u32);
+            }
+            self.tokens -= 1.0;
+        }
+        self.day_count += 1;
+        Ok(wait)
+    }
+
+    // Requests left of the daily limit at `now` without taking a permit,
+    // `None` without the limit.
+    pub fn remaining_today(&self, now: Instant) -> Option<u32> {
+        self.limit.per_day.map(|per_day| {
+            let expired = self.day_started
+                .map(|started| now.duration_since(started) >= Duration::from_secs(DAY))
+                .unwrap_or(true);
+            if expired { per_day } else { per_day.saturating_sub(self.day_count) }
+        })
+    }
+}
+
+fn burst_size(rate: f64) -> f64 {
+    rate.max(1.0)
+}
+
+#[cfg(test)]
+mod tests {
+
+    use std::time::{Duration, Instant};
+    use super::{RateLimit, RateLimiter, LimitAction};
+
+    #[test]
+    fn check_rate_wait() {
+        let mut limiter = RateLimiter::new(RateLimit {
+            per_second: Some(2.0),
+            ..RateLimit::default()
+        });
+        let now = Instant::now();
+        assert_eq!(limiter.acquire(now).unwrap(), Duration::from_secs(0));
+        assert_eq!(limiter.acquire(now).unwrap(), Duration::from_secs(0));
+        assert_eq!(limiter.acquire(now).unwrap(), Duration::from_millis(500));
+        assert_eq!(limiter.acquire(now).unwrap(), Duration::from_millis(1000));
+        let later = now + Duration::from_secs(10);
+        assert_eq!(limiter.acquire(later).unwrap(), Duration::from_secs(0));
+    }
+
+    #[test]
+    fn check_rate_reject() {
+        let mut limiter = RateLimiter::new(RateLimit {
+            per_second: Some(1.0),
+            action: LimitAction::Reject,
+            ..RateLimit::default()
+        });
+        let now = Instant::now();
+        assert!(limiter.acquire(now).is_ok());
+        assert!(limiter.acquire(now).is_err());
+        assert!(limiter.acquire(now + Duration::from_secs(1)).is_ok());
+    }
+
+    #[test]
+    fn check_daily_limit() {
+        let mut limiter = RateLimiter::new(RateLimit {
+            per_day: Some(2),
+            ..RateLimit::default()
+        });
+        let now = Instant::now();
+        assert!(limiter.acquire(now).is_ok());
+        assert!(limiter.acquire(now).is_ok());
+        assert_eq!(limiter.remaining_today(now), Some(0));
+        assert!(limiter.acquire(now + Duration::from_secs(3600)).is_err());
+        assert_eq!(limiter.remaining_today(now + Duration::from_secs(24 * 3600)), Some(2));
+        assert!(limiter.acquire(now + Duration::from_secs(24 * 3600)).is_ok());
+    }
+}
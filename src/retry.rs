@@ -0,0 +1,167 @@
+use std::cmp;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use super::RequestError;
+
+// Controls how `Api` repeats requests which failed for transient reasons.
+#[derive(Clone)]
+pub struct RetryPolicy {
+    // Total number of tries including the first one.
+    pub max_attempts: u32,
+    // Delay before the second try, doubled for every next one.
+    pub base_delay: Duration,
+    pub max_delay: Duration,
+    // Randomizes delays in `[delay / 2, delay]` so clients don't retry in sync.
+    pub jitter: bool,
+    pub retry_if: fn(&RequestError) -> bool,
+    // Longest Retry-After of 429 and 503 responses to wait for instead of
+    // the delay, responses asking for more fail at once.
+    pub max_retry_after: Duration,
+}
+
+impl RetryPolicy {
+    // Single attempt, as if there were no retries at all.
+    pub fn never() -> RetryPolicy {
+        RetryPolicy {
+            max_attempts: 1,
+            ..RetryPolicy::default()
+        }
+    }
+
+    // Delay after `attempt` failed tries.
+    pub fn delay(&self, attempt: u32) -> Duration {
+        let factor = 1u32.checked_shl(attempt.saturating_sub(1)).unwrap_or(u32::MAX);
+        let delay = self.base_delay.checked_mul(factor).unwrap_or(self.max_delay);
+        let delay = cmp::min(delay, self.max_delay);
+        if self.jitter {
+            let half = delay / 2;
+            let nanos = (half.as_secs() * 1_000_000_000 + half.subsec_nanos() as u64) as f64;
+            half + Duration::from_nanos((nanos * random_fraction()) as u64)
+        } else {
+            delay
+        }
+    }
+}
+
+impl Default for RetryPolicy {
+    fn default() -> RetryPolicy {
+        RetryPolicy {
+            max_attempts: 3,
+            base_delay: Duration::from_millis(200),
+            max_delay: Duration::from_secs(5),
+            jitter: true,
+            retry_if: is_transient,
+            max_retry_after: Duration::from_secs(60),
+        }
+    }
+}
+
+// Network failures and server-side HTTP errors are worth another try,
+// errors reported by the API itself are not.
+pub fn is_transient(error: &RequestError) -> bool {
+    match *error.root() {
+        #[cfg(feature = "hyper")]
+        RequestError::HyperError(_) => true,
+        #[cfg(feature = "ureq")]
+        RequestError::UreqError(_) => true,
+        RequestError::IOError(_) | RequestError::Timeout => true,
+        RequestError::HttpStatus(status) => status == 429 || status >= 500,
+        _ => false,
+    }
+}
+
+const MONTHS: [&'static str; 12] = ["Jan", "Feb", "Mar", "Apr", "May", "Jun",
+                                     "Jul", "Aug", "Sep", "Oct", "Nov", "Dec"];
+
+// Wait asked by Retry-After header at unix time `now`: seconds or
+// a date like "Wed, 21 Oct 2015 07:28:00 GMT". Dates in the past mean
+// no wait.
+pub(crate) fn parse_retry_after(value: &str, now: u64) -> Option<Duration> {
+    let value = value.trim();
+    if let Ok(seconds) = value.parse::<u64>() {
+        return Some(Duration::from_secs(seconds));
+    }
+    let parts: Vec<&str> = value.split_whitespace().collect();
+    if parts.len() != 6 || parts[5] != "GMT" {
+        return None;
+    }
+    let month = MONTHS.iter().position(|&month| month == parts[2]);
+    let time: Vec<u64> = parts[4].split(':').filter_map(|part| part.parse().ok()).collect();
+    match (parts[1].parse::<u64>(), month, parts[3].parse::<u64>()) {
+        (Ok(day), Some(month), Ok(year)) if time.len() == 3 && year >= 1970 => {
+            let days = days_from_civil(year, month as u64 + 1, day);
+            let secs = days * 86400 + time[0] * 3600 + time[1] * 60 + time[2];
+            Some(Duration::from_secs(secs.saturating_sub(now)))
+        },
+        _ => None,
+    }
+}
+
+// Days since 1970-01-01 of a date in the proleptic Gregorian calendar,
+// http://howardhinnant.github.io/date_algorithms.html#days_from_civil
+fn days_from_civil(year: u64, month: u64, day: u64) -> u64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year / 400;
+    let yoe = year - era * 400;
+    let doy = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    (era * 146097 + doe).saturating_sub(719468)
+}
+
+// Jitter doesn't need a good generator, subsecond part of clock is enough.
+fn random_fraction() -> f64 {
+    let nanos = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.subsec_nanos()).unwrap_or(0);
+    let mut x = nanos as u64 ^ 0x9e3779b97f4a7c15;
+    x ^= x >> 33;
+    x = x.wrapping_mul(0xff51afd7ed558ccd);
+    x ^= x >> 33;
+    (x % 1_000_000) as f64 / 1_000_000.0
+}
+
+#[cfg(test)]
+mod tests {
+
+    use std::time::Duration;
+    use super::{RetryPolicy, is_transient, parse_retry_after};
+    use super::super::RequestError;
+
+    #[test]
+    fn check_retry_delay() {
+        let policy = RetryPolicy {
+            jitter: false,
+            ..RetryPolicy::default()
+        };
+        assert_eq!(policy.delay(1), Duration::from_millis(200));
+        assert_eq!(policy.delay(2), Duration::from_millis(400));
+        assert_eq!(policy.delay(3), Duration::from_millis(800));
+        assert_eq!(policy.delay(10), Duration::from_secs(5));
+        assert_eq!(policy.delay(100), Duration::from_secs(5));
+        let policy = RetryPolicy::default();
+        for attempt in 1..5 {
+            let delay = policy.delay(attempt);
+            let full = Duration::from_millis(200 << (attempt - 1));
+            assert!(delay >= full / 2 && delay <= full);
+        }
+    }
+
+    #[test]
+    fn check_transient_errors() {
+        assert!(is_transient(&RequestError::HttpStatus(503)));
+        assert!(is_transient(&RequestError::HttpStatus(429)));
+        assert!(is_transient(&RequestError::Timeout));
+        assert!(!is_transient(&RequestError::HttpStatus(404)));
+        assert!(!is_transient(&RequestError::KeyBlocked));
+        assert!(!is_transient(&RequestError::InvalidDataFormat));
+    }
+
+    #[test]
+    fn check_parse_retry_after() {
+        // 2015-10-21 07:28:00 UTC
+        let date = 1445412480;
+        assert_eq!(parse_retry_after("120", date), Some(Duration::from_secs(120)));
+        assert_eq!(parse_retry_after("Wed, 21 Oct 2015 07:28:00 GMT", date - 30), Some(Duration::from_secs(30)));
+        assert_eq!(parse_retry_after("Wed, 21 Oct 2015 07:28:00 GMT", date + 30), Some(Duration::from_secs(0)));
+        assert_eq!(parse_retry_after("Thu, 29 Feb 2024 00:00:01 GMT", 1709164800), Some(Duration::from_secs(1)));
+        assert_eq!(parse_retry_after("soon", date), None);
+        assert_eq!(parse_retry_after("Wed, 21 Foo 2015 07:28:00 GMT", date), None);
+    }
+}
@@ -0,0 +1,182 @@
+// Spaced repetition of looked up words with SM-2 algorithm, see
+// https://www.supermemo.com/en/archives1990-2015/english/ol/sm2
+
+use std::fs::File;
+use std::io::{Error as IOError, ErrorKind};
+use std::path::{Path, PathBuf};
+use serde_json;
+use cache;
+use history::{History, HistoryEntry};
+
+const DAY: u64 = 24 * 60 * 60;
+const MIN_EASE: f64 = 1.3;
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Card {
+    pub lang: String,
+    pub text: String,
+    pub ease: f64,
+    // Days until the next review after the last answer.
+    pub interval: u32,
+    // Correct answers in a row.
+    pub repetitions: u32,
+    // Seconds since Unix epoch.
+    pub due: u64,
+}
+
+impl Card {
+    pub fn new(lang: &str, text: &str, now: u64) -> Card {
+        Card {
+            lang: lang.to_owned(),
+            text: text.to_owned(),
+            ease: 2.5,
+            interval: 0,
+            repetitions: 0,
+            due: now,
+        }
+    }
+
+    // `grade` is from 0 (blackout) to 5 (perfect), 3 and above
+    // count as remembered.
+    pub fn answer(&mut self, grade: u8, now: u64) {
+        let grade = if grade > 5 { 5 } else { grade };
+        if grade >= 3 {
+            self.interval = match self.repetitions {
+                0 => 1,
+                1 => 6,
+                _ => (f64::from(self.interval) * self.ease).round() as u32,
+            };
+            self.repetitions += 1;
+        } else {
+            self.interval = 1;
+            self.repetitions = 0;
+        }
+        let miss = f64::from(5 - grade);
+        self.ease = (self.ease + 0.1 - miss * (0.08 + miss * 0.02)).max(MIN_EASE);
+        self.due = now + u64::from(self.interval) * DAY;
+    }
+}
+
+// Cards of words kept in a JSON file, saved after every change.
+pub struct Schedule {
+    path: PathBuf,
+    cards: Vec<Card>,
+}
+
+impl Schedule {
+    // `review.json` next to `History::default_path`.
+    pub fn default_path() -> Option<PathBuf> {
+        History::default_path().map(|path| path.with_file_name("review.json"))
+    }
+
+    // No file means no cards yet.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Schedule, IOError> {
+        let path = path.as_ref().to_owned();
+        let cards = match File::open(&path) {
+            Ok(file) => serde_json::from_reader(file).map_err(IOError::from)?,
+            Err(ref e) if e.kind() == ErrorKind::NotFound => Vec::new(),
+            Err(e) => return Err(e),
+        };
+        Ok(Schedule {
+            path: path,
+            cards: cards,
+        })
+    }
+
+    pub fn cards(&self) -> &[Card] {
+        &self.cards
+    }
+
+    // Adds cards of words which have none yet, due at the time of lookup.
+    // Returns the number of added cards.
+    pub fn add_history(&mut self, entries: &[HistoryEntry]) -> Result<usize, IOError> {
+        let mut added = 0;
+        for entry in entries {
+            if self.find(&entry.lang, &entry.text).is_none() {
+                self.cards.push(Card::new(&entry.lang, &entry.text, entry.time));
+                added += 1;
+            }
+        }
+        if added > 0 {
+            self.save()?;
+        }
+        Ok(added)
+    }
+
+    // Cards due at `now`, the most overdue first.
+    pub fn due(&self, now: u64) -> Vec<Card> {
+        let mut due: Vec<Card> = self.cards.iter().filter(|card| card.due <= now).cloned().collect();
+        due.sort_by_key(|card| card.due);
+        due
+    }
+
+    pub fn answer(&mut self, lang: &str, text: &str, grade: u8, now: u64) -> Result<(), IOError> {
+        match self.find(lang, text) {
+            Some(idx) => self.cards[idx].answer(grade, now),
+            None => return Err(IOError::new(ErrorKind::NotFound, format!("no card of '{}' in {}", text, lang))),
+        }
+        self.save()
+    }
+
+    fn find(&self, lang: &str, text: &str) -> Option<usize> {
+        self.cards.iter().position(|card| card.lang == lang && card.text == text)
+    }
+
+    fn save(&self) -> Result<(), IOError> {
+        let data = serde_json::to_vec(&self.cards).map_err(IOError::from)?;
+        cache::write_file_atomically(&self.path, &data)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use std::env;
+    use std::fs;
+    use super::{Card, Schedule, DAY};
+    use history::HistoryEntry;
+
+    #[test]
+    fn check_sm2() {
+        let mut card = Card::new("en-ru", "rust", 0);
+        card.answer(5, 0);
+        assert_eq!((card.interval, card.repetitions, card.due), (1, 1, DAY));
+        card.answer(4, DAY);
+        assert_eq!(card.interval, 6);
+        card.answer(4, 7 * DAY);
+        assert_eq!(card.interval, 16);
+        assert!((card.ease - 2.6).abs() < 1e-9);
+        // Forgotten word starts over with lower ease
+        card.answer(1, 23 * DAY);
+        assert_eq!((card.interval, card.repetitions), (1, 0));
+        assert!((card.ease - 2.06).abs() < 1e-9);
+        for _ in 0..10 {
+            card.answer(0, 0);
+        }
+        assert_eq!(card.ease, 1.3);
+    }
+
+    #[test]
+    fn check_schedule() {
+        let dir = env::temp_dir().join("yadict-test-review");
+        let _ = fs::remove_dir_all(&dir);
+        let path = dir.join("review.json");
+        let entries: Vec<_> = [(10, "rust"), (20, "iron"), (30, "rust")].iter().map(|&(time, text)| HistoryEntry {
+            time: time,
+            lang: "en-ru".to_owned(),
+            text: text.to_owned(),
+        }).collect();
+        let mut schedule = Schedule::open(&path).unwrap();
+        assert_eq!(schedule.add_history(&entries).unwrap(), 2);
+        assert_eq!(schedule.add_history(&entries).unwrap(), 0);
+        let due: Vec<_> = schedule.due(100).into_iter().map(|card| card.text).collect();
+        assert_eq!(due, vec!["rust", "iron"]);
+        schedule.answer("en-ru", "rust", 5, 100).unwrap();
+        assert!(schedule.answer("de-en", "rust", 5, 100).is_err());
+
+        let schedule = Schedule::open(&path).unwrap();
+        assert_eq!(schedule.cards().len(), 2);
+        assert_eq!(schedule.due(100).len(), 1);
+        assert_eq!(schedule.due(100 + DAY).len(), 2);
+    }
+}
@@ -0,0 +1,125 @@
+// Stable identifiers of definitions and translations, so decks and
+// databases can refer to a sense across lookups. An id hashes direction,
+// headword, part of speech and the number of the definition among ones of
+// the same headword and part of speech, so a new sense of another part of
+// speech in an answer doesn't shift it.
+
+use std::fmt;
+use std::str::FromStr;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use serde::de::Error as DeError;
+use cache::stable_hash;
+use super::{Def, LangPair, normalize_headword};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct SenseId(pub u64);
+
+impl SenseId {
+    fn of(parts: &[&str]) -> SenseId {
+        // Parts are joined by NUL which can't appear in them
+        SenseId(stable_hash(parts.join("\0").as_bytes()))
+    }
+}
+
+// 16 hex digits
+impl fmt::Display for SenseId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:016x}", self.0)
+    }
+}
+
+impl FromStr for SenseId {
+    type Err = ::std::num::ParseIntError;
+
+    fn from_str(s: &str) -> Result<SenseId, Self::Err> {
+        u64::from_str_radix(s, 16).map(SenseId)
+    }
+}
+
+// As a string, numbers above 2^53 get rounded in JavaScript.
+impl Serialize for SenseId {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.to_string())
+    }
+}
+
+impl<'de> Deserialize<'de> for SenseId {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<SenseId, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        s.parse().map_err(D::Error::custom)
+    }
+}
+
+// Ids of a definition and of its translations in their order.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SenseIds {
+    pub def: SenseId,
+    pub trans: Vec<SenseId>,
+}
+
+impl Def {
+    // Id of the definition with `number` among definitions of the same
+    // headword and part of speech, see `Def::ids`.
+    pub fn id<L: Into<LangPair>>(&self, lang: L, number: usize) -> SenseId {
+        let lang = lang.into();
+        let pos = self.word.pos.as_ref().map_or("", |pos| pos.as_str());
+        SenseId::of(&[lang.as_str(), &normalize_headword(&self.word.text), pos, &number.to_string()])
+    }
+
+    // Id of the translation at `index` of the definition.
+    pub fn translation_id<L: Into<LangPair>>(&self, lang: L, number: usize, index: usize) -> SenseId {
+        SenseId::of(&[&self.id(lang, number).to_string(), &index.to_string()])
+    }
+
+    // Ids of all definitions of an answer, numbered by headword and part
+    // of speech in order of the answer.
+    pub fn ids<L: Into<LangPair>>(defs: &[Def], lang: L) -> Vec<SenseIds> {
+        let lang = lang.into();
+        let mut seen: Vec<(String, Option<&str>)> = Vec::new();
+        defs.iter().map(|def| {
+            let key = (normalize_headword(&def.word.text), def.word.pos.as_ref().map(|pos| pos.as_str()));
+            let number = seen.iter().filter(|&seen| *seen == key).count();
+            seen.push(key);
+            SenseIds {
+                def: def.id(&lang, number),
+                trans: (0..def.trans.len()).map(|index| def.translation_id(&lang, number, index)).collect(),
+            }
+        }).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use serde_json;
+    use super::SenseId;
+    use super::super::{Def, LookupBody};
+    use testing::FIXTURE_LOOKUP_RUST;
+
+    #[test]
+    fn check_sense_ids() {
+        let defs: Vec<Def> = serde_json::from_str::<LookupBody>(FIXTURE_LOOKUP_RUST).unwrap().def;
+        let ids = Def::ids(&defs, "en-ru");
+        assert_eq!(ids.len(), defs.len());
+        assert_eq!(ids[0].def, defs[0].id("en-ru", 0));
+        assert_eq!(ids[0].trans.len(), defs[0].trans.len());
+        assert_eq!(ids[0].trans[0], defs[0].translation_id("en-ru", 0, 0));
+        // Same across runs and builds
+        assert_eq!(Def::ids(&defs, "en-ru"), ids);
+        assert_eq!(ids[0].def.to_string(), format!("{:016x}", ids[0].def.0));
+        assert_ne!(defs[0].id("en-de", 0), ids[0].def);
+        assert_ne!(defs[0].id("en-ru", 1), ids[0].def);
+        // The same part of speech twice is numbered
+        let twice = vec![defs[0].clone(), defs[0].clone()];
+        let twice_ids = Def::ids(&twice, "en-ru");
+        assert_eq!(twice_ids[0].def, ids[0].def);
+        assert_eq!(twice_ids[1].def, defs[0].id("en-ru", 1));
+        let mut all: Vec<SenseId> = ids.iter().flat_map(|ids| ids.trans.iter().cloned()).collect();
+        all.sort();
+        all.dedup();
+        assert_eq!(all.len(), defs.iter().map(|def| def.trans.len()).sum::<usize>());
+        let json = serde_json::to_string(&ids[0].def).unwrap();
+        assert_eq!(serde_json::from_str::<SenseId>(&json).unwrap(), ids[0].def);
+        assert!("xyz".parse::<SenseId>().is_err());
+    }
+}
@@ -0,0 +1,230 @@
+// Typo correction with Yandex Speller, it needs no key but knows
+// Russian, Ukrainian and English only.
+
+use super::{Api, Def, LangPair, RequestError, api_url, decode, response_to_json};
+
+pub const SPELLER_URL: &'static str = "https://speller.yandex.net/services/spellservice.json";
+
+const SPELLER_LANGS: [&'static str; 3] = ["ru", "uk", "en"];
+
+#[derive(Deserialize)]
+struct SpellError {
+    word: String,
+    s: Vec<String>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Corrected {
+    // Text which was looked up, `None` if the original one was used.
+    pub correction: Option<String>,
+    pub defs: Vec<Def>,
+}
+
+// Result of `Api::lookup_outcome`, which tells an unknown word from a
+// known one without definitions in the direction.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum LookupOutcome {
+    Found(Vec<Def>),
+    // Spellings offered by the speller, best first, then known words
+    // close to the text, see `Api::similar_words`.
+    NotFound { suggestions: Vec<String> },
+}
+
+impl LookupOutcome {
+    pub fn is_found(&self) -> bool {
+        match *self {
+            LookupOutcome::Found(_) => true,
+            LookupOutcome::NotFound { .. } => false,
+        }
+    }
+
+    pub fn defs(&self) -> &[Def] {
+        match *self {
+            LookupOutcome::Found(ref defs) => defs,
+            LookupOutcome::NotFound { .. } => &[],
+        }
+    }
+
+    pub fn suggestions(&self) -> &[String] {
+        match *self {
+            LookupOutcome::Found(_) => &[],
+            LookupOutcome::NotFound { ref suggestions } => suggestions,
+        }
+    }
+}
+
+// Replaces misspelled words with their first suggestions in order.
+fn apply_corrections(text: &str, errors: &[SpellError]) -> String {
+    let mut out = String::new();
+    let mut rest = text;
+    for error in errors {
+        if let (Some(idx), Some(suggestion)) = (rest.find(&error.word), error.s.first()) {
+            out.push_str(&rest[..idx]);
+            out.push_str(suggestion);
+            rest = &rest[idx + error.word.len()..];
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+// Variants of the whole text for every suggestion of its first misspelled
+// word, the rest of words get their first suggestions.
+fn all_corrections(text: &str, errors: &[SpellError]) -> Vec<String> {
+    let (first, rest) = match errors.split_first() {
+        Some(split) => split,
+        None => return Vec::new(),
+    };
+    let mut variants: Vec<String> = Vec::new();
+    for suggestion in &first.s {
+        let mut errors = vec![SpellError {
+            word: first.word.clone(),
+            s: vec![suggestion.clone()],
+        }];
+        errors.extend(rest.iter().map(|error| SpellError {
+            word: error.word.clone(),
+            s: error.s.clone(),
+        }));
+        let variant = apply_corrections(text, &errors);
+        if variant != text && !variants.contains(&variant) {
+            variants.push(variant);
+        }
+    }
+    variants
+}
+
+impl Api {
+    fn spell_errors(&self, lang: &str, text: &str) -> Result<Vec<SpellError>, RequestError> {
+        if !SPELLER_LANGS.contains(&lang) {
+            return Ok(Vec::new());
+        }
+        let url = api_url(SPELLER_URL, "checkText", &[("lang", lang), ("text", text)]);
+        let (status, body) = self.transport.get(&url)?;
+        decode(response_to_json(status, &body)?)
+    }
+
+    // Corrected `text` in language `lang` or `None` if nothing is wrong
+    // or the language isn't supported by the speller.
+    pub fn spell_check(&self, lang: &str, text: &str) -> Result<Option<String>, RequestError> {
+        let errors = self.spell_errors(lang, text)?;
+        let corrected = apply_corrections(text, &errors);
+        Ok(if corrected != text { Some(corrected) } else { None })
+    }
+
+    // Looks up `text` like `lookup_def`, but an empty answer becomes
+    // `NotFound` with suggestions of the speller and of the vocabulary.
+    // The speller is asked only then, and its failures leave its
+    // suggestions out instead of failing the lookup.
+    pub fn lookup_outcome<L: Into<LangPair>>(&self, lang: L, text: &str) -> Result<LookupOutcome, RequestError> {
+        let lang = lang.into();
+        let defs = self.lookup_def(&lang, text)?;
+        if !defs.is_empty() {
+            return Ok(LookupOutcome::Found(defs));
+        }
+        let mut suggestions = match self.spell_errors(lang.source(), text) {
+            Ok(errors) => all_corrections(text, &errors),
+            Err(e) => {
+                log_debug!("no suggestions for {}: {}", text, e);
+                Vec::new()
+            },
+        };
+        for word in self.similar_words(&lang, text) {
+            if !suggestions.contains(&word) {
+                suggestions.push(word);
+            }
+        }
+        Ok(LookupOutcome::NotFound { suggestions: suggestions })
+    }
+
+    // Looks up `text` and, if nothing is found, looks up its spelling
+    // correction. Correct words cost no speller requests this way.
+    pub fn lookup_corrected<L: Into<LangPair>>(&self, lang: L, text: &str) -> Result<Corrected, RequestError> {
+        let lang = lang.into();
+        let defs = self.lookup_def(&lang, text)?;
+        if !defs.is_empty() {
+            return Ok(Corrected {
+                correction: None,
+                defs: defs,
+            });
+        }
+        match self.spell_check(lang.source(), text)? {
+            Some(correction) => {
+                let defs = self.lookup_def(&lang, &correction)?;
+                Ok(Corrected {
+                    correction: Some(correction),
+                    defs: defs,
+                })
+            },
+            None => Ok(Corrected {
+                correction: None,
+                defs: defs,
+            }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::LookupOutcome;
+    use super::super::Api;
+    use testing::MockTransport;
+
+    #[test]
+    fn check_lookup_corrected() {
+        let transport = MockTransport::with_fixtures()
+            .on("checkText?lang=en&text=russt", 200,
+                r#"[{"code": 1, "pos": 0, "row": 0, "col": 0, "len": 5, "word": "russt", "s": ["rust", "russ"]}]"#)
+            .on("checkText?", 200, "[]");
+        let api = Api::from_token("token").unwrap().with_transport(transport.clone());
+        let corrected = api.lookup_corrected("en-ru", "russt").unwrap();
+        assert_eq!(corrected.correction.unwrap(), "rust");
+        assert_eq!(corrected.defs.len(), 3);
+        let corrected = api.lookup_corrected("en-ru", "rust").unwrap();
+        assert!(corrected.correction.is_none());
+        assert_eq!(corrected.defs.len(), 3);
+        let corrected = api.lookup_corrected("en-ru", "qwzx").unwrap();
+        assert!(corrected.correction.is_none());
+        assert!(corrected.defs.is_empty());
+        let speller = transport.requests().iter().filter(|url| url.contains("checkText")).count();
+        assert_eq!(speller, 2);
+        assert!(api.lookup_corrected("de-ru", "qwzx").unwrap().defs.is_empty());
+    }
+
+    #[test]
+    fn check_lookup_outcome() {
+        let transport = MockTransport::with_fixtures()
+            .on("checkText?lang=en&text=russt", 200,
+                r#"[{"code": 1, "pos": 0, "row": 0, "col": 0, "len": 5, "word": "russt", "s": ["rust", "russ", "rust"]}]"#)
+            .on("checkText?lang=en&text=qwzx", 500, "")
+            .on("checkText?", 200, "[]");
+        let api = Api::from_token("token").unwrap().with_transport(transport.clone());
+        let outcome = api.lookup_outcome("en-ru", "rust").unwrap();
+        assert!(outcome.is_found());
+        assert_eq!(outcome.defs().len(), 3);
+        assert_eq!(api.lookup_outcome("en-ru", "russt").unwrap(), LookupOutcome::NotFound {
+            suggestions: vec!["rust".to_owned(), "russ".to_owned()],
+        });
+        // Failures of the speller aren't failures of the lookup
+        let outcome = api.lookup_outcome("en-ru", "qwzx").unwrap();
+        assert!(!outcome.is_found());
+        assert!(outcome.suggestions().is_empty());
+        assert!(api.lookup_outcome("de-ru", "qwzx").unwrap().suggestions().is_empty());
+    }
+
+    #[test]
+    fn check_fuzzy_suggestions() {
+        let transport = MockTransport::with_fixtures()
+            .on("checkText?", 200, "[]");
+        let api = Api::from_token("token").unwrap()
+            .with_transport(transport.clone())
+            .with_word_list("en", &["trust", "dust"]);
+        api.lookup_def("en-ru", "rust").unwrap();
+        api.lookup_def("en-ru", "russt").unwrap();
+        assert_eq!(api.similar_words("en-ru", "rustt"), vec!["rust", "trust", "dust"]);
+        // Words without definitions aren't offered
+        assert_eq!(api.similar_words("en-ru", "russ"), vec!["rust"]);
+        assert_eq!(api.lookup_outcome("en-ru", "dusst").unwrap().suggestions(), &["dust", "rust"]);
+        assert!(api.lookup_outcome("de-ru", "dusst").unwrap().suggestions().is_empty());
+    }
+}
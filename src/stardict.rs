@@ -0,0 +1,275 @@
+// Offline dictionary in StarDict format: `.ifo` with metadata, `.idx` with
+// sorted headwords and `.dict` with articles. Compressed `.dict.dz` should
+// be unpacked with `gzip -d` first, it's a plain gzip file.
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use super::{Def, LangPair, RequestError, Word};
+use dictionary::Dictionary;
+
+// Lowercased headword to the original one and article positions.
+type Index = HashMap<String, Vec<(String, u64, usize)>>;
+
+pub struct StarDict {
+    lang: LangPair,
+    name: String,
+    dict_path: PathBuf,
+    types: Option<String>,
+    index: Index,
+}
+
+impl StarDict {
+    // Opens the dictionary by its `.ifo` file, the other files must be
+    // next to it. StarDict doesn't store languages, so `lang` tells which
+    // direction the dictionary translates.
+    pub fn open<P: AsRef<Path>, L: Into<LangPair>>(ifo: P, lang: L) -> Result<StarDict, RequestError> {
+        let ifo = ifo.as_ref();
+        let mut info = String::new();
+        File::open(ifo)?.read_to_string(&mut info)?;
+        let mut lines = info.lines();
+        if lines.next().map(|line| line.trim()) != Some("StarDict's dict ifo file") {
+            return Err(RequestError::InvalidDataFormat);
+        }
+        let mut options = HashMap::new();
+        for line in lines {
+            if let Some(pos) = line.find('=') {
+                options.insert(line[..pos].trim().to_owned(), line[pos + 1..].trim().to_owned());
+            }
+        }
+        let offset_bits = options.get("idxoffsetbits").map(|bits| bits.as_str()).unwrap_or("32");
+        let mut idx = Vec::new();
+        File::open(ifo.with_extension("idx"))?.read_to_end(&mut idx)?;
+        Ok(StarDict {
+            lang: lang.into(),
+            name: options.get("bookname").cloned().unwrap_or_default(),
+            dict_path: ifo.with_extension("dict"),
+            types: options.get("sametypesequence").cloned(),
+            index: parse_index(&idx, offset_bits == "64")?,
+        })
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn read_article(&self, offset: u64, size: usize) -> Result<Vec<u8>, RequestError> {
+        let mut file = File::open(&self.dict_path)?;
+        file.seek(SeekFrom::Start(offset))?;
+        let mut data = vec![0; size];
+        file.read_exact(&mut data)?;
+        Ok(data)
+    }
+}
+
+fn parse_index(data: &[u8], offset64: bool) -> Result<Index, RequestError> {
+    let mut index: Index = HashMap::new();
+    let offset_len = if offset64 { 8 } else { 4 };
+    let mut rest = data;
+    while !rest.is_empty() {
+        let end = match rest.iter().position(|b| *b == 0) {
+            Some(end) => end,
+            None => return Err(RequestError::InvalidDataFormat),
+        };
+        if rest.len() < end + 1 + offset_len + 4 {
+            return Err(RequestError::InvalidDataFormat);
+        }
+        let word = ::std::str::from_utf8(&rest[..end])?.to_owned();
+        let numbers = &rest[end + 1..];
+        let offset = read_be(&numbers[..offset_len]);
+        let size = read_be(&numbers[offset_len..offset_len + 4]) as usize;
+        index.entry(word.to_lowercase()).or_default().push((word, offset, size));
+        rest = &numbers[offset_len + 4..];
+    }
+    Ok(index)
+}
+
+fn read_be(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0, |acc, b| (acc << 8) | *b as u64)
+}
+
+// Splits an article into typed fields. With `sametypesequence` types are
+// given once and omitted in the data, the last field takes the rest.
+// Upper-case types are binary with a size and are skipped.
+fn parse_fields(data: &[u8], types: Option<&str>) -> Vec<(char, String)> {
+    let mut fields = Vec::new();
+    let mut rest = data;
+    let same_types = types.map(|types| types.chars().collect::<Vec<_>>());
+    let mut idx = 0;
+    while !rest.is_empty() {
+        let kind = match same_types {
+            Some(ref types) => match types.get(idx) {
+                Some(kind) => *kind,
+                None => break,
+            },
+            None => {
+                let kind = rest[0] as char;
+                rest = &rest[1..];
+                kind
+            },
+        };
+        let last = same_types.as_ref().is_some_and(|types| idx + 1 == types.len());
+        idx += 1;
+        if kind.is_uppercase() {
+            if last || rest.len() < 4 {
+                break;
+            }
+            let size = read_be(&rest[..4]) as usize;
+            rest = &rest[(4 + size).min(rest.len())..];
+            continue;
+        }
+        let end = if last {
+            rest.len()
+        } else {
+            rest.iter().position(|b| *b == 0).unwrap_or(rest.len())
+        };
+        fields.push((kind, String::from_utf8_lossy(&rest[..end]).into_owned()));
+        rest = &rest[(end + 1).min(rest.len())..];
+    }
+    fields
+}
+
+// Markup of Pango, XDXF and HTML articles is dropped, only text remains.
+fn strip_tags(text: &str) -> String {
+    let mut out = String::new();
+    let mut in_tag = false;
+    for c in text.chars() {
+        match c {
+            '<' => in_tag = true,
+            '>' if in_tag => in_tag = false,
+            c if !in_tag => out.push(c),
+            _ => (),
+        }
+    }
+    out.replace("&lt;", "<").replace("&gt;", ">").replace("&quot;", "\"").replace("&amp;", "&")
+}
+
+fn word(text: &str) -> Word {
+    Word {
+        text: text.to_owned(),
+        pos: None,
+        ts: None,
+        gen: None,
+        asp: None,
+        num: None,
+        fr: None,
+        syn: Vec::new(),
+        mean: Vec::new(),
+        ex: Vec::new(),
+    }
+}
+
+// Every non-empty line of an article becomes a translation. StarDict has
+// no parts of speech, so they stay empty.
+fn article_to_def(headword: &str, fields: Vec<(char, String)>) -> Def {
+    let mut def = Def {
+        word: word(headword),
+        trans: Vec::new(),
+    };
+    for (kind, text) in fields {
+        match kind {
+            't' => def.word.ts = Some(text.trim().to_owned()).filter(|ts| !ts.is_empty()),
+            'm' | 'l' | 'g' | 'x' | 'h' => {
+                let text = if kind == 'm' || kind == 'l' { text } else { strip_tags(&text) };
+                for line in text.lines().map(|line| line.trim()).filter(|line| !line.is_empty()) {
+                    def.trans.push(word(line));
+                }
+            },
+            _ => (),
+        }
+    }
+    def
+}
+
+impl Dictionary for StarDict {
+    fn lookup_def(&self, lang: &LangPair, text: &str) -> Result<Vec<Def>, RequestError> {
+        if *lang != self.lang {
+            return Err(RequestError::LangNotSupported);
+        }
+        let entries = match self.index.get(&text.trim().to_lowercase()) {
+            Some(entries) => entries,
+            None => return Ok(Vec::new()),
+        };
+        let mut defs = Vec::new();
+        for &(ref headword, offset, size) in entries {
+            let data = self.read_article(offset, size)?;
+            let fields = parse_fields(&data, self.types.as_deref());
+            defs.push(article_to_def(headword, fields));
+        }
+        Ok(defs)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use std::env;
+    use std::fs::{self, File};
+    use std::io::Write;
+    use std::path::PathBuf;
+    use super::StarDict;
+    use super::super::{LangPair, RequestError};
+    use dictionary::Dictionary;
+
+    // Writes a dictionary with articles given as raw data.
+    fn write_dict(name: &str, types: Option<&str>, articles: &[(&str, &[u8])]) -> PathBuf {
+        let dir = env::temp_dir().join(format!("yadict-test-stardict-{}", name));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let mut idx = Vec::new();
+        let mut dict = Vec::new();
+        for &(word, data) in articles {
+            idx.extend_from_slice(word.as_bytes());
+            idx.push(0);
+            idx.extend_from_slice(&(dict.len() as u32).to_be_bytes());
+            idx.extend_from_slice(&(data.len() as u32).to_be_bytes());
+            dict.extend_from_slice(data);
+        }
+        let mut ifo = format!("StarDict's dict ifo file\nversion=2.4.2\nwordcount={}\n\
+                               idxfilesize={}\nbookname=Test\n", articles.len(), idx.len());
+        if let Some(types) = types {
+            ifo.push_str(&format!("sametypesequence={}\n", types));
+        }
+        let path = dir.join("test.ifo");
+        File::create(&path).unwrap().write_all(ifo.as_bytes()).unwrap();
+        File::create(dir.join("test.idx")).unwrap().write_all(&idx).unwrap();
+        File::create(dir.join("test.dict")).unwrap().write_all(&dict).unwrap();
+        path
+    }
+
+    #[test]
+    fn check_stardict_same_types() {
+        let path = write_dict("same", Some("tm"), &[
+            ("iron", b"a\xc9\xaan\0\xd0\xb6\xd0\xb5\xd0\xbb\xd0\xb5\xd0\xb7\xd0\xbe\nutyug"),
+            ("Rust", b"r\xca\x8cst\0rzhavchina"),
+        ]);
+        let dict = StarDict::open(&path, "en-ru").unwrap();
+        assert_eq!(dict.name(), "Test");
+        let lang = LangPair::from("en-ru");
+        let defs = dict.lookup_def(&lang, "rust").unwrap();
+        assert_eq!(defs.len(), 1);
+        assert_eq!(defs[0].word.text, "Rust");
+        assert_eq!(defs[0].word.ts.as_ref().unwrap(), "r\u{28c}st");
+        assert_eq!(defs[0].trans[0].text, "rzhavchina");
+        let defs = dict.lookup_def(&lang, "iron").unwrap();
+        let trans: Vec<&str> = defs[0].trans.iter().map(|tr| tr.text.as_str()).collect();
+        assert_eq!(trans, vec!["\u{436}\u{435}\u{43b}\u{435}\u{437}\u{43e}", "utyug"]);
+        assert!(dict.lookup_def(&lang, "steel").unwrap().is_empty());
+        match dict.lookup_def(&LangPair::from("ru-en"), "rust") {
+            Err(RequestError::LangNotSupported) => (),
+            other => panic!("unexpected result: {:?}", other.map(|defs| defs.len())),
+        }
+    }
+
+    #[test]
+    fn check_stardict_typed_fields() {
+        let path = write_dict("typed", None, &[
+            ("rust", b"h<b>rust</b> &amp; corrosion<br>\nrusting\0"),
+        ]);
+        let dict = StarDict::open(&path, "en-en").unwrap();
+        let defs = dict.lookup_def(&LangPair::from("en-en"), "rust").unwrap();
+        let trans: Vec<&str> = defs[0].trans.iter().map(|tr| tr.text.as_str()).collect();
+        assert_eq!(trans, vec!["rust & corrosion", "rusting"]);
+    }
+}
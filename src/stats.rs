@@ -0,0 +1,178 @@
+// Counters of requests and cache use, for a session in `Api::stats` and
+// by UTC day in a `UsageLog` file, e.g. to see how much of the daily limit
+// of the key is used.
+
+use std::collections::BTreeMap;
+use std::env;
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use serde_json;
+use cache;
+
+const DAY: u64 = 24 * 60 * 60;
+
+// Days kept in a usage log.
+pub const KEEP_DAYS: u64 = 90;
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Stats {
+    // Requests sent, every retry included.
+    pub requests: u64,
+    pub cache_hits: u64,
+    pub cache_misses: u64,
+    // Size of response bodies.
+    pub bytes: u64,
+    // Failed requests by `RequestError::kind`.
+    #[serde(default)]
+    pub errors: BTreeMap<String, u64>,
+    // Sum of latencies of requests in milliseconds.
+    pub latency_ms: u64,
+}
+
+impl Stats {
+    pub fn errors_total(&self) -> u64 {
+        self.errors.values().sum()
+    }
+
+    pub fn average_latency(&self) -> Option<Duration> {
+        match self.requests {
+            0 => None,
+            requests => Some(Duration::from_millis(self.latency_ms / requests)),
+        }
+    }
+
+    pub fn add(&mut self, other: &Stats) {
+        self.requests += other.requests;
+        self.cache_hits += other.cache_hits;
+        self.cache_misses += other.cache_misses;
+        self.bytes += other.bytes;
+        for (kind, count) in &other.errors {
+            *self.errors.entry(kind.clone()).or_insert(0) += count;
+        }
+        self.latency_ms += other.latency_ms;
+    }
+
+    pub(crate) fn request(latency: Duration, bytes: usize, error: Option<&str>) -> Stats {
+        let mut stats = Stats {
+            requests: 1,
+            bytes: bytes as u64,
+            latency_ms: latency.as_secs() * 1000 + latency.subsec_millis() as u64,
+            ..Stats::default()
+        };
+        if let Some(kind) = error {
+            stats.errors.insert(kind.to_owned(), 1);
+        }
+        stats
+    }
+
+    pub(crate) fn cache(hit: bool) -> Stats {
+        Stats {
+            cache_hits: if hit { 1 } else { 0 },
+            cache_misses: if hit { 0 } else { 1 },
+            ..Stats::default()
+        }
+    }
+}
+
+// Stats by UTC day in a JSON file, which is rewritten on every count like
+// the file of `Api::with_quota_file`. Concurrent processes may lose a few
+// counts. Unreadable file is treated as empty.
+pub struct UsageLog {
+    path: PathBuf,
+}
+
+impl UsageLog {
+    pub fn new<P: AsRef<Path>>(path: P) -> UsageLog {
+        UsageLog {
+            path: path.as_ref().to_owned(),
+        }
+    }
+
+    // `$XDG_DATA_HOME/yadict/usage.json` or `~/.local/share/yadict/usage.json`.
+    pub fn default_path() -> Option<PathBuf> {
+        let dir = match env::var_os("XDG_DATA_HOME") {
+            Some(ref dir) if !dir.is_empty() => PathBuf::from(dir),
+            _ => match env::var_os("HOME") {
+                Some(home) => PathBuf::from(home).join(".local").join("share"),
+                None => return None,
+            },
+        };
+        Some(dir.join("yadict").join("usage.json"))
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    // Stats by days since Unix epoch.
+    pub fn days(&self) -> BTreeMap<u64, Stats> {
+        File::open(&self.path).ok()
+            .and_then(|file| serde_json::from_reader(file).ok())
+            .unwrap_or_default()
+    }
+
+    // Stats of the day of `now`, in seconds since Unix epoch.
+    pub fn day(&self, now: u64) -> Stats {
+        self.days().remove(&(now / DAY)).unwrap_or_default()
+    }
+
+    // Adds `stats` to the day of `now` and drops days older than `KEEP_DAYS`.
+    pub fn add(&self, now: u64, stats: &Stats) {
+        let today = now / DAY;
+        let mut days = self.days();
+        days.entry(today).or_default().add(stats);
+        let days: BTreeMap<u64, Stats> = days.into_iter().filter(|&(day, _)| day + KEEP_DAYS > today).collect();
+        // Failing to persist usage must not fail the request
+        if let Ok(data) = serde_json::to_vec(&days) {
+            let _ = cache::write_file_atomically(&self.path, &data);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use std::env;
+    use std::fs;
+    use std::time::Duration;
+    use super::{Stats, UsageLog, DAY, KEEP_DAYS};
+    use super::super::Api;
+    use testing::{MockTransport, FIXTURE_GET_LANGS, FIXTURE_LOOKUP_RUST, FIXTURE_NOT_FOUND};
+
+    #[test]
+    fn check_stats() {
+        let dir = env::temp_dir().join("yadict-test-stats");
+        let _ = fs::remove_dir_all(&dir);
+        let api = Api::from_token("token").unwrap()
+            .with_transport(MockTransport::new()
+                .on("/getLangs?", 200, FIXTURE_GET_LANGS)
+                .on("text=rust", 200, FIXTURE_LOOKUP_RUST)
+                .on("text=down", 503, "")
+                .on("/lookup?", 200, FIXTURE_NOT_FOUND))
+            .with_cache(10)
+            .with_usage_log(dir.join("usage.json"));
+        api.lookup_def("en-ru", "rust").unwrap();
+        api.lookup_def("en-ru", "rust").unwrap();
+        api.lookup_def("en-ru", "qwzx").unwrap();
+        assert!(api.lookup_def("en-ru", "down").is_err());
+        // Clones share the counters
+        api.clone().get_langs().unwrap();
+        let stats = api.stats();
+        assert_eq!((stats.requests, stats.cache_hits, stats.cache_misses), (4, 1, 3));
+        assert!(stats.bytes > 0);
+        assert_eq!(stats.errors["http_status"], 1);
+        assert!(stats.average_latency().is_some());
+
+        let log = UsageLog::new(dir.join("usage.json"));
+        let days = log.days();
+        assert_eq!(days.len(), 1);
+        assert_eq!(*days.values().next().unwrap(), stats);
+        let old = Stats::request(Duration::from_millis(10), 5, Some("timeout"));
+        log.add(0, &old);
+        log.add(KEEP_DAYS * DAY, &old);
+        let days = log.days();
+        assert!(!days.contains_key(&0));
+        assert_eq!(log.day(KEEP_DAYS * DAY).errors["timeout"], 1);
+    }
+}
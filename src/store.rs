@@ -0,0 +1,247 @@
+// Personal dictionary: found words with their definitions and how often
+// and when they were looked up, kept in SQLite. `Api::with_store` records
+// every lookup with definitions, cached ones included.
+
+use std::env;
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::io::Error as IOError;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use rusqlite::{self, Connection, Row, params};
+use serde_json;
+use super::{Api, Def, normalize_headword};
+use unwind;
+
+#[derive(Debug)]
+pub enum StoreError {
+    Io(IOError),
+    Sqlite(rusqlite::Error),
+    // Definitions of a row aren't valid JSON.
+    Parse(serde_json::Error),
+}
+
+impl fmt::Display for StoreError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            StoreError::Io(ref e) => write!(f, "can't open dictionary store: {}", e),
+            StoreError::Sqlite(ref e) => write!(f, "dictionary store error: {}", e),
+            StoreError::Parse(ref e) => write!(f, "invalid definitions in dictionary store: {}", e),
+        }
+    }
+}
+
+impl Error for StoreError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match *self {
+            StoreError::Io(ref e) => Some(e),
+            StoreError::Sqlite(ref e) => Some(e),
+            StoreError::Parse(ref e) => Some(e),
+        }
+    }
+}
+
+impl From<IOError> for StoreError {
+    fn from(e: IOError) -> Self {
+        StoreError::Io(e)
+    }
+}
+
+impl From<rusqlite::Error> for StoreError {
+    fn from(e: rusqlite::Error) -> Self {
+        StoreError::Sqlite(e)
+    }
+}
+
+impl From<serde_json::Error> for StoreError {
+    fn from(e: serde_json::Error) -> Self {
+        StoreError::Parse(e)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct StoreEntry {
+    pub lang: String,
+    // Lowercase headword as it was looked up.
+    pub word: String,
+    // Definitions of the latest lookup.
+    pub defs: Vec<Def>,
+    // Seconds since Unix epoch.
+    pub first_seen: u64,
+    pub last_seen: u64,
+    pub lookups: u64,
+}
+
+const SCHEMA: &'static str = "CREATE TABLE IF NOT EXISTS entries (
+    lang TEXT NOT NULL,
+    word TEXT NOT NULL,
+    defs TEXT NOT NULL,
+    first_seen INTEGER NOT NULL,
+    last_seen INTEGER NOT NULL,
+    lookups INTEGER NOT NULL,
+    PRIMARY KEY (lang, word)
+)";
+
+const COLUMNS: &'static str = "lang, word, defs, first_seen, last_seen, lookups";
+
+// A connection shared by clones of `Api`, other processes may use the
+// same file at once.
+pub struct Store {
+    path: PathBuf,
+    conn: Mutex<Connection>,
+}
+
+impl fmt::Debug for Store {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Store").field("path", &self.path).finish()
+    }
+}
+
+fn entry(row: &Row) -> rusqlite::Result<(StoreEntry, String)> {
+    let entry = StoreEntry {
+        lang: row.get(0)?,
+        word: row.get(1)?,
+        defs: Vec::new(),
+        first_seen: row.get::<_, i64>(3)? as u64,
+        last_seen: row.get::<_, i64>(4)? as u64,
+        lookups: row.get::<_, i64>(5)? as u64,
+    };
+    Ok((entry, row.get(2)?))
+}
+
+impl Store {
+    // Opens the database at `path`, created with its directory if missing.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Store, StoreError> {
+        let path = path.as_ref();
+        if let Some(dir) = path.parent() {
+            if !dir.as_os_str().is_empty() {
+                fs::create_dir_all(dir)?;
+            }
+        }
+        let conn = Connection::open(path)?;
+        // Waits for writers of other processes instead of failing at once
+        conn.busy_timeout(Duration::from_secs(5))?;
+        conn.execute_batch(SCHEMA)?;
+        Ok(Store {
+            path: path.to_owned(),
+            conn: Mutex::new(conn),
+        })
+    }
+
+    // `$XDG_DATA_HOME/yadict/dictionary.sqlite` or `~/.local/share/yadict/dictionary.sqlite`.
+    pub fn default_path() -> Option<PathBuf> {
+        let dir = match env::var_os("XDG_DATA_HOME") {
+            Some(ref dir) if !dir.is_empty() => PathBuf::from(dir),
+            _ => match env::var_os("HOME") {
+                Some(home) => PathBuf::from(home).join(".local").join("share"),
+                None => return None,
+            },
+        };
+        Some(dir.join("yadict").join("dictionary.sqlite"))
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    // Counts a lookup of `word` at `time` and keeps its latest definitions.
+    pub fn record(&self, lang: &str, word: &str, defs: &[Def], time: u64) -> Result<(), StoreError> {
+        let defs = serde_json::to_string(defs)?;
+        let conn = unwind::lock(&self.conn);
+        conn.execute("INSERT INTO entries (lang, word, defs, first_seen, last_seen, lookups)
+                           VALUES (?1, ?2, ?3, ?4, ?4, 1)
+                           ON CONFLICT (lang, word) DO UPDATE SET defs = excluded.defs,
+                               last_seen = MAX(last_seen, excluded.last_seen), lookups = lookups + 1",
+                          params![lang, normalize_headword(word), defs, time as i64])?;
+        Ok(())
+    }
+
+    fn query(&self, sql: &str, params: &[&dyn rusqlite::ToSql]) -> Result<Vec<StoreEntry>, StoreError> {
+        let conn = unwind::lock(&self.conn);
+        let mut statement = conn.prepare(sql)?;
+        let rows = statement.query_map(params, entry)?;
+        let mut entries = Vec::new();
+        for row in rows {
+            let (mut entry, defs) = row?;
+            entry.defs = serde_json::from_str(&defs)?;
+            entries.push(entry);
+        }
+        Ok(entries)
+    }
+
+    pub fn get(&self, lang: &str, word: &str) -> Result<Option<StoreEntry>, StoreError> {
+        let sql = format!("SELECT {} FROM entries WHERE lang = ?1 AND word = ?2", COLUMNS);
+        let entries = self.query(&sql, &[&lang, &normalize_headword(word)])?;
+        Ok(entries.into_iter().next())
+    }
+
+    // All entries in order of the first lookup.
+    pub fn entries(&self) -> Result<Vec<StoreEntry>, StoreError> {
+        self.query(&format!("SELECT {} FROM entries ORDER BY first_seen, word", COLUMNS), &[])
+    }
+
+    // Up to `limit` entries looked up most often, recent ones first on ties.
+    pub fn most_looked_up(&self, limit: usize) -> Result<Vec<StoreEntry>, StoreError> {
+        let sql = format!("SELECT {} FROM entries ORDER BY lookups DESC, last_seen DESC LIMIT ?1", COLUMNS);
+        self.query(&sql, &[&(limit as i64)])
+    }
+
+    // Entries first looked up from `since` and before `until`, in order
+    // of the first lookup.
+    pub fn by_date_range(&self, since: u64, until: u64) -> Result<Vec<StoreEntry>, StoreError> {
+        let sql = format!("SELECT {} FROM entries WHERE first_seen >= ?1 AND first_seen < ?2 \
+                           ORDER BY first_seen, word", COLUMNS);
+        self.query(&sql, &[&(since as i64), &(until as i64)])
+    }
+}
+
+impl Api {
+    // Records lookups with definitions in `store`.
+    pub fn with_store(mut self, store: Store) -> Api {
+        self.store = Some(Arc::new(store));
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use std::env;
+    use std::fs;
+    use super::Store;
+    use super::super::Api;
+    use testing::MockTransport;
+
+    #[test]
+    fn check_store() {
+        let dir = env::temp_dir().join("yadict-test-store");
+        let _ = fs::remove_dir_all(&dir);
+        let store = Store::open(dir.join("dictionary.sqlite")).unwrap();
+        let api = Api::from_token("token").unwrap().with_transport(MockTransport::with_fixtures());
+        let defs = api.lookup_def("en-ru", "rust").unwrap();
+        store.record("en-ru", "rust", &defs, 100).unwrap();
+        store.record("de-en", "Haus", &[], 200).unwrap();
+        store.record("en-ru", "Rust", &defs, 300).unwrap();
+
+        let rust = store.get("en-ru", "RUST").unwrap().unwrap();
+        assert_eq!((rust.first_seen, rust.last_seen, rust.lookups), (100, 300, 2));
+        assert_eq!(rust.defs, defs);
+        assert!(store.get("en-de", "rust").unwrap().is_none());
+        let top = store.most_looked_up(1).unwrap();
+        assert_eq!(top.len(), 1);
+        assert_eq!(top[0].word, "rust");
+        let words: Vec<String> = store.by_date_range(150, 400).unwrap().into_iter().map(|e| e.word).collect();
+        assert_eq!(words, vec!["haus"]);
+        assert_eq!(store.entries().unwrap().len(), 2);
+
+        // Lookups through `Api`, misses aren't kept
+        let api = api.with_cache(10).with_store(Store::open(dir.join("dictionary.sqlite")).unwrap());
+        api.lookup_def("en-ru", "rust").unwrap();
+        api.lookup_def("en-ru", "rust").unwrap();
+        api.lookup_def("en-ru", "qwzx").unwrap();
+        assert_eq!(store.get("en-ru", "rust").unwrap().unwrap().lookups, 4);
+        assert_eq!(store.entries().unwrap().len(), 2);
+    }
+}
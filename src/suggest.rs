@@ -0,0 +1,87 @@
+// "Did you mean" candidates for words the dictionary doesn't know:
+// known words of the source language within a small edit distance.
+
+use std::cmp;
+use std::collections::{HashMap, HashSet};
+
+// Candidates offered for a single miss.
+pub const MAX_SUGGESTIONS: usize = 5;
+
+// Words found so far and given word lists, by source language.
+#[derive(Default)]
+pub struct Vocabulary {
+    words: HashMap<String, (Vec<String>, HashSet<String>)>,
+}
+
+impl Vocabulary {
+    // Earlier words win among candidates at the same distance, so word
+    // lists should go from frequent to rare ones.
+    pub fn add(&mut self, lang: &str, word: &str) {
+        let word = word.to_lowercase();
+        let &mut (ref mut order, ref mut known) = self.words.entry(lang.to_owned()).or_default();
+        if known.insert(word.clone()) {
+            order.push(word);
+        }
+    }
+
+    // Known words closest to `text`, the word itself excluded. Up to one
+    // edit is allowed for words of four letters and less, two for longer ones.
+    pub fn suggest(&self, lang: &str, text: &str) -> Vec<String> {
+        let text = text.to_lowercase();
+        let limit = if text.chars().count() <= 4 { 1 } else { 2 };
+        let order = match self.words.get(lang) {
+            Some((order, _)) => order,
+            None => return Vec::new(),
+        };
+        let mut found: Vec<(usize, usize, &String)> = order.iter().enumerate()
+            .filter(|&(_, word)| *word != text)
+            .map(|(idx, word)| (levenshtein(&text, word), idx, word))
+            .filter(|&(distance, _, _)| distance <= limit)
+            .collect();
+        found.sort();
+        found.into_iter().take(MAX_SUGGESTIONS).map(|(_, _, word)| word.clone()).collect()
+    }
+}
+
+// Edit distance in characters: insertions, deletions and substitutions.
+pub fn levenshtein(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..b.len() + 1).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, &cb) in b.iter().enumerate() {
+            let substitution = diagonal + if ca == cb { 0 } else { 1 };
+            diagonal = row[j + 1];
+            row[j + 1] = cmp::min(substitution, cmp::min(row[j], row[j + 1]) + 1);
+        }
+    }
+    row[b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::{Vocabulary, levenshtein};
+
+    #[test]
+    fn check_levenshtein() {
+        assert_eq!(levenshtein("rust", "rust"), 0);
+        assert_eq!(levenshtein("rust", "russt"), 1);
+        assert_eq!(levenshtein("kitten", "sitting"), 3);
+        assert_eq!(levenshtein("", "ёж"), 2);
+        assert_eq!(levenshtein("ёжик", "ежик"), 1);
+    }
+
+    #[test]
+    fn check_vocabulary() {
+        let mut vocabulary = Vocabulary::default();
+        for word in &["trust", "Rust", "dust", "rest", "rust", "rusty", "crust"] {
+            vocabulary.add("en", word);
+        }
+        assert_eq!(vocabulary.suggest("en", "rustt"), vec!["rust", "rusty", "trust", "dust", "rest"]);
+        assert_eq!(vocabulary.suggest("en", "Rust"), vec!["trust", "dust", "rest", "rusty", "crust"]);
+        assert!(vocabulary.suggest("en", "iron").is_empty());
+        assert!(vocabulary.suggest("de", "rust").is_empty());
+    }
+}
@@ -0,0 +1,377 @@
+// Offline transport with canned responses. Available with `testing` feature
+// to let applications test their code without a token and network.
+
+use std::env;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Error as IOError, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use serde_json;
+use cache::write_file_atomically;
+use super::{RequestError, redact_key};
+use transport::{HttpTransport, Validators};
+
+pub const FIXTURE_GET_LANGS: &'static str = include_str!("../fixtures/getLangs.json");
+pub const FIXTURE_LOOKUP_RUST: &'static str = include_str!("../fixtures/lookup_en-ru_rust.json");
+pub const FIXTURE_NOT_FOUND: &'static str = include_str!("../fixtures/lookup_not_found.json");
+pub const FIXTURE_KEY_INVALID: &'static str = include_str!("../fixtures/error_key_invalid.json");
+
+// Set it to record cassettes opened with `Cassette::from_env` anew.
+pub const RECORD_VAR: &'static str = "YADICT_RECORD";
+
+struct Rule {
+    pattern: String,
+    status: u16,
+    body: String,
+    retry_after: Option<String>,
+}
+
+#[derive(Default)]
+struct MockState {
+    rules: Vec<Rule>,
+    requests: Vec<String>,
+    headers: Vec<Vec<(String, String)>>,
+    forms: Vec<String>,
+}
+
+// Answers with the body of the first rule whose pattern is a part of the
+// requested URL. Clones share rules and the log of requests, so a clone kept
+// by a test can inspect what `Api` has requested.
+#[derive(Clone, Default)]
+pub struct MockTransport {
+    state: Arc<Mutex<MockState>>,
+}
+
+impl MockTransport {
+    pub fn new() -> MockTransport {
+        MockTransport::default()
+    }
+
+    // Serves `getLangs` and the lookup of "rust" in en-ru from bundled
+    // fixtures, unknown words aren't found.
+    pub fn with_fixtures() -> MockTransport {
+        MockTransport::new()
+            .on("/getLangs?", 200, FIXTURE_GET_LANGS)
+            .on("lang=en-ru&text=rust", 200, FIXTURE_LOOKUP_RUST)
+            .on("/lookup?", 200, FIXTURE_NOT_FOUND)
+    }
+
+    pub fn on(self, pattern: &str, status: u16, body: &str) -> MockTransport {
+        self.state.lock().unwrap().rules.push(Rule {
+            pattern: pattern.to_owned(),
+            status: status,
+            body: body.to_owned(),
+            retry_after: None,
+        });
+        self
+    }
+
+    // Answers with `status`, an empty body and Retry-After header, e.g. to
+    // test backpressure of the service.
+    pub fn on_retry_after(self, pattern: &str, status: u16, retry_after: &str) -> MockTransport {
+        self.state.lock().unwrap().rules.push(Rule {
+            pattern: pattern.to_owned(),
+            status: status,
+            body: String::new(),
+            retry_after: Some(retry_after.to_owned()),
+        });
+        self
+    }
+
+    // URLs requested so far.
+    pub fn requests(&self) -> Vec<String> {
+        self.state.lock().unwrap().requests.clone()
+    }
+
+    // Bodies of POST requests, their URLs are in `requests` without query.
+    pub fn forms(&self) -> Vec<String> {
+        self.state.lock().unwrap().forms.clone()
+    }
+
+    // Headers sent with each of `requests`.
+    pub fn headers(&self) -> Vec<Vec<(String, String)>> {
+        self.state.lock().unwrap().headers.clone()
+    }
+}
+
+impl HttpTransport for MockTransport {
+    fn get(&self, url: &str) -> Result<(u16, String), RequestError> {
+        self.get_with_headers(url, &[], &Validators::default()).map(|(status, body, _)| (status, body))
+    }
+
+    fn get_with_headers(&self, url: &str, headers: &[(String, String)], _cached: &Validators)
+        -> Result<(u16, String, Validators), RequestError> {
+        let mut state = self.state.lock().unwrap();
+        state.requests.push(url.to_owned());
+        state.headers.push(headers.to_vec());
+        match state.rules.iter().find(|rule| url.contains(&rule.pattern)) {
+            Some(rule) => Ok((rule.status, rule.body.clone(), Validators {
+                retry_after: rule.retry_after.clone(),
+                ..Validators::default()
+            })),
+            None => Ok((404, String::new(), Validators::default())),
+        }
+    }
+
+    // Rules match the URL with the form as its query.
+    fn post_form(&self, url: &str, form: &str, headers: &[(String, String)]) -> Result<(u16, String), RequestError> {
+        let mut state = self.state.lock().unwrap();
+        state.requests.push(url.to_owned());
+        state.headers.push(headers.to_vec());
+        state.forms.push(form.to_owned());
+        let query = format!("{}?{}", url, form);
+        match state.rules.iter().find(|rule| query.contains(&rule.pattern)) {
+            Some(rule) => Ok((rule.status, rule.body.clone())),
+            None => Ok((404, String::new())),
+        }
+    }
+}
+
+impl MockTransport {
+    // Serves the rules over HTTP on a local port for clients which don't
+    // take a transport, like `AsyncApi`, and returns the base URL to pass
+    // to their `with_base_url`. The server lives as long as the process.
+    pub fn serve(&self) -> Result<String, IOError> {
+        let listener = TcpListener::bind("127.0.0.1:0")?;
+        let base_url = format!("http://{}", listener.local_addr()?);
+        let transport = self.clone();
+        thread::spawn(move || {
+            // A broken connection fails only its own request
+            for stream in listener.incoming().flatten() {
+                let _ = transport.answer(stream);
+            }
+        });
+        Ok(base_url)
+    }
+
+    // Answers one request, every response closes the connection.
+    fn answer(&self, stream: TcpStream) -> Result<(), IOError> {
+        let mut reader = BufReader::new(stream.try_clone()?);
+        let mut line = String::new();
+        reader.read_line(&mut line)?;
+        let mut parts = line.split_whitespace();
+        let method = parts.next().unwrap_or("").to_owned();
+        let target = parts.next().unwrap_or("").to_owned();
+        let mut headers = Vec::new();
+        let mut length = 0;
+        loop {
+            line.clear();
+            if reader.read_line(&mut line)? == 0 || line.trim().is_empty() {
+                break;
+            }
+            if let Some(pos) = line.find(':') {
+                let (name, value) = (line[..pos].trim().to_owned(), line[pos + 1..].trim().to_owned());
+                if name.eq_ignore_ascii_case("Content-Length") {
+                    length = value.parse().unwrap_or(0);
+                }
+                headers.push((name, value));
+            }
+        }
+        let (status, body) = if method == "POST" {
+            let mut form = vec![0; length];
+            reader.read_exact(&mut form)?;
+            self.post_form(&target, &String::from_utf8_lossy(&form), &headers).map_err(to_io)?
+        } else {
+            let (status, body, _) = self.get_with_headers(&target, &headers, &Validators::default()).map_err(to_io)?;
+            (status, body)
+        };
+        let mut stream = stream;
+        write!(stream, "HTTP/1.1 {} Mock\r\nContent-Type: application/json; charset=utf-8\r\n\
+                        Content-Length: {}\r\nConnection: close\r\n\r\n{}", status, body.len(), body)
+    }
+}
+
+fn to_io(e: RequestError) -> IOError {
+    IOError::other(e.to_string())
+}
+
+// Exchange with the service, the key is hidden in the URL.
+#[derive(Serialize, Deserialize)]
+struct Interaction {
+    url: String,
+    status: u16,
+    body: String,
+}
+
+// Replays responses recorded in a JSON file, or records them from another
+// transport. Cassettes give reproducible tests without a token which still
+// see real answers of the service, re-recording reveals changes of its schema.
+// URLs are compared without keys, so any token replays the cassette.
+pub struct Cassette {
+    path: PathBuf,
+    // Set in record mode.
+    inner: Option<Box<dyn HttpTransport>>,
+    interactions: Mutex<Vec<Interaction>>,
+}
+
+impl Cassette {
+    pub fn replay<P: AsRef<Path>>(path: P) -> Result<Cassette, IOError> {
+        let file = File::open(path.as_ref())?;
+        let interactions = serde_json::from_reader(file)?;
+        Ok(Cassette {
+            path: path.as_ref().to_owned(),
+            inner: None,
+            interactions: Mutex::new(interactions),
+        })
+    }
+
+    // Sends requests with `inner` and writes the file after each response,
+    // previous contents of the file are replaced.
+    pub fn record<P: AsRef<Path>, T: HttpTransport + 'static>(path: P, inner: T) -> Cassette {
+        Cassette {
+            path: path.as_ref().to_owned(),
+            inner: Some(Box::new(inner)),
+            interactions: Mutex::new(Vec::new()),
+        }
+    }
+
+    // Records with `inner` if `RECORD_VAR` is set and replays otherwise.
+    pub fn from_env<P: AsRef<Path>, T: HttpTransport + 'static>(path: P, inner: T) -> Result<Cassette, IOError> {
+        if env::var_os(RECORD_VAR).is_some_and(|value| !value.is_empty()) {
+            Ok(Cassette::record(path, inner))
+        } else {
+            Cassette::replay(path)
+        }
+    }
+}
+
+impl Cassette {
+    // Answers from the file or records the answer of `send` under `url`.
+    // POST requests are kept with the form in the query of `url`.
+    fn exchange<F>(&self, url: &str, send: F) -> Result<(u16, String), RequestError>
+        where F: Fn(&dyn HttpTransport) -> Result<(u16, String), RequestError> {
+        let redacted = redact_key(url);
+        let inner = match self.inner {
+            Some(ref inner) => inner,
+            None => {
+                let interactions = self.interactions.lock().unwrap();
+                return match interactions.iter().find(|i| i.url == redacted) {
+                    Some(i) => Ok((i.status, i.body.clone())),
+                    None => Err(RequestError::IOError(IOError::new(io::ErrorKind::NotFound,
+                        format!("{} isn't recorded in {}", redacted, self.path.display())))),
+                };
+            },
+        };
+        let (status, body) = send(&**inner)?;
+        let mut interactions = self.interactions.lock().unwrap();
+        interactions.push(Interaction {
+            url: redacted,
+            status: status,
+            body: body.clone(),
+        });
+        let json = serde_json::to_string_pretty(&*interactions)?;
+        write_file_atomically(&self.path, json.as_bytes())?;
+        Ok((status, body))
+    }
+}
+
+// Headers reach the recorded transport, but aren't kept in the file.
+impl HttpTransport for Cassette {
+    fn get(&self, url: &str) -> Result<(u16, String), RequestError> {
+        self.exchange(url, |inner| inner.get(url))
+    }
+
+    fn get_with_headers(&self, url: &str, headers: &[(String, String)], _cached: &Validators)
+        -> Result<(u16, String, Validators), RequestError> {
+        self.exchange(url, |inner| {
+            inner.get_with_headers(url, headers, &Validators::default()).map(|(status, body, _)| (status, body))
+        }).map(|(status, body)| (status, body, Validators::default()))
+    }
+
+    fn post_form(&self, url: &str, form: &str, headers: &[(String, String)]) -> Result<(u16, String), RequestError> {
+        self.exchange(&format!("{}?{}", url, form), |inner| inner.post_form(url, form, headers))
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+
+    use std::env;
+    use super::{Cassette, MockTransport, FIXTURE_KEY_INVALID};
+    use super::super::{Api, PartOfSpeech, RequestError};
+
+    fn api(transport: &MockTransport) -> Api {
+        Api::from_token("token").unwrap().with_transport(transport.clone())
+    }
+
+    #[test]
+    fn check_mock_get_langs() {
+        let transport = MockTransport::with_fixtures();
+        let langs = api(&transport).get_langs().unwrap();
+        assert!(langs.contains(&"en-ru".to_string()));
+        assert_eq!(transport.requests().len(), 1);
+        assert!(transport.requests()[0].ends_with("/getLangs?key=token"));
+    }
+
+    #[test]
+    fn check_mock_lookup_def() {
+        let transport = MockTransport::with_fixtures();
+        let defs = api(&transport).lookup_def("en-ru", "rust").unwrap();
+        assert_eq!(defs.len(), 3);
+        for def in defs {
+            assert_eq!(def.word.text, "rust");
+            assert_eq!(def.word.ts.as_ref().unwrap(), "r\u{28c}st");
+            let text = &def.trans[0].text;
+            match *def.word.pos.as_ref().unwrap() {
+                PartOfSpeech::Noun => {
+                    assert_eq!(text, "\u{440}\u{436}\u{430}\u{432}\u{447}\u{438}\u{43d}\u{430}");
+                    assert_eq!(def.trans.len(), 2);
+                    assert_eq!(def.trans[0].syn.len(), 1);
+                    assert_eq!(def.trans[0].gen.as_ref().unwrap(), "\u{436}");
+                    assert_eq!(def.trans[0].fr, Some(10));
+                    assert_eq!(def.trans[0].mean[0].text, "corrosion");
+                    assert_eq!(def.trans[0].ex[0].text, "rust stain");
+                },
+                PartOfSpeech::Verb => {
+                    assert_eq!(text, "\u{440}\u{436}\u{430}\u{432}\u{435}\u{442}\u{44c}");
+                    assert_eq!(def.trans[0].asp.as_ref().unwrap(), "\u{43d}\u{435}\u{441}\u{43e}\u{432}");
+                    assert_eq!(def.trans[0].syn[0].asp.as_ref().unwrap(), "\u{441}\u{43e}\u{432}");
+                },
+                PartOfSpeech::Adjective => assert_eq!(text, "\u{440}\u{436}\u{430}\u{432}\u{44b}\u{439}"),
+                ref pos => panic!("Unknown pos of 'rust' word: {}", pos),
+            }
+        }
+        assert!(api(&transport).lookup_def("en-ru", "qwzx").unwrap().is_empty());
+    }
+
+    #[test]
+    fn check_mock_error() {
+        let transport = MockTransport::new().on("/lookup?", 403, FIXTURE_KEY_INVALID);
+        match api(&transport).lookup_def("en-ru", "rust") {
+            Err(RequestError::KeyInvalid) => (),
+            Err(e) => panic!("unexpected error: {}", e),
+            Ok(_) => panic!("error expected"),
+        }
+    }
+
+    #[test]
+    fn check_cassette_replay() {
+        let cassette = Cassette::replay("fixtures/cassette_en-ru.json").unwrap();
+        let api = Api::from_token("any").unwrap().with_transport(cassette);
+        assert!(api.get_langs().unwrap().contains(&"en-ru".to_string()));
+        assert_eq!(api.lookup_def("en-ru", "rust").unwrap().len(), 3);
+        assert!(api.lookup_def("en-ru", "qwzx").unwrap().is_empty());
+        match api.lookup_def("en-ru", "iron") {
+            Err(RequestError::IOError(_)) => (),
+            other => panic!("unexpected result: {:?}", other.map(|defs| defs.len())),
+        }
+    }
+
+    #[test]
+    fn check_cassette_record() {
+        let path = env::temp_dir().join("yadict-test-cassette.json");
+        let transport = MockTransport::with_fixtures();
+        let api = Api::from_token("secret").unwrap().with_transport(Cassette::record(&path, transport.clone()));
+        assert_eq!(api.lookup_def("en-ru", "rust").unwrap().len(), 3);
+        assert!(api.lookup_def("en-ru", "qwzx").unwrap().is_empty());
+        assert!(!::std::fs::read_to_string(&path).unwrap().contains("secret"));
+
+        let api = Api::from_token("other").unwrap().with_transport(Cassette::replay(&path).unwrap());
+        assert_eq!(api.lookup_def("en-ru", "rust").unwrap().len(), 3);
+        assert!(api.lookup_def("en-ru", "qwzx").unwrap().is_empty());
+        assert_eq!(transport.requests().len(), 2);
+    }
+}
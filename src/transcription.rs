@@ -0,0 +1,161 @@
+// Transcriptions of the `ts` field. The service mostly gives IPA, but some
+// entries use lookalike letters, ASCII stress and length marks or ligatures
+// retired from IPA, so they're mapped to standard symbols for rendering.
+
+use std::fmt;
+use super::Word;
+
+// Lookalikes and their IPA symbols.
+const SYMBOLS: &'static [(char, &'static str)] = &[
+    ('\'', "\u{2c8}"),
+    ('\u{2019}', "\u{2c8}"),
+    ('\u{b4}', "\u{2c8}"),
+    (':', "\u{2d0}"),
+    ('g', "\u{261}"),
+    ('\u{1dd}', "\u{259}"),
+    ('\u{3b5}', "\u{25b}"),
+    ('\u{3b1}', "\u{251}"),
+    ('\u{1d27}', "\u{28c}"),
+    ('\u{2a4}', "d\u{292}"),
+    ('\u{2a7}', "t\u{283}"),
+    ('\u{2a6}', "ts"),
+    ('\u{2a3}', "dz"),
+];
+
+// How `Display` encloses a transcription: slashes for phonemic notation
+// like "/rʌst/", square brackets for phonetic like "[rʌst]".
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[derive(Default)]
+pub enum Brackets {
+    #[default]
+    Slashes,
+    Square,
+}
+
+
+// Whether and how formatters show transcriptions of headwords.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[derive(Default)]
+pub enum TranscriptionStyle {
+    Hidden,
+    #[default]
+    Slashes,
+    Square,
+}
+
+
+impl TranscriptionStyle {
+    // Empty if hidden or there's no transcription.
+    pub fn render(&self, ts: &Transcription) -> String {
+        match *self {
+            TranscriptionStyle::Hidden => String::new(),
+            TranscriptionStyle::Slashes => ts.clone().with_brackets(Brackets::Slashes).to_string(),
+            TranscriptionStyle::Square => ts.clone().with_brackets(Brackets::Square).to_string(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Transcription {
+    raw: String,
+    ipa: String,
+    brackets: Brackets,
+}
+
+impl Transcription {
+    pub fn new(raw: &str) -> Transcription {
+        Transcription {
+            raw: raw.to_owned(),
+            ipa: normalize_ipa(raw),
+            brackets: Brackets::default(),
+        }
+    }
+
+    // As the service returned it.
+    pub fn raw(&self) -> &str {
+        &self.raw
+    }
+
+    pub fn as_ipa(&self) -> &str {
+        &self.ipa
+    }
+
+    pub fn with_brackets(mut self, brackets: Brackets) -> Transcription {
+        self.brackets = brackets;
+        self
+    }
+
+    pub fn brackets(&self) -> Brackets {
+        self.brackets
+    }
+
+    pub fn display_slashed(&self) -> String {
+        self.clone().with_brackets(Brackets::Slashes).to_string()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ipa.is_empty()
+    }
+}
+
+// Empty transcriptions display as nothing, not as "//".
+impl fmt::Display for Transcription {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.is_empty() {
+            return Ok(());
+        }
+        match self.brackets {
+            Brackets::Slashes => write!(f, "/{}/", self.ipa),
+            Brackets::Square => write!(f, "[{}]", self.ipa),
+        }
+    }
+}
+
+impl Word {
+    pub fn transcription(&self) -> Transcription {
+        Transcription::new(self.ts.as_ref().map_or("", |ts| ts.as_str()))
+    }
+}
+
+// Maps known lookalikes and drops enclosing slashes or brackets, which
+// some entries carry.
+fn normalize_ipa(ts: &str) -> String {
+    let ts = ts.trim();
+    let ts = ts.trim_start_matches(['/', '['])
+        .trim_end_matches(['/', ']'])
+        .trim();
+    let mut ipa = String::with_capacity(ts.len());
+    for c in ts.chars() {
+        match SYMBOLS.iter().find(|&&(from, _)| from == c) {
+            Some(&(_, to)) => ipa.push_str(to),
+            None => ipa.push(c),
+        }
+    }
+    ipa
+}
+
+#[cfg(test)]
+mod tests {
+
+    use serde_json;
+    use super::{Brackets, Transcription, normalize_ipa};
+    use super::super::Word;
+
+    #[test]
+    fn check_ipa_normalization() {
+        assert_eq!(normalize_ipa("'g\u{1dd}u:"), "\u{2c8}\u{261}\u{259}u\u{2d0}");
+        assert_eq!(normalize_ipa(" [\u{2a4}\u{3b5}m] "), "d\u{292}\u{25b}m");
+        assert_eq!(normalize_ipa("r\u{28c}st"), "r\u{28c}st");
+        assert_eq!(normalize_ipa("//"), "");
+
+        let word: Word = serde_json::from_str(r#"{"text": "gem", "ts": "ʤem"}"#).unwrap();
+        let ts = word.transcription();
+        assert_eq!(ts.raw(), "\u{2a4}em");
+        assert_eq!(ts.as_ipa(), "d\u{292}em");
+        assert_eq!(ts.to_string(), "/d\u{292}em/");
+        let ts = ts.with_brackets(Brackets::Square);
+        assert_eq!(ts.to_string(), "[d\u{292}em]");
+        assert_eq!(ts.display_slashed(), "/d\u{292}em/");
+        assert_eq!(Transcription::new(" ").with_brackets(Brackets::Square).to_string(), "");
+    }
+}
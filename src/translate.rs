@@ -0,0 +1,156 @@
+// Client of Yandex Translate for whole sentences, it needs its own key.
+// Errors and transports are the same as for dictionary `Api`.
+
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use serde_json::Value;
+use cache::LruCache;
+use transport::{self, HttpTransport};
+use unwind;
+use super::{Api, LangPair, RequestError, api_url, decode, response_to_json};
+
+pub const TRANSLATE_URL: &'static str = "https://translate.yandex.net/api/v1.5/tr.json";
+
+#[derive(Deserialize)]
+struct TranslateResponse {
+    text: Vec<String>,
+}
+
+#[derive(Deserialize)]
+struct DetectResponse {
+    lang: String,
+}
+
+#[derive(Deserialize)]
+struct LangsResponse {
+    dirs: Vec<String>,
+}
+
+pub struct Translator {
+    token: String,
+    base_url: String,
+    transport: Arc<dyn HttpTransport>,
+    cache: Option<Mutex<LruCache<(String, String), String>>>,
+}
+
+impl fmt::Debug for Translator {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Translator")
+            .field("token", &"***")
+            .field("base_url", &self.base_url)
+            .field("cache", &self.cache.is_some())
+            .finish()
+    }
+}
+
+impl Translator {
+    pub fn from_token(token: &str) -> Translator {
+        Translator {
+            token: token.to_owned(),
+            base_url: TRANSLATE_URL.to_owned(),
+            transport: transport::default_transport(),
+            cache: None,
+        }
+    }
+
+    pub fn with_base_url(mut self, base_url: &str) -> Translator {
+        self.base_url = base_url.trim_end_matches('/').to_owned();
+        self
+    }
+
+    pub fn with_transport<T: HttpTransport + 'static>(mut self, transport: T) -> Translator {
+        self.transport = Arc::new(transport);
+        self
+    }
+
+    // Remembers up to `capacity` recent translations.
+    pub fn with_cache(mut self, capacity: usize) -> Translator {
+        self.cache = Some(Mutex::new(LruCache::new(capacity)));
+        self
+    }
+
+    fn fetch_json<V: AsRef<str>>(&self, method: &str, params: &[(&str, V)]) -> Result<Value, RequestError> {
+        let url = api_url(&self.base_url, method, params);
+        let (status, body) = unwind::catch("transport", || self.transport.get(&url))??;
+        response_to_json(status, &body).map_err(|e| match e {
+            // Translate reports the exceeded daily limit with its own code
+            RequestError::UnknownError(404) => RequestError::DailyLimitExceeded,
+            e => e,
+        })
+    }
+
+    // Translates `text` in direction like "en-ru" or just into the target
+    // language like "ru", then the source language is detected.
+    pub fn translate<L: Into<LangPair>>(&self, lang: L, text: &str) -> Result<String, RequestError> {
+        let lang = lang.into();
+        let key = (lang.as_str().to_owned(), text.to_owned());
+        if let Some(ref cache) = self.cache {
+            if let Some(translation) = unwind::lock_or_reset(cache, |cache| cache.clear()).get(&key) {
+                return Ok(translation);
+            }
+        }
+        let params = [("key", &self.token[..]), ("lang", lang.as_str()), ("text", text)];
+        let response: TranslateResponse = decode(self.fetch_json("translate", &params)?)?;
+        let translation = response.text.concat();
+        if let Some(ref cache) = self.cache {
+            unwind::lock_or_reset(cache, |cache| cache.clear()).put(key, translation.clone());
+        }
+        Ok(translation)
+    }
+
+    // Code of the language of `text`, empty if it isn't recognized.
+    pub fn detect(&self, text: &str) -> Result<String, RequestError> {
+        let params = [("key", &self.token[..]), ("text", text)];
+        let response: DetectResponse = decode(self.fetch_json("detect", &params)?)?;
+        Ok(response.lang)
+    }
+
+    // Supported translation directions.
+    pub fn get_langs(&self) -> Result<Vec<String>, RequestError> {
+        let response: LangsResponse = decode(self.fetch_json("getLangs", &[("key", &self.token)])?)?;
+        Ok(response.dirs)
+    }
+}
+
+impl Api {
+    // Translator with the given Translate key which sends requests
+    // through the transport of this `Api`.
+    pub fn translator(&self, token: &str) -> Translator {
+        let mut translator = Translator::from_token(token);
+        translator.transport = self.transport.clone();
+        translator
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::super::{Api, RequestError};
+    use testing::MockTransport;
+
+    fn transport() -> MockTransport {
+        MockTransport::new()
+            .on("/translate?key=trkey&lang=en-ru&text=hello+world", 200,
+                r#"{"code": 200, "lang": "en-ru", "text": ["привет мир"]}"#)
+            .on("/detect?", 200, r#"{"code": 200, "lang": "en"}"#)
+            .on("/getLangs?", 200, r#"{"dirs": ["en-ru", "ru-en"], "langs": {"en": "English"}}"#)
+            .on("/translate?", 404, r#"{"code": 404, "message": "Exceeded the daily limit"}"#)
+    }
+
+    #[test]
+    fn check_translate() {
+        let transport = transport();
+        let api = Api::from_token("dictkey").unwrap().with_transport(transport.clone());
+        let translator = api.translator("trkey").with_cache(10);
+        assert_eq!(translator.translate("en-ru", "hello world").unwrap(),
+                   "\u{43f}\u{440}\u{438}\u{432}\u{435}\u{442} \u{43c}\u{438}\u{440}");
+        assert!(translator.translate("en-ru", "hello world").is_ok());
+        assert_eq!(transport.requests().len(), 1);
+        assert_eq!(translator.detect("hello").unwrap(), "en");
+        assert_eq!(translator.get_langs().unwrap(), vec!["en-ru", "ru-en"]);
+        match translator.translate("en-ru", "more") {
+            Err(RequestError::DailyLimitExceeded) => (),
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+}
@@ -0,0 +1,162 @@
+// Romanization of Cyrillic text for readers who don't know the alphabet
+// yet. Letters of other scripts are kept as they are.
+
+use super::Word;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Scheme {
+    // ISO 9:1995, a letter for a letter with diacritics, so it's reversible.
+    Iso9,
+    // BGN/PCGN 1947 for Russian, plain ASCII digraphs which are easy to read.
+    Bgn,
+}
+
+const ISO9: [(char, &'static str); 38] = [
+    ('а', "a"), ('б', "b"), ('в', "v"), ('г', "g"), ('д', "d"), ('е', "e"), ('ё', "ë"),
+    ('ж', "ž"), ('з', "z"), ('и', "i"), ('й', "j"), ('к', "k"), ('л', "l"), ('м', "m"),
+    ('н', "n"), ('о', "o"), ('п', "p"), ('р', "r"), ('с', "s"), ('т', "t"), ('у', "u"),
+    ('ф', "f"), ('х', "h"), ('ц', "c"), ('ч', "č"), ('ш', "š"), ('щ', "ŝ"), ('ъ', "ʺ"),
+    ('ы', "y"), ('ь', "ʹ"), ('э', "è"), ('ю', "û"), ('я', "â"),
+    ('є', "ê"), ('і', "ì"), ('ї', "ï"), ('ў', "ŭ"), ('ґ', "g̀"),
+];
+
+const BGN: [(char, &'static str); 33] = [
+    ('а', "a"), ('б', "b"), ('в', "v"), ('г', "g"), ('д', "d"), ('е', "e"), ('ё', "ë"),
+    ('ж', "zh"), ('з', "z"), ('и', "i"), ('й', "y"), ('к', "k"), ('л', "l"), ('м', "m"),
+    ('н', "n"), ('о', "o"), ('п', "p"), ('р', "r"), ('с', "s"), ('т', "t"), ('у', "u"),
+    ('ф', "f"), ('х', "kh"), ('ц', "ts"), ('ч', "ch"), ('ш', "sh"), ('щ', "shch"), ('ъ', "ˮ"),
+    ('ы', "y"), ('ь', "ʼ"), ('э', "e"), ('ю', "yu"), ('я', "ya"),
+];
+
+// BGN writes е and ё as "ye" and "yë" at the start of a word and after
+// vowels, й, ъ and ь.
+const BGN_IOTATED: [(char, &'static str); 2] = [('е', "ye"), ('ё', "yë")];
+const BGN_IOTATING: &'static str = "аеёиоуыэюяйъь";
+
+fn table(scheme: Scheme) -> &'static [(char, &'static str)] {
+    match scheme {
+        Scheme::Iso9 => &ISO9,
+        Scheme::Bgn => &BGN,
+    }
+}
+
+fn lower(c: char) -> char {
+    c.to_lowercase().next().unwrap_or(c)
+}
+
+// Keeps the case of the source letter: the first letter of a digraph
+// is capital in words like "Shchi", all of them in "SHCHI".
+fn with_case(latin: &str, upper: bool, caps: bool) -> String {
+    if caps {
+        return latin.to_uppercase();
+    }
+    if !upper {
+        return latin.to_owned();
+    }
+    let mut chars = latin.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
+
+pub fn to_latin(text: &str, scheme: Scheme) -> String {
+    let chars: Vec<char> = text.chars().collect();
+    let mut out = String::new();
+    for (idx, &c) in chars.iter().enumerate() {
+        let letter = lower(c);
+        let prev = if idx > 0 { Some(lower(chars[idx - 1])) } else { None };
+        let iotated = scheme == Scheme::Bgn && prev.is_none_or(|prev| {
+            !prev.is_alphabetic() || BGN_IOTATING.contains(prev)
+        });
+        let found = if iotated {
+            BGN_IOTATED.iter().chain(table(scheme)).find(|&&(cyr, _)| cyr == letter)
+        } else {
+            table(scheme).iter().find(|&&(cyr, _)| cyr == letter)
+        };
+        match found {
+            Some(&(_, latin)) => {
+                let upper = c.is_uppercase();
+                let caps = upper && (chars.get(idx + 1).is_some_and(|next| next.is_uppercase())
+                    || idx > 0 && chars[idx - 1].is_uppercase());
+                out.push_str(&with_case(latin, upper, caps));
+            },
+            None => out.push(c),
+        }
+    }
+    out
+}
+
+// Reverses `to_latin`, taking the longest known spelling at every place.
+// ISO 9 gives the source text back, BGN can't tell е from э or й from ы,
+// so its result is a guess.
+pub fn to_cyrillic(text: &str, scheme: Scheme) -> String {
+    let mut spellings: Vec<(Vec<char>, char)> = table(scheme).iter()
+        .chain(if scheme == Scheme::Bgn { &BGN_IOTATED[..] } else { &[] })
+        .map(|&(cyr, latin)| (latin.chars().collect(), cyr))
+        .collect();
+    // Stable, so earlier letters of the table win among equal spellings
+    spellings.sort_by_key(|(latin, _)| ::std::cmp::Reverse(latin.len()));
+    let chars: Vec<char> = text.chars().collect();
+    let mut out = String::new();
+    let mut idx = 0;
+    while idx < chars.len() {
+        let rest = &chars[idx..];
+        let found = spellings.iter().find(|&(latin, _)| {
+            latin.len() <= rest.len() && latin.iter().zip(rest).all(|(&l, &c)| l == lower(c))
+        });
+        match found {
+            Some(&(ref latin, cyr)) => {
+                if chars[idx].is_uppercase() {
+                    out.extend(cyr.to_uppercase());
+                } else {
+                    out.push(cyr);
+                }
+                idx += latin.len();
+            },
+            None => {
+                out.push(chars[idx]);
+                idx += 1;
+            },
+        }
+    }
+    out
+}
+
+impl Word {
+    // The text in Latin letters by BGN/PCGN, e.g. "shchi" for "щи".
+    pub fn transliterated(&self) -> String {
+        to_latin(&self.text, Scheme::Bgn)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use serde_json;
+    use super::{Scheme, to_cyrillic, to_latin};
+    use super::super::Word;
+
+    #[test]
+    fn check_to_latin() {
+        assert_eq!(to_latin("Щука и ёжик", Scheme::Iso9), "Ŝuka i ëžik");
+        assert_eq!(to_latin("Щука и ёжик", Scheme::Bgn), "Shchuka i yëzhik");
+        assert_eq!(to_latin("Ель, подъезд, поле", Scheme::Bgn), "Yelʼ, podˮyezd, pole");
+        assert_eq!(to_latin("ЩИ и rust", Scheme::Bgn), "SHCHI i rust");
+        assert_eq!(to_latin("Київ", Scheme::Iso9), "Kiïv");
+    }
+
+    #[test]
+    fn check_to_cyrillic() {
+        let text = "Съешь же ещё этих мягких французских булок, да выпей чаю";
+        assert_eq!(to_cyrillic(&to_latin(text, Scheme::Iso9), Scheme::Iso9), text);
+        assert_eq!(to_cyrillic("Shchuka i yozh", Scheme::Bgn), "Щука и йож");
+        assert_eq!(to_cyrillic("borshch, rust", Scheme::Bgn), "борщ, руст");
+    }
+
+    #[test]
+    fn check_word_transliterated() {
+        let word: Word = serde_json::from_str(r#"{"text": "ржавчина", "pos": "noun"}"#).unwrap();
+        assert_eq!(word.transliterated(), "rzhavchina");
+    }
+}
@@ -0,0 +1,155 @@
+#[cfg(any(feature = "hyper", feature = "ureq"))]
+use std::env;
+#[cfg(not(any(feature = "hyper", feature = "ureq")))]
+use std::io;
+use std::sync::Arc;
+use std::time::Duration;
+#[cfg(any(feature = "hyper", feature = "ureq"))]
+use url::Url;
+use super::RequestError;
+#[cfg(feature = "hyper")]
+pub use self::hyper::HyperTransport;
+#[cfg(feature = "ureq")]
+pub use self::ureq::UreqTransport;
+
+#[cfg(feature = "hyper")]
+mod hyper;
+#[cfg(feature = "ureq")]
+mod ureq;
+
+// Timeouts of transports made by the crate. `Api` gives up on a request
+// after `Api::with_timeout`, but its worker thread only ends when the
+// transport does, so they bound how long such threads live.
+pub const DEFAULT_CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+pub const DEFAULT_READ_TIMEOUT: Duration = Duration::from_secs(30);
+
+// Validators of a response: sent back with a request for the same URL,
+// they let the server answer 304 Not Modified instead of the whole body.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Validators {
+    pub etag: Option<String>,
+    pub last_modified: Option<String>,
+    // Retry-After of 429 and 503 responses. It isn't a validator and isn't
+    // sent back, but comes with them as the other header `Api` reads.
+    #[serde(skip)]
+    pub retry_after: Option<String>,
+}
+
+impl Validators {
+    pub fn is_empty(&self) -> bool {
+        self.etag.is_none() && self.last_modified.is_none()
+    }
+}
+
+// Sends HTTP requests for `Api`. Implement it to use another HTTP client
+// or to serve canned responses in tests.
+pub trait HttpTransport: Send + Sync {
+    // Returns HTTP status and body of the response.
+    fn get(&self, url: &str) -> Result<(u16, String), RequestError>;
+
+    // Sends `cached` as If-None-Match and If-Modified-Since headers and
+    // returns validators of the response too. Transports which don't
+    // implement it make plain requests.
+    fn get_conditional(&self, url: &str, cached: &Validators) -> Result<(u16, String, Validators), RequestError> {
+        let _ = cached;
+        self.get(url).map(|(status, body)| (status, body, Validators::default()))
+    }
+
+    // Sends `headers` set with `Api::with_header` besides validators of
+    // `get_conditional`. Transports which don't implement it drop them.
+    fn get_with_headers(&self, url: &str, headers: &[(String, String)], cached: &Validators)
+        -> Result<(u16, String, Validators), RequestError> {
+        let _ = headers;
+        self.get_conditional(url, cached)
+    }
+
+    // Sends `form` as urlencoded body of POST request, the service takes
+    // parameters both ways. Transports which don't implement it send the
+    // form in the query of GET request.
+    fn post_form(&self, url: &str, form: &str, headers: &[(String, String)]) -> Result<(u16, String), RequestError> {
+        let url = format!("{}?{}", url, form);
+        self.get_with_headers(&url, headers, &Validators::default()).map(|(status, body, _)| (status, body))
+    }
+}
+
+// Transport of new clients: ureq which carries TLS, or hyper without TLS
+// when it's the only backend enabled, with proxy from the environment.
+#[cfg(feature = "ureq")]
+pub(crate) fn default_transport() -> Arc<dyn HttpTransport> {
+    Arc::new(UreqTransport::new())
+}
+
+#[cfg(all(feature = "hyper", not(feature = "ureq")))]
+pub(crate) fn default_transport() -> Arc<dyn HttpTransport> {
+    Arc::new(HyperTransport::from_env())
+}
+
+// Proxy variable by its name or the lowercase one, empty means unset.
+#[cfg(any(feature = "hyper", feature = "ureq"))]
+fn proxy_var(name: &str) -> Option<String> {
+    env::var(name).or_else(|_| env::var(name.to_lowercase())).ok().filter(|v| !v.is_empty())
+}
+
+#[cfg(any(feature = "hyper", feature = "ureq"))]
+fn parse_no_proxy(list: &str) -> Vec<String> {
+    list.split(',')
+        .map(|host| host.trim().trim_start_matches('.').to_lowercase())
+        .filter(|host| !host.is_empty())
+        .collect()
+}
+
+// NO_PROXY entry matches the host itself and all its subdomains,
+// "*" matches everything.
+#[cfg(any(feature = "hyper", feature = "ureq"))]
+fn bypasses_proxy(host: &str, no_proxy: &[String]) -> bool {
+    let host = host.to_lowercase();
+    no_proxy.iter().any(|pattern| {
+        pattern == "*" || host == *pattern || host.ends_with(&format!(".{}", pattern))
+    })
+}
+
+// Whether the host of `url` goes around the proxy.
+#[cfg(any(feature = "hyper", feature = "ureq"))]
+fn url_bypasses_proxy(url: &str, no_proxy: &[String]) -> bool {
+    match Url::parse(url) {
+        Ok(url) => url.host_str().is_some_and(|host| bypasses_proxy(host, no_proxy)),
+        Err(_) => false,
+    }
+}
+
+// Without a blocking backend (e.g. on wasm32 where only `AsyncApi` works)
+// the transport has to be set with `with_transport`.
+#[cfg(not(any(feature = "hyper", feature = "ureq")))]
+pub(crate) fn default_transport() -> Arc<dyn HttpTransport> {
+    Arc::new(NoTransport)
+}
+
+#[cfg(not(any(feature = "hyper", feature = "ureq")))]
+struct NoTransport;
+
+#[cfg(not(any(feature = "hyper", feature = "ureq")))]
+impl HttpTransport for NoTransport {
+    fn get(&self, _url: &str) -> Result<(u16, String), RequestError> {
+        Err(RequestError::IOError(io::Error::new(io::ErrorKind::Other,
+            "no HTTP backend is enabled, set a transport with `with_transport`")))
+    }
+}
+
+#[cfg(all(test, any(feature = "hyper", feature = "ureq")))]
+mod tests {
+
+    use super::{parse_no_proxy, bypasses_proxy, url_bypasses_proxy};
+
+    #[test]
+    fn check_no_proxy() {
+        let no_proxy = parse_no_proxy("localhost, .yandex.net,,127.0.0.1");
+        assert_eq!(no_proxy, vec!["localhost", "yandex.net", "127.0.0.1"]);
+        assert!(bypasses_proxy("dictionary.yandex.net", &no_proxy));
+        assert!(bypasses_proxy("YANDEX.net", &no_proxy));
+        assert!(bypasses_proxy("localhost", &no_proxy));
+        assert!(!bypasses_proxy("notyandex.net", &no_proxy));
+        assert!(bypasses_proxy("example.com", &parse_no_proxy("*")));
+        assert!(url_bypasses_proxy("http://127.0.0.1:8700/lookup", &no_proxy));
+        assert!(!url_bypasses_proxy("https://example.com/", &no_proxy));
+    }
+}
@@ -0,0 +1,194 @@
+#[cfg(all(feature = "native-tls", not(feature = "rustls")))]
+use std::sync::Arc;
+use std::time::Duration;
+use ureq::{Agent, AgentBuilder, Error, Proxy, Response};
+#[cfg(all(feature = "native-tls", not(feature = "rustls")))]
+use ureq::native_tls;
+use super::{DEFAULT_CONNECT_TIMEOUT, DEFAULT_READ_TIMEOUT, HttpTransport, Validators};
+use super::{parse_no_proxy, proxy_var, url_bypasses_proxy};
+use super::super::RequestError;
+
+// Blocking transport based on ureq. HTTPS goes through the TLS of the
+// system with the `native-tls` feature, with `rustls` it needs no OpenSSL,
+// so it suits static and musl builds. Proxy is taken from ALL_PROXY,
+// HTTPS_PROXY or HTTP_PROXY except for hosts listed in NO_PROXY.
+pub struct UreqTransport {
+    agent: Agent,
+    // Set when `agent` goes through a proxy: the agent for hosts which
+    // are excluded by NO_PROXY and their patterns.
+    direct: Option<(Agent, Vec<String>)>,
+}
+
+impl UreqTransport {
+    // Connects and reads within `DEFAULT_CONNECT_TIMEOUT` and
+    // `DEFAULT_READ_TIMEOUT`.
+    pub fn new() -> UreqTransport {
+        UreqTransport::with_timeouts(DEFAULT_CONNECT_TIMEOUT, DEFAULT_READ_TIMEOUT)
+    }
+
+    // Fails requests which can't connect in `connect` or wait for data
+    // longer than `read`.
+    pub fn with_timeouts(connect: Duration, read: Duration) -> UreqTransport {
+        let proxy = proxy_var("ALL_PROXY").or_else(|| proxy_var("HTTPS_PROXY")).or_else(|| proxy_var("HTTP_PROXY"));
+        let no_proxy = proxy_var("NO_PROXY").map(|list| parse_no_proxy(&list)).unwrap_or_default();
+        UreqTransport::with_proxy_settings(proxy, no_proxy, connect, read)
+    }
+
+    fn with_proxy_settings(proxy: Option<String>, no_proxy: Vec<String>, connect: Duration, read: Duration)
+        -> UreqTransport {
+        let builder = || builder().timeout_connect(connect).timeout_read(read).timeout_write(read);
+        match proxy.and_then(|proxy| Proxy::new(proxy).ok()) {
+            Some(proxy) => {
+                let mut transport = UreqTransport::from_agent(builder().proxy(proxy).build());
+                if !no_proxy.is_empty() {
+                    transport.direct = Some((builder().build(), no_proxy));
+                }
+                transport
+            },
+            None => UreqTransport::from_agent(builder().build()),
+        }
+    }
+
+    // Sends all requests through HTTP proxy, HTTPS goes in CONNECT tunnel.
+    pub fn with_proxy(host: &str, port: u16) -> UreqTransport {
+        let mut builder = builder()
+            .timeout_connect(DEFAULT_CONNECT_TIMEOUT)
+            .timeout_read(DEFAULT_READ_TIMEOUT)
+            .timeout_write(DEFAULT_READ_TIMEOUT);
+        if let Ok(proxy) = Proxy::new(format!("{}:{}", host, port)) {
+            builder = builder.proxy(proxy);
+        }
+        UreqTransport::from_agent(builder.build())
+    }
+
+    // Uses configured agent, e.g. with own proxy or timeouts.
+    pub fn from_agent(agent: Agent) -> UreqTransport {
+        UreqTransport {
+            agent: agent,
+            direct: None,
+        }
+    }
+
+    fn agent_for(&self, url: &str) -> &Agent {
+        if let Some((ref direct, ref no_proxy)) = self.direct {
+            if url_bypasses_proxy(url, no_proxy) {
+                return direct;
+            }
+        }
+        &self.agent
+    }
+}
+
+impl Default for UreqTransport {
+    fn default() -> UreqTransport {
+        UreqTransport::new()
+    }
+}
+
+// ureq picks rustls by itself, native TLS has to be set up. rustls wins
+// when both features are enabled.
+#[cfg(all(feature = "native-tls", not(feature = "rustls")))]
+fn builder() -> AgentBuilder {
+    let builder = AgentBuilder::new();
+    match native_tls::TlsConnector::new() {
+        Ok(connector) => builder.tls_connector(Arc::new(connector)),
+        Err(_) => builder,
+    }
+}
+
+#[cfg(any(feature = "rustls", not(feature = "native-tls")))]
+fn builder() -> AgentBuilder {
+    AgentBuilder::new()
+}
+
+// API errors come with 4xx statuses, their codes are in the body.
+fn response(result: Result<Response, Error>) -> Result<Response, RequestError> {
+    match result {
+        Ok(response) => Ok(response),
+        Err(Error::Status(_, response)) => Ok(response),
+        Err(Error::Transport(e)) => Err(RequestError::UreqError(Box::new(e))),
+    }
+}
+
+impl HttpTransport for UreqTransport {
+    fn get(&self, url: &str) -> Result<(u16, String), RequestError> {
+        self.get_conditional(url, &Validators::default()).map(|(status, body, _)| (status, body))
+    }
+
+    fn get_conditional(&self, url: &str, cached: &Validators) -> Result<(u16, String, Validators), RequestError> {
+        self.get_with_headers(url, &[], cached)
+    }
+
+    fn get_with_headers(&self, url: &str, headers: &[(String, String)], cached: &Validators)
+        -> Result<(u16, String, Validators), RequestError> {
+        let mut request = self.agent_for(url).get(url);
+        for (name, value) in headers {
+            request = request.set(name, value);
+        }
+        if let Some(ref etag) = cached.etag {
+            request = request.set("If-None-Match", etag);
+        }
+        if let Some(ref last_modified) = cached.last_modified {
+            request = request.set("If-Modified-Since", last_modified);
+        }
+        let response = response(request.call())?;
+        let status = response.status();
+        let validators = Validators {
+            etag: response.header("ETag").map(|etag| etag.to_owned()),
+            last_modified: response.header("Last-Modified").map(|date| date.to_owned()),
+            retry_after: response.header("Retry-After").map(|after| after.to_owned()),
+        };
+        let body = response.into_string()?;
+        Ok((status, body, validators))
+    }
+
+    fn post_form(&self, url: &str, form: &str, headers: &[(String, String)]) -> Result<(u16, String), RequestError> {
+        let mut request = self.agent_for(url).post(url);
+        for (name, value) in headers {
+            request = request.set(name, value);
+        }
+        let request = request.set("Content-Type", "application/x-www-form-urlencoded");
+        let response = response(request.send_string(form))?;
+        let status = response.status();
+        let body = response.into_string()?;
+        Ok((status, body))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use std::net::TcpListener;
+    use std::time::{Duration, Instant};
+    use super::UreqTransport;
+    use testing::{MockTransport, FIXTURE_GET_LANGS};
+    use transport::{DEFAULT_CONNECT_TIMEOUT, DEFAULT_READ_TIMEOUT, HttpTransport};
+
+    #[test]
+    fn check_read_timeout() {
+        // Connections wait in the backlog, nothing is ever answered
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/lookup", listener.local_addr().unwrap());
+        let transport = UreqTransport::with_timeouts(Duration::from_secs(1), Duration::from_millis(200));
+        let started = Instant::now();
+        assert!(transport.get(&url).is_err());
+        assert!(started.elapsed() < Duration::from_secs(5));
+    }
+
+    #[test]
+    fn check_no_proxy() {
+        let base_url = MockTransport::new().on("/getLangs", 200, FIXTURE_GET_LANGS).serve().unwrap();
+        let url = format!("{}/getLangs", base_url);
+        // Nothing listens on the proxy port
+        let proxy = {
+            let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+            format!("http://{}", listener.local_addr().unwrap())
+        };
+        let direct = UreqTransport::with_proxy_settings(Some(proxy.clone()), vec!["127.0.0.1".to_owned()],
+                                                        DEFAULT_CONNECT_TIMEOUT, DEFAULT_READ_TIMEOUT);
+        assert_eq!(direct.get(&url).unwrap().0, 200);
+        let proxied = UreqTransport::with_proxy_settings(Some(proxy), vec!["localhost".to_owned()],
+                                                         DEFAULT_CONNECT_TIMEOUT, DEFAULT_READ_TIMEOUT);
+        assert!(proxied.get(&url).is_err());
+    }
+}
@@ -0,0 +1,201 @@
+// Pronunciation of words by text-to-speech engines: Yandex SpeechKit
+// in the cloud or a local program like espeak-ng. Implement `Speaker`
+// to use another engine.
+
+use std::io::Error as IOError;
+use std::process;
+#[cfg(feature = "hyper")]
+use std::io::Read;
+#[cfg(feature = "hyper")]
+use hyper::client::Client;
+#[cfg(feature = "hyper")]
+use hyper::header::{ContentType, Headers};
+#[cfg(feature = "hyper")]
+use url::form_urlencoded;
+use super::{RequestError, Word};
+
+#[cfg(feature = "hyper")]
+pub const SPEECHKIT_URL: &'static str = "https://tts.api.cloud.yandex.net/speech/v1/tts:synthesize";
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Audio {
+    // Extension of files of the format, e.g. "mp3".
+    pub format: &'static str,
+    pub data: Vec<u8>,
+}
+
+impl Audio {
+    // Name of a file for the pronunciation of `text`, safe on any file system.
+    pub fn file_name(&self, text: &str) -> String {
+        let stem: String = text.chars().map(|c| if c.is_alphanumeric() { c } else { '_' }).collect();
+        format!("{}.{}", stem, self.format)
+    }
+}
+
+pub trait Speaker: Send + Sync {
+    // Speaks `text` in language like "en".
+    fn speak(&self, lang: &str, text: &str) -> Result<Audio, RequestError>;
+}
+
+impl Word {
+    pub fn pronounce<S: Speaker + ?Sized>(&self, speaker: &S, lang: &str) -> Result<Audio, RequestError> {
+        speaker.speak(lang, &self.text)
+    }
+}
+
+// Yandex SpeechKit with an API key of Yandex Cloud, answers in MP3.
+#[cfg(feature = "hyper")]
+pub struct SpeechKit {
+    key: String,
+    url: String,
+    voice: Option<String>,
+    client: Client,
+}
+
+#[cfg(feature = "hyper")]
+impl SpeechKit {
+    pub fn from_key(key: &str) -> SpeechKit {
+        SpeechKit {
+            key: key.to_owned(),
+            url: SPEECHKIT_URL.to_owned(),
+            voice: None,
+            client: Client::new(),
+        }
+    }
+
+    pub fn with_url(mut self, url: &str) -> SpeechKit {
+        self.url = url.to_owned();
+        self
+    }
+
+    // Voice like "alena" or "john", SpeechKit picks one for the language by default.
+    pub fn with_voice(mut self, voice: &str) -> SpeechKit {
+        self.voice = Some(voice.to_owned());
+        self
+    }
+}
+
+// SpeechKit takes locales, dictionary languages are mapped to
+// the main country of the language.
+#[cfg(feature = "hyper")]
+fn locale(lang: &str) -> String {
+    let country = match lang {
+        _ if lang.contains('-') => return lang.to_owned(),
+        "en" => "US",
+        "he" => "IL",
+        "kk" => "KZ",
+        "uk" => "UA",
+        _ => return format!("{}-{}", lang, lang.to_uppercase()),
+    };
+    format!("{}-{}", lang, country)
+}
+
+#[cfg(feature = "hyper")]
+impl Speaker for SpeechKit {
+    fn speak(&self, lang: &str, text: &str) -> Result<Audio, RequestError> {
+        let mut form = form_urlencoded::Serializer::new(String::new());
+        form.append_pair("text", text)
+            .append_pair("lang", &locale(lang))
+            .append_pair("format", "mp3");
+        if let Some(ref voice) = self.voice {
+            form.append_pair("voice", voice);
+        }
+        let form = form.finish();
+        let mut headers = Headers::new();
+        headers.set(ContentType::form_url_encoded());
+        headers.set_raw("Authorization", vec![format!("Api-Key {}", self.key).into_bytes()]);
+        let mut response = self.client.post(&self.url).headers(headers).body(form.as_str()).send()?;
+        let mut data = Vec::new();
+        response.read_to_end(&mut data)?;
+        let status = response.status.to_u16();
+        if status != 200 {
+            return Err(RequestError::HttpStatus(status));
+        }
+        Ok(Audio {
+            format: "mp3",
+            data: data,
+        })
+    }
+}
+
+// Runs a program which writes audio to stdout. "{lang}" and "{text}"
+// in its arguments are replaced with the language and the text.
+pub struct Command {
+    program: String,
+    args: Vec<String>,
+    format: &'static str,
+}
+
+impl Command {
+    pub fn new(program: &str, args: &[&str], format: &'static str) -> Command {
+        Command {
+            program: program.to_owned(),
+            args: args.iter().map(|arg| arg.to_string()).collect(),
+            format: format,
+        }
+    }
+
+    // espeak-ng, offline and less natural than SpeechKit.
+    pub fn espeak() -> Command {
+        Command::new("espeak-ng", &["--stdout", "-v", "{lang}", "{text}"], "wav")
+    }
+}
+
+impl Speaker for Command {
+    fn speak(&self, lang: &str, text: &str) -> Result<Audio, RequestError> {
+        let args: Vec<String> = self.args.iter()
+            .map(|arg| arg.replace("{lang}", lang).replace("{text}", text))
+            .collect();
+        let output = process::Command::new(&self.program).args(&args).output()?;
+        if !output.status.success() {
+            let message = format!("{} failed with {}", self.program, output.status);
+            return Err(RequestError::IOError(IOError::other(message)));
+        }
+        Ok(Audio {
+            format: self.format,
+            data: output.stdout,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use serde_json;
+    use super::{Audio, Command, Speaker};
+    use super::super::{RequestError, Word};
+
+    #[test]
+    fn check_audio_file_name() {
+        let audio = Audio {
+            format: "mp3",
+            data: Vec::new(),
+        };
+        assert_eq!(audio.file_name("rust"), "rust.mp3");
+        assert_eq!(audio.file_name("as/is: ёж"), "as_is__ёж.mp3");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn check_command_speaker() {
+        let word: Word = serde_json::from_str(r#"{"text": "rust"}"#).unwrap();
+        let echo = Command::new("echo", &["-n", "{lang}:{text}"], "txt");
+        let audio = word.pronounce(&echo, "en").unwrap();
+        assert_eq!(audio.data, b"en:rust");
+        assert_eq!(audio.format, "txt");
+        match Command::new("false", &[], "wav").speak("en", "rust") {
+            Err(RequestError::IOError(_)) => (),
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+
+    #[cfg(feature = "hyper")]
+    #[test]
+    fn check_locale() {
+        use super::locale;
+        assert_eq!(locale("en"), "en-US");
+        assert_eq!(locale("ru"), "ru-RU");
+        assert_eq!(locale("uk"), "uk-UA");
+        assert_eq!(locale("de-AT"), "de-AT");
+    }
+}
@@ -0,0 +1,140 @@
+// Surviving panics of user code: middlewares, cache backends, transports
+// and other hooks. A panic is turned into `RequestError::CallbackPanicked`
+// of the call it happened in, and a lock it poisoned is taken back, so
+// other calls and clones of `Api` go on working.
+
+use std::any::Any;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::{Mutex, MutexGuard, PoisonError};
+use super::RequestError;
+
+// Runs `hook`, `what` names it in the error. Whatever `hook` changed before
+// the panic is left as is, callers reset data which may be half-updated.
+pub(crate) fn catch<T, F: FnOnce() -> T>(what: &str, hook: F) -> Result<T, RequestError> {
+    panic::catch_unwind(AssertUnwindSafe(hook)).map_err(|payload| {
+        RequestError::CallbackPanicked(format!("{}: {}", what, message(&*payload)))
+    })
+}
+
+fn message(payload: &(dyn Any + Send)) -> &str {
+    match payload.downcast_ref::<&'static str>() {
+        Some(message) => message,
+        None => payload.downcast_ref::<String>().map_or("unknown panic", |message| message.as_str()),
+    }
+}
+
+// Locks data which stays consistent whatever panics, like counters
+// updated in one step.
+pub(crate) fn lock<'a, T: ?Sized>(mutex: &'a Mutex<T>) -> MutexGuard<'a, T> {
+    lock_or_reset(mutex, |_| ())
+}
+
+// Locks data and calls `reset` on it if a panic poisoned the lock, as it
+// may have been left half-updated.
+pub(crate) fn lock_or_reset<'a, T: ?Sized, F: FnOnce(&mut T)>(mutex: &'a Mutex<T>, reset: F) -> MutexGuard<'a, T> {
+    match mutex.lock() {
+        Ok(guard) => guard,
+        Err(poisoned) => {
+            let mut guard = PoisonError::into_inner(poisoned);
+            reset(&mut *guard);
+            mutex.clear_poison();
+            guard
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use std::sync::{Arc, Mutex};
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::thread;
+    use serde_json::Value;
+    use super::{catch, lock, lock_or_reset};
+    use super::super::{Api, RequestError};
+    use cache::{CacheBackend, CacheKey, LruCache};
+    use middleware::{Middleware, Next, Request, Response};
+    use testing::MockTransport;
+
+    // Panics once, on the first request.
+    struct PanicOnce(AtomicBool);
+
+    impl Middleware for PanicOnce {
+        fn handle(&self, request: Request, next: Next) -> Result<Response, RequestError> {
+            if !self.0.swap(true, Ordering::SeqCst) {
+                panic!("middleware failed");
+            }
+            next.run(request)
+        }
+    }
+
+    // Panics on every put of "boom".
+    struct PanicOnBoom(LruCache<CacheKey, Value>);
+
+    impl CacheBackend for PanicOnBoom {
+        fn get(&mut self, key: &CacheKey) -> Option<Value> {
+            self.0.get(key)
+        }
+
+        fn put(&mut self, key: CacheKey, value: Value) {
+            if key.text == "boom" {
+                panic!("cache failed");
+            }
+            self.0.put(key, value)
+        }
+
+        fn clear(&mut self) {
+            self.0.clear()
+        }
+    }
+
+    #[test]
+    fn check_poisoned_locks() {
+        let data = Arc::new(Mutex::new(vec![1, 2]));
+        let shared = data.clone();
+        let _ = thread::spawn(move || {
+            let mut data = shared.lock().unwrap();
+            data.push(3);
+            panic!("half-updated");
+        }).join();
+        assert!(data.is_poisoned());
+        assert_eq!(*lock_or_reset(&data, |data| data.clear()), Vec::<i32>::new());
+        assert!(!data.is_poisoned());
+        lock(&data).push(4);
+        assert_eq!(*lock(&data), vec![4]);
+
+        match catch("hook", || -> u32 { panic!("boom") }) {
+            Err(RequestError::CallbackPanicked(ref message)) => assert_eq!(message, "hook: boom"),
+            other => panic!("unexpected {:?}", other),
+        }
+        assert_eq!(catch("hook", || 5).unwrap(), 5);
+    }
+
+    #[test]
+    fn check_panicking_hooks() {
+        let api = Api::from_token("token").unwrap()
+            .with_transport(MockTransport::with_fixtures())
+            .with_middleware(PanicOnce(AtomicBool::new(false)))
+            .with_cache_backend(PanicOnBoom(LruCache::new(10)));
+        match api.lookup_def("en-ru", "rust") {
+            Err(RequestError::CallbackPanicked(ref message)) => assert_eq!(message, "middleware: middleware failed"),
+            other => panic!("unexpected {:?}", other),
+        }
+        let other = api.clone();
+        let defs = thread::spawn(move || other.lookup_def("en-ru", "rust")).join().unwrap().unwrap();
+        assert_eq!(defs.len(), 3);
+
+        match api.lookup_def("en-ru", "boom") {
+            Err(RequestError::CallbackPanicked(ref message)) => assert_eq!(message, "cache backend: cache failed"),
+            other => panic!("unexpected {:?}", other),
+        }
+        // The cache was cleared, the lock isn't poisoned
+        let other = api.clone();
+        assert!(thread::spawn(move || other.lookup_def("en-ru", "qwzx")).join().unwrap().unwrap().is_empty());
+        assert_eq!(api.stats().cache_hits, 0);
+        assert_eq!(api.lookup_def("en-ru", "rust").unwrap().len(), 3);
+        assert_eq!(api.stats().cache_hits, 0);
+        assert_eq!(api.lookup_def("en-ru", "rust").unwrap().len(), 3);
+        assert_eq!(api.stats().cache_hits, 1);
+    }
+}
@@ -0,0 +1,101 @@
+// Events which don't fail a call but may be worth showing to users, like
+// a retried request or an old cached answer, see `Api::with_warnings`.
+
+use std::fmt;
+use std::time::Duration;
+use parse::ParseWarning;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Warning {
+    // A request failed and is sent again after `delay`, `attempt` is the
+    // number of the failed one.
+    Retry { method: String, attempt: u32, delay: Duration, error: String },
+    // The service rejected the key at `index` of `Api::from_tokens`, the
+    // next one is used.
+    KeyRejected { index: usize, error: String },
+    // Entries of an answer skipped by `ParseMode::Lenient`.
+    SkippedEntries(Vec<ParseWarning>),
+    // An old cached answer is used as the service failed, see
+    // `CachePolicy::StaleIfError`.
+    StaleCache { lang: String, text: String, age: Duration, error: String },
+}
+
+impl fmt::Display for Warning {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Warning::Retry { ref method, attempt, delay, ref error } =>
+                write!(f, "attempt {} of {} failed, retrying in {:?}: {}", attempt, method, delay, error),
+            Warning::KeyRejected { index, ref error } => write!(f, "key #{} rejected: {}", index, error),
+            Warning::SkippedEntries(ref entries) => {
+                let entries: Vec<String> = entries.iter().map(|entry| entry.to_string()).collect();
+                write!(f, "skipped invalid entries: {}", entries.join(", "))
+            },
+            Warning::StaleCache { ref lang, ref text, age, ref error } =>
+                write!(f, "cached answer of {} '{}' is used, {}s old: {}", lang, text, age.as_secs(), error),
+        }
+    }
+}
+
+// Receives warnings on the calling thread, closures are sinks too.
+pub trait Warnings: Send + Sync {
+    fn warn(&self, warning: &Warning);
+}
+
+impl<F: Fn(&Warning) + Send + Sync> Warnings for F {
+    fn warn(&self, warning: &Warning) {
+        self(warning)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+    use super::Warning;
+    use super::super::{Api, ParseMode, ParseWarning, RetryPolicy};
+    use testing::{MockTransport, FIXTURE_KEY_INVALID, FIXTURE_LOOKUP_RUST};
+
+    #[test]
+    fn check_warnings() {
+        let warnings: Arc<Mutex<Vec<Warning>>> = Arc::new(Mutex::new(Vec::new()));
+        let sink = warnings.clone();
+        let transport = MockTransport::new()
+            .on("key=bad", 403, FIXTURE_KEY_INVALID)
+            .on("text=odd", 200, r#"{"def": [{"text": "odd", "tr": [{"pos": "noun"}, {"text": "странный"}]}]}"#)
+            .on("text=rust", 200, FIXTURE_LOOKUP_RUST)
+            .on("/lookup?", 503, "");
+        let api = Api::from_tokens(&["bad", "good"]).unwrap()
+            .with_transport(transport)
+            .with_parse_mode(ParseMode::Lenient)
+            .with_retry(RetryPolicy {
+                max_attempts: 2,
+                base_delay: Duration::from_millis(1),
+                jitter: false,
+                ..RetryPolicy::default()
+            })
+            .with_warnings(move |warning: &Warning| sink.lock().unwrap().push(warning.clone()));
+        assert_eq!(api.lookup_def("en-ru", "rust").unwrap().len(), 3);
+        assert_eq!(api.lookup_def("en-ru", "odd").unwrap()[0].trans.len(), 1);
+        assert!(api.lookup_def("en-ru", "down").is_err());
+        let warnings = warnings.lock().unwrap();
+        assert_eq!(warnings.len(), 3);
+        match warnings[0] {
+            Warning::KeyRejected { index: 0, .. } => (),
+            ref other => panic!("unexpected {:?}", other),
+        }
+        match warnings[1] {
+            Warning::SkippedEntries(ref entries) => assert_eq!(entries[..], [ParseWarning {
+                path: "def[0].tr[0].text".to_owned(),
+                expected: "string".to_owned(),
+                found: "nothing".to_owned(),
+            }]),
+            ref other => panic!("unexpected {:?}", other),
+        }
+        match warnings[2] {
+            Warning::Retry { ref method, attempt: 1, .. } => assert_eq!(method, "lookup"),
+            ref other => panic!("unexpected {:?}", other),
+        }
+        assert!(warnings[2].to_string().starts_with("attempt 1 of lookup failed"));
+    }
+}